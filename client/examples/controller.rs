@@ -25,9 +25,12 @@ fn main() -> Result<()> {
             "" => continue,
             "exit" => break,
             inp => {
-                let Ok(cmd) = text_format::de::from_str::<Concrete>(inp) else {
-                    println!("Couldn't parse command");
-                    continue;
+                let cmd = match text_format::de::from_str::<Concrete>(inp) {
+                    Ok(cmd) => cmd,
+                    Err(e) => {
+                        println!("Couldn't parse command: {e}");
+                        continue;
+                    }
                 };
 
                 println!("{}", text_format::ser::to_string(&cmd)?);
@@ -67,6 +70,14 @@ fn execute(cmd: Concrete, robot: &impl Transport) -> Result<()> {
 
         Concrete::Nop(c) => robot.cmd(c)?,
         Concrete::GetUptime(c) => println!("{:?}", robot.cmd(c)?),
+        Concrete::GetCapabilities(c) => println!("{:?}", robot.cmd(c)?),
+        Concrete::GetSchema(c) => println!("{:?}", robot.cmd(c)?),
+        Concrete::GetChallenge(c) => println!("{}", robot.cmd(c)?),
+        Concrete::Authenticate(c) => println!("{}", robot.cmd(c)?),
+        Concrete::AcquireControl(c) => println!("{:?}", robot.cmd(c)?),
+        Concrete::TakeControl(c) => println!("{:?}", robot.cmd(c)?),
+        Concrete::ReleaseControl(c) => robot.cmd(c)?,
+        Concrete::Heartbeat(c) => robot.cmd(c)?,
 
         Concrete::GetPosition(c) => {
             if let Some(p) = robot.cmd(c)? {
@@ -79,6 +90,16 @@ fn execute(cmd: Concrete, robot: &impl Transport) -> Result<()> {
         Concrete::Abort(_) => {
             println!("Abort no supported");
         }
+        Concrete::Batch(_) => {
+            println!("Batch no supported");
+        }
+        Concrete::Ack(c) => robot.cmd(c)?,
+        Concrete::Schedule(c) => println!("{}", robot.cmd(c)?),
+        Concrete::ListScheduled(c) => println!("{:?}", robot.cmd(c)?),
+        Concrete::CancelScheduled(c) => println!("{}", robot.cmd(c)?),
+        Concrete::Custom(_) => {
+            println!("Custom no supported");
+        }
     }
     Ok(())
 }
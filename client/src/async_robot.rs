@@ -14,6 +14,21 @@ impl<T: TransportAsync> RobotAsync<T> {
         Self { transport }
     }
 
+    /// Like [`RobotAsync::new`], but fails if the server can't handle everything this client can send,
+    /// see [`cmd::Capabilities::verify`]
+    pub async fn new_verified(transport: T) -> Result<Self> {
+        let robot = Self::new(transport);
+        robot.get_capabilities().await?.verify()?;
+        Ok(robot)
+    }
+
+    /// Like [`RobotAsync::new`], but authenticates with the server's shared secret first,
+    /// see [`cmd::Authenticate`]
+    pub async fn new_authenticated(transport: T, secret: &str) -> Result<Self> {
+        transport.authenticate(secret).await?;
+        Ok(Self::new(transport))
+    }
+
     pub async fn measure_latency(&self) -> Result<Duration> {
         let start = Instant::now();
         let _ = self.transport.cmd(cmd::GetUptime).await?;
@@ -22,6 +37,11 @@ impl<T: TransportAsync> RobotAsync<T> {
     pub async fn get_server_uptime(&self) -> Result<Duration> {
         self.transport.cmd(cmd::GetUptime).await
     }
+
+    /// Starts building a batch of commands, which are sent and executed in one go
+    pub fn batch(&self) -> crate::batch::Batch<'_, Self, ()> {
+        crate::batch::Batch::new(self)
+    }
 }
 impl<T: SubscribableAsync> RobotAsync<T> {
     pub async fn subscribe<E: Event>(&self, ev: E) -> Result<broadcast::Receiver<E::Item>> {
@@ -42,7 +62,41 @@ impl<T: TransportAsync> RoblibBuiltinAsync for RobotAsync<T> {
         self.transport.cmd(cmd::GetUptime).await
     }
 
+    async fn get_capabilities(&self) -> anyhow::Result<cmd::Capabilities> {
+        self.transport.cmd(cmd::GetCapabilities).await
+    }
+
+    async fn get_schema(&self) -> anyhow::Result<roblib::schema::Schema> {
+        self.transport.cmd(cmd::GetSchema).await
+    }
+
     async fn abort(&self) -> anyhow::Result<()> {
         self.transport.cmd(cmd::Abort).await
     }
+
+    async fn schedule(&self, when: cmd::When, cmd: cmd::Concrete) -> anyhow::Result<u32> {
+        self.transport.cmd(cmd::Schedule::new(when, cmd)).await
+    }
+
+    async fn list_scheduled(&self) -> anyhow::Result<Vec<cmd::Scheduled>> {
+        self.transport.cmd(cmd::ListScheduled).await
+    }
+
+    async fn cancel_scheduled(&self, id: u32) -> anyhow::Result<bool> {
+        self.transport.cmd(cmd::CancelScheduled(id)).await
+    }
+
+    async fn acquire_control(&self) -> anyhow::Result<Duration> {
+        self.transport.cmd(cmd::AcquireControl).await
+    }
+
+    async fn take_control(&self, admin_token: &str) -> anyhow::Result<Duration> {
+        self.transport
+            .cmd(cmd::TakeControl(admin_token.into()))
+            .await
+    }
+
+    async fn release_control(&self) -> anyhow::Result<()> {
+        self.transport.cmd(cmd::ReleaseControl).await
+    }
 }
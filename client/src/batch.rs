@@ -0,0 +1,112 @@
+use crate::{transports::Transport, Robot};
+use anyhow::Result;
+use roblib::cmd::{self, Command, Concrete};
+use serde::{de::DeserializeOwned, Serialize};
+use std::marker::PhantomData;
+
+/// Builds a [`cmd::Batch`], started by [`Robot::batch`] or `RobotAsync::batch`.
+///
+/// Every added command adds its return value to the end of a flat tuple,
+/// so `robot.batch().led(..).track_sensor().send()` returns `((), [bool; 4])`.
+/// Up to 16 commands fit in one, like serde's tuples.
+///
+/// The batch isn't atomic: if a command fails, the ones before it have already run.
+pub struct Batch<'r, Robot, R> {
+    robot: &'r Robot,
+    cmds: Vec<Concrete>,
+    _ret: PhantomData<R>,
+}
+
+impl<'r, Robot> Batch<'r, Robot, ()> {
+    pub(crate) fn new(robot: &'r Robot) -> Self {
+        Self {
+            robot,
+            cmds: vec![],
+            _ret: PhantomData,
+        }
+    }
+}
+
+/// A batch with the return value `T` of one more command, see [`Push`]
+pub type Then<'r, Robot, R, T> = Batch<'r, Robot, <R as Push<T>>::Output>;
+
+/// Appends `T` to a tuple, `()` being the empty one
+pub trait Push<T> {
+    type Output;
+}
+
+impl<T> Push<T> for () {
+    type Output = (T,);
+}
+
+macro_rules! push {
+    ($($t:ident),*) => {
+        impl<$($t,)* T> Push<T> for ($($t,)*) {
+            type Output = ($($t,)* T);
+        }
+    };
+}
+push!(A);
+push!(A, B);
+push!(A, B, C);
+push!(A, B, C, D);
+push!(A, B, C, D, E);
+push!(A, B, C, D, E, F);
+push!(A, B, C, D, E, F, G);
+push!(A, B, C, D, E, F, G, H);
+push!(A, B, C, D, E, F, G, H, I);
+push!(A, B, C, D, E, F, G, H, I, J);
+push!(A, B, C, D, E, F, G, H, I, J, K);
+push!(A, B, C, D, E, F, G, H, I, J, K, L);
+push!(A, B, C, D, E, F, G, H, I, J, K, L, M);
+push!(A, B, C, D, E, F, G, H, I, J, K, L, M, N);
+push!(A, B, C, D, E, F, G, H, I, J, K, L, M, N, O);
+
+impl<'r, Robot, R> Batch<'r, Robot, R> {
+    /// Adds `cmd`, don't wrap it in an [`cmd::Ack`] if the server replies to it anyway, those are rejected
+    pub fn cmd<C: Command>(self, cmd: C) -> Then<'r, Robot, R, C::Return>
+    where
+        R: Push<C::Return>,
+    {
+        let mut cmds = self.cmds;
+        cmds.push(cmd.into());
+        Batch {
+            robot: self.robot,
+            cmds,
+            _ret: PhantomData,
+        }
+    }
+
+    pub fn nop(self) -> Then<'r, Robot, R, ()>
+    where
+        R: Push<()>,
+    {
+        self.cmd(cmd::Nop)
+    }
+
+    pub fn get_uptime(self) -> Then<'r, Robot, R, std::time::Duration>
+    where
+        R: Push<std::time::Duration>,
+    {
+        self.cmd(cmd::GetUptime)
+    }
+}
+
+impl<T: Transport, R> Batch<'_, Robot<T>, R>
+where
+    R: Serialize + DeserializeOwned + Send + Sync + 'static,
+{
+    pub fn send(self) -> Result<R> {
+        self.robot.transport.cmd(cmd::Batch::new(self.cmds))
+    }
+}
+
+#[cfg(feature = "async")]
+impl<T: crate::transports::TransportAsync, R> Batch<'_, crate::RobotAsync<T>, R>
+where
+    R: Serialize + DeserializeOwned + Send + Sync + 'static,
+{
+    pub async fn send(self) -> Result<R> {
+        self.robot.transport.cmd(cmd::Batch::new(self.cmds)).await
+    }
+}
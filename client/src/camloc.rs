@@ -1,7 +1,11 @@
 use anyhow::Result;
 use roblib::cmd;
 
-use crate::{transports::Transport, Robot};
+use crate::{
+    batch::{Batch, Push, Then},
+    transports::Transport,
+    Robot,
+};
 
 impl<T: Transport> roblib::camloc::Camloc for Robot<T> {
     fn get_position(&self) -> Result<Option<roblib::camloc::Position>> {
@@ -9,6 +13,15 @@ impl<T: Transport> roblib::camloc::Camloc for Robot<T> {
     }
 }
 
+impl<'r, Robot, R> Batch<'r, Robot, R> {
+    pub fn get_position(self) -> Then<'r, Robot, R, Option<roblib::camloc::Position>>
+    where
+        R: Push<Option<roblib::camloc::Position>>,
+    {
+        self.cmd(cmd::GetPosition)
+    }
+}
+
 #[cfg(feature = "async")]
 #[async_trait::async_trait]
 impl<T: crate::transports::TransportAsync> roblib::camloc::CamlocAsync
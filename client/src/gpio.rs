@@ -2,6 +2,7 @@ use anyhow::Result;
 use roblib::{cmd, gpio::event::GpioPin};
 
 use crate::{
+    batch::{Batch, Push, Then},
     transports::{Subscribable, Transport},
     Robot,
 };
@@ -28,6 +29,43 @@ impl<T: Transport> roblib::gpio::Gpio for Robot<T> {
     }
 }
 
+impl<'r, Robot, R> Batch<'r, Robot, R> {
+    pub fn read_pin(self, pin: u8) -> Then<'r, Robot, R, bool>
+    where
+        R: Push<bool>,
+    {
+        self.cmd(cmd::ReadPin(pin))
+    }
+
+    pub fn write_pin(self, pin: u8, value: bool) -> Then<'r, Robot, R, ()>
+    where
+        R: Push<()>,
+    {
+        self.cmd(cmd::WritePin(pin, value))
+    }
+
+    pub fn pwm(self, pin: u8, hz: f64, cycle: f64) -> Then<'r, Robot, R, ()>
+    where
+        R: Push<()>,
+    {
+        self.cmd(cmd::Pwm(pin, hz, cycle))
+    }
+
+    pub fn servo(self, pin: u8, degree: f64) -> Then<'r, Robot, R, ()>
+    where
+        R: Push<()>,
+    {
+        self.cmd(cmd::Servo(pin, degree))
+    }
+
+    pub fn pin_mode(self, pin: u8, mode: roblib::gpio::Mode) -> Then<'r, Robot, R, ()>
+    where
+        R: Push<()>,
+    {
+        self.cmd(cmd::PinMode(pin, mode))
+    }
+}
+
 pub struct Pin<'r, T: Transport> {
     robot: &'r Robot<T>,
     pin: u8,
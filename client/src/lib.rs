@@ -1,3 +1,4 @@
+pub mod batch;
 pub mod logger;
 pub mod transports;
 
@@ -31,6 +32,21 @@ impl<T: Transport> Robot<T> {
         Self { transport }
     }
 
+    /// Like [`Robot::new`], but fails if the server can't handle everything this client can send,
+    /// see [`cmd::Capabilities::verify`]
+    pub fn new_verified(transport: T) -> Result<Self> {
+        let robot = Self::new(transport);
+        robot.get_capabilities()?.verify()?;
+        Ok(robot)
+    }
+
+    /// Like [`Robot::new`], but authenticates with the server's shared secret first,
+    /// see [`cmd::Authenticate`]
+    pub fn new_authenticated(transport: T, secret: &str) -> Result<Self> {
+        transport.authenticate(secret)?;
+        Ok(Self::new(transport))
+    }
+
     pub fn measure_latency(&self) -> Result<Duration> {
         let start = Instant::now();
         let _ = self.transport.cmd(cmd::GetUptime)?;
@@ -39,6 +55,11 @@ impl<T: Transport> Robot<T> {
     pub fn get_server_uptime(&self) -> Result<Duration> {
         self.transport.cmd(cmd::GetUptime)
     }
+
+    /// Starts building a batch of commands, which are sent and executed in one go
+    pub fn batch(&self) -> batch::Batch<'_, Self, ()> {
+        batch::Batch::new(self)
+    }
 }
 impl<T: Subscribable> Robot<T> {
     pub fn subscribe<E: Event>(
@@ -62,7 +83,39 @@ impl<T: Transport> RoblibBuiltin for Robot<T> {
         self.transport.cmd(cmd::GetUptime)
     }
 
+    fn get_capabilities(&self) -> anyhow::Result<cmd::Capabilities> {
+        self.transport.cmd(cmd::GetCapabilities)
+    }
+
+    fn get_schema(&self) -> anyhow::Result<roblib::schema::Schema> {
+        self.transport.cmd(cmd::GetSchema)
+    }
+
     fn abort(&self) -> anyhow::Result<()> {
         self.transport.cmd(cmd::Abort)
     }
+
+    fn schedule(&self, when: cmd::When, cmd: cmd::Concrete) -> anyhow::Result<u32> {
+        self.transport.cmd(cmd::Schedule::new(when, cmd))
+    }
+
+    fn list_scheduled(&self) -> anyhow::Result<Vec<cmd::Scheduled>> {
+        self.transport.cmd(cmd::ListScheduled)
+    }
+
+    fn cancel_scheduled(&self, id: u32) -> anyhow::Result<bool> {
+        self.transport.cmd(cmd::CancelScheduled(id))
+    }
+
+    fn acquire_control(&self) -> anyhow::Result<Duration> {
+        self.transport.cmd(cmd::AcquireControl)
+    }
+
+    fn take_control(&self, admin_token: &str) -> anyhow::Result<Duration> {
+        self.transport.cmd(cmd::TakeControl(admin_token.into()))
+    }
+
+    fn release_control(&self) -> anyhow::Result<()> {
+        self.transport.cmd(cmd::ReleaseControl)
+    }
 }
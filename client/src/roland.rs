@@ -1,4 +1,8 @@
-use crate::{transports::Transport, Robot};
+use crate::{
+    batch::{Batch, Push, Then},
+    transports::Transport,
+    Robot,
+};
 use anyhow::Result;
 use roblib::cmd;
 
@@ -87,3 +91,67 @@ impl<T: crate::transports::TransportAsync> roblib::roland::RolandAsync
         self.transport.cmd(cmd::StopRobot).await
     }
 }
+
+impl<'r, Robot, R> Batch<'r, Robot, R> {
+    pub fn drive(self, left: f64, right: f64) -> Then<'r, Robot, R, ()>
+    where
+        R: Push<()>,
+    {
+        if !(-1. ..=1.).contains(&left) || !(-1. ..=1.).contains(&right) {
+            log::warn!("Drive values are now [-1, 1] not [-100, 100]");
+        }
+        self.cmd(cmd::MoveRobot(left, right))
+    }
+
+    pub fn drive_by_angle(self, angle: f64, speed: f64) -> Then<'r, Robot, R, ()>
+    where
+        R: Push<()>,
+    {
+        if !(-1. ..=1.).contains(&speed) {
+            log::warn!("Drive values are now [-1, 1] not [-100, 100]");
+        }
+        self.cmd(cmd::MoveRobotByAngle(angle, speed))
+    }
+
+    pub fn led(self, r: bool, g: bool, b: bool) -> Then<'r, Robot, R, ()>
+    where
+        R: Push<()>,
+    {
+        self.cmd(cmd::Led(r, g, b))
+    }
+
+    pub fn roland_servo(self, degree: f64) -> Then<'r, Robot, R, ()>
+    where
+        R: Push<()>,
+    {
+        self.cmd(cmd::RolandServo(degree))
+    }
+
+    pub fn buzzer(self, pw: f64) -> Then<'r, Robot, R, ()>
+    where
+        R: Push<()>,
+    {
+        self.cmd(cmd::Buzzer(pw))
+    }
+
+    pub fn track_sensor(self) -> Then<'r, Robot, R, [bool; 4]>
+    where
+        R: Push<[bool; 4]>,
+    {
+        self.cmd(cmd::TrackSensor)
+    }
+
+    pub fn ultra_sensor(self) -> Then<'r, Robot, R, f64>
+    where
+        R: Push<f64>,
+    {
+        self.cmd(cmd::UltraSensor)
+    }
+
+    pub fn stop(self) -> Then<'r, Robot, R, ()>
+    where
+        R: Push<()>,
+    {
+        self.cmd(cmd::StopRobot)
+    }
+}
@@ -1,12 +1,17 @@
 use reqwest::Client;
 use roblib::{
-    cmd::{has_return, Command, Concrete},
-    text_format,
+    cmd::{self, has_return, Command, Concrete},
+    json, text_format,
 };
+use std::{sync::RwLock, time::Duration};
 
 pub struct Http {
     base_url: String,
     client: Client,
+    timeout: Option<Duration>,
+    json: bool,
+    /// The session token sent with every request, once authenticated
+    token: RwLock<Option<String>>,
 }
 
 impl Http {
@@ -14,30 +19,122 @@ impl Http {
         Ok(Self {
             base_url: format!("http://{base_url}/cmd"),
             client: Client::new(),
+            timeout: None,
+            json: false,
+            token: RwLock::new(None),
         })
     }
+
+    /// Connects using the json encoding instead of the text format, see [`roblib::json`]
+    pub fn connect_json(base_url: &str) -> anyhow::Result<Self> {
+        Ok(Self {
+            json: true,
+            ..Self::connect(base_url)?
+        })
+    }
+
+    /// Connects with `https://`, checking the server's certificate with `tls`
+    #[cfg(feature = "tls")]
+    pub fn connect_tls(base_url: &str, tls: &super::tls::Tls) -> anyhow::Result<Self> {
+        let client = Client::builder()
+            .use_preconfigured_tls(tls.client_config())
+            .build()?;
+        Ok(Self {
+            base_url: format!("https://{base_url}/cmd"),
+            client,
+            ..Self::connect(base_url)?
+        })
+    }
+
+    /// Same as [`Http::connect_tls`], with the json encoding
+    #[cfg(feature = "tls")]
+    pub fn connect_json_tls(base_url: &str, tls: &super::tls::Tls) -> anyhow::Result<Self> {
+        Ok(Self {
+            json: true,
+            ..Self::connect_tls(base_url, tls)?
+        })
+    }
+
+    /// Sets the default timeout for commands, see [`super::TransportAsync::cmd`]
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    async fn cmd_opt<C: Command>(
+        &self,
+        cmd: C,
+        timeout: Option<Duration>,
+    ) -> anyhow::Result<C::Return> {
+        let cmd: Concrete = cmd.into();
+
+        let mut req = self.client.post(&self.base_url);
+        req = if self.json {
+            let body = serde_json::to_string(&json::Request { id: 0, cmd })?;
+            req.header(reqwest::header::CONTENT_TYPE, json::CONTENT_TYPE)
+                .body(body)
+        } else {
+            req.body(text_format::ser::to_string(&cmd)?)
+        };
+        if let Some(t) = timeout {
+            req = req.timeout(t);
+        }
+        if let Some(token) = &*self.token.read().unwrap() {
+            req = req.bearer_auth(token);
+        }
+
+        let res = match req.send().await {
+            Ok(res) => res,
+            Err(e) if e.is_timeout() => return Err(super::Timeout(timeout.unwrap()).into()),
+            Err(e) => return Err(e.into()),
+        };
+
+        let status = res.status();
+        let res = res.text().await?;
+
+        if !status.is_success() {
+            let code = if status == reqwest::StatusCode::UNAUTHORIZED {
+                cmd::ErrorCode::Unauthorized
+            } else if status == reqwest::StatusCode::CONFLICT {
+                cmd::ErrorCode::NotInControl
+            } else if status.is_client_error() {
+                cmd::ErrorCode::Decode
+            } else {
+                cmd::ErrorCode::Execute
+            };
+            return Err(cmd::Error(code, res).into());
+        }
+
+        if !has_return::<C>() {
+            super::no_reply()
+        } else if self.json {
+            Ok(serde_json::from_str(&res)?)
+        } else {
+            Ok(text_format::de::from_str(&res)?)
+        }
+    }
 }
 
 #[cfg(feature = "async")]
 #[cfg_attr(feature = "async", async_trait::async_trait)]
 impl super::TransportAsync for Http {
     async fn cmd<C: Command + Send>(&self, cmd: C) -> anyhow::Result<C::Return> {
-        let cmd: Concrete = cmd.into();
-        let s = text_format::ser::to_string(&cmd)?;
-
-        let res = self
-            .client
-            .post(&self.base_url)
-            .body(s)
-            .send()
-            .await?
-            .text()
-            .await?;
-
-        if has_return::<C>() {
-            Ok(text_format::de::from_str(&res)?)
-        } else {
-            Ok(unsafe { std::mem::zeroed() })
-        }
+        self.cmd_opt(cmd, self.timeout).await
+    }
+
+    async fn cmd_timeout<C: Command + Send>(
+        &self,
+        cmd: C,
+        timeout: Duration,
+    ) -> anyhow::Result<C::Return> {
+        self.cmd_opt(cmd, Some(timeout)).await
+    }
+
+    /// Every request is on its own, so the session token is kept and sent with the later ones
+    async fn authenticate(&self, secret: &str) -> anyhow::Result<()> {
+        let challenge = self.cmd(cmd::GetChallenge).await?;
+        let token = self.cmd(cmd::Authenticate::new(secret, challenge)).await?;
+        *self.token.write().unwrap() = Some(token);
+        Ok(())
     }
 }
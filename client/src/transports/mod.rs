@@ -1,10 +1,16 @@
 use anyhow::Result;
-use roblib::{cmd::Command, event::Event};
+use roblib::{
+    cmd::{self, Command},
+    event::Event,
+};
+use std::time::Duration;
 
 #[cfg(feature = "http")]
 pub mod http;
 #[cfg(feature = "tcp")]
 pub mod tcp;
+#[cfg(feature = "tls")]
+pub mod tls;
 #[cfg(feature = "udp")]
 pub mod udp;
 #[cfg(feature = "ws")]
@@ -12,10 +18,156 @@ pub mod ws;
 
 const ID_START: u32 = 1;
 
+/// How often [`cmd::Heartbeat`]s are sent while the robot is driving, by default
+pub const HEARTBEAT_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Sends a [`cmd::Heartbeat`] now and then while the robot is driving,
+/// so the server's watchdog doesn't stop it while the program is just waiting.
+/// They're sent with the id 0, which commands never get.
+#[cfg(any(feature = "tcp", feature = "ws"))]
+struct Heartbeat {
+    interval: std::sync::Mutex<Option<Duration>>,
+    /// The number of the last drive, and whether it's still going.
+    /// A heartbeat thread left from an earlier drive knows to stop by it.
+    drive: std::sync::Mutex<(u64, bool)>,
+}
+
+#[cfg(any(feature = "tcp", feature = "ws"))]
+impl Heartbeat {
+    fn new() -> Self {
+        Self {
+            interval: Some(HEARTBEAT_INTERVAL).into(),
+            drive: Default::default(),
+        }
+    }
+
+    fn set_interval(&self, interval: Option<Duration>) {
+        *self.interval.lock().unwrap() = interval;
+    }
+
+    /// Notes whether `cmd` starts or stops the robot, returns the number of the drive if it starts one
+    fn update(&self, cmd: &cmd::Concrete) -> Option<u64> {
+        let driving = cmd.drives()?;
+        let mut drive = self.drive.lock().unwrap();
+        if driving == drive.1 {
+            return None;
+        }
+        drive.1 = driving;
+        if driving {
+            drive.0 += 1;
+            Some(drive.0)
+        } else {
+            None
+        }
+    }
+
+    /// How long to wait before the next heartbeat of `drive`, `None` once it's over
+    #[cfg(feature = "tcp")]
+    fn next(&self, drive: u64) -> Option<Duration> {
+        let (current, driving) = *self.drive.lock().unwrap();
+        if driving && current == drive {
+            *self.interval.lock().unwrap()
+        } else {
+            None
+        }
+    }
+
+    /// Keeps the timer of an async worker, `None` while the robot isn't driving,
+    /// so it starts over with every drive
+    #[cfg(feature = "async")]
+    fn ticks(&self, ticks: &mut Option<tokio::time::Interval>) {
+        let interval = *self.interval.lock().unwrap();
+        let driving = self.drive.lock().unwrap().1;
+        match interval.filter(|_| driving) {
+            Some(interval) if ticks.is_none() => {
+                let start = tokio::time::Instant::now() + interval;
+                let mut i = tokio::time::interval_at(start, interval);
+                i.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+                *ticks = Some(i);
+            }
+            Some(_) => (),
+            None => *ticks = None,
+        }
+    }
+}
+
+/// The server didn't reply to a command in time.
+///
+/// Returned inside an [`anyhow::Error`], check for it with `err.is::<Timeout>()`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Timeout(pub Duration);
+
+impl std::fmt::Display for Timeout {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "No reply from the server in {:?}", self.0)
+    }
+}
+
+impl std::error::Error for Timeout {}
+
+/// Converts `cmd` for sending, wrapping it in an [`cmd::Ack`] if `ack` is set and it needs one.
+/// Also returns whether the server is going to reply.
+fn prepare<C: Command>(cmd: C, ack: bool) -> (cmd::Concrete, bool) {
+    if ack && !C::REPLIES {
+        (cmd::Ack::new(cmd).into(), true)
+    } else {
+        (cmd.into(), C::REPLIES)
+    }
+}
+
+/// The return value of a command the server didn't reply to, always `()` or some other zero sized type
+fn no_reply<T: serde::de::DeserializeOwned>() -> Result<T> {
+    Ok(bincode::Options::deserialize(bincode::options(), &[])?)
+}
+
+/// Waits for a reply from the listener thread, forever if there's no `timeout`
+#[cfg(any(feature = "tcp", feature = "udp"))]
+fn recv_reply<T>(rx: &std::sync::mpsc::Receiver<T>, timeout: Option<Duration>) -> Result<T> {
+    use std::sync::mpsc::RecvTimeoutError;
+
+    match timeout {
+        Some(t) => rx.recv_timeout(t).map_err(|e| match e {
+            RecvTimeoutError::Timeout => Timeout(t).into(),
+            RecvTimeoutError::Disconnected => anyhow::anyhow!("Disconnected from the server"),
+        }),
+        None => rx
+            .recv()
+            .map_err(|_| anyhow::anyhow!("Disconnected from the server")),
+    }
+}
+
+/// Waits for a reply in an async transport, forever if there's no `timeout`
+#[cfg(feature = "async")]
+async fn wait_reply<F: std::future::Future>(
+    fut: F,
+    timeout: Option<Duration>,
+) -> Result<F::Output> {
+    match timeout {
+        Some(t) => tokio::time::timeout(t, fut)
+            .await
+            .map_err(|_| Timeout(t).into()),
+        None => Ok(fut.await),
+    }
+}
+
 pub trait Transport {
+    /// Sends `cmd`, waiting for its reply at most as long as the transport's default timeout, if it has one
     fn cmd<C>(&self, cmd: C) -> Result<C::Return>
     where
         C: Command;
+
+    /// Sends `cmd`, failing with [`Timeout`] if there's no reply in `timeout`
+    fn cmd_timeout<C>(&self, cmd: C, timeout: Duration) -> Result<C::Return>
+    where
+        C: Command;
+
+    /// Proves that the client knows the server's shared secret, see [`cmd::Authenticate`].
+    /// Servers with a secret don't run anything else until this is done.
+    fn authenticate(&self, secret: &str) -> Result<()> {
+        let challenge = self.cmd(cmd::GetChallenge)?;
+        self.cmd(cmd::Authenticate::new(secret, challenge))?;
+        Ok(())
+    }
 }
 
 pub trait Subscribable: Transport {
@@ -32,9 +184,25 @@ pub trait Subscribable: Transport {
 #[cfg(feature = "async")]
 #[cfg_attr(feature = "async", async_trait::async_trait)]
 pub trait TransportAsync: Send + Sync {
+    /// Sends `cmd`, waiting for its reply at most as long as the transport's default timeout, if it has one.
+    ///
+    /// Cancel safe: if the future is dropped before the reply arrives, the reply is discarded.
     async fn cmd<C>(&self, cmd: C) -> Result<C::Return>
     where
         C: Command;
+
+    /// Sends `cmd`, failing with [`Timeout`] if there's no reply in `timeout`
+    async fn cmd_timeout<C>(&self, cmd: C, timeout: Duration) -> Result<C::Return>
+    where
+        C: Command;
+
+    /// Proves that the client knows the server's shared secret, see [`cmd::Authenticate`].
+    /// Servers with a secret don't run anything else until this is done.
+    async fn authenticate(&self, secret: &str) -> Result<()> {
+        let challenge = self.cmd(cmd::GetChallenge).await?;
+        self.cmd(cmd::Authenticate::new(secret, challenge)).await?;
+        Ok(())
+    }
 }
 
 #[cfg(feature = "async")]
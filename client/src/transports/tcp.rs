@@ -1,14 +1,18 @@
 use super::{Subscribable, Transport};
 use anyhow::Result;
 use roblib::{
-    cmd::{self, has_return, Command},
+    cmd::{self, Command},
     event::Event,
 };
 use serde::Deserialize;
 use std::{
     collections::HashMap,
-    io::{Cursor, Read, Write},
-    sync::Arc,
+    io::{self, Cursor, Read, Write},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
 };
 
 type D<'a> = bincode::Deserializer<
@@ -21,40 +25,142 @@ struct TcpInner {
     handlers: std::sync::Mutex<HashMap<u32, (Handler, bool)>>,
     events: std::sync::Mutex<HashMap<roblib::event::ConcreteType, u32>>,
     running: std::sync::RwLock<bool>,
+    max_frame: AtomicUsize,
 }
+/// The connection to the server, read by the listener thread while commands are written to it
+enum Socket {
+    Plain(std::net::TcpStream),
+    #[cfg(feature = "tls")]
+    Tls(Box<super::tls::TlsStream>),
+}
+
+impl Read for &Socket {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Socket::Plain(s) => (&*s).read(buf),
+            #[cfg(feature = "tls")]
+            Socket::Tls(s) => (&**s).read(buf),
+        }
+    }
+}
+
+impl Write for &Socket {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Socket::Plain(s) => (&*s).write(buf),
+            #[cfg(feature = "tls")]
+            Socket::Tls(s) => (&**s).write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Socket::Plain(s) => (&*s).flush(),
+            #[cfg(feature = "tls")]
+            Socket::Tls(s) => (&**s).flush(),
+        }
+    }
+}
+
+/// The socket and a lock on writing to it, taken for every frame so they can't interleave on the wire.
+/// A single frame might be written in more than one go.
+struct Connection {
+    socket: Socket,
+    write: std::sync::Mutex<()>,
+}
+
+impl Connection {
+    fn send(&self, frame: &[u8]) -> io::Result<()> {
+        let _write = self.write.lock().unwrap();
+        (&self.socket).write_all(frame)
+    }
+}
+
 pub struct Tcp {
     inner: Arc<TcpInner>,
 
-    socket: std::net::TcpStream,
+    socket: Arc<Connection>,
     id: std::sync::Mutex<u32>,
+    timeout: Option<Duration>,
+    ack: bool,
+    heartbeat: Arc<super::Heartbeat>,
 }
 
 impl Tcp {
-    const HEADER: usize = std::mem::size_of::<u32>();
-
     pub fn connect(robot: impl std::net::ToSocketAddrs) -> anyhow::Result<Self> {
         let socket = std::net::TcpStream::connect(robot)?;
+        Ok(Self::start(Socket::Plain(socket)))
+    }
 
+    /// Connects over TLS, checking the server's certificate with `tls`
+    #[cfg(feature = "tls")]
+    pub fn connect_tls(robot: &str, tls: &super::tls::Tls) -> anyhow::Result<Self> {
+        let socket = std::net::TcpStream::connect(robot)?;
+        let stream = super::tls::TlsStream::connect(socket, tls, robot)?;
+        Ok(Self::start(Socket::Tls(Box::new(stream))))
+    }
+
+    fn start(socket: Socket) -> Self {
+        let socket = Arc::new(Connection {
+            socket,
+            write: Default::default(),
+        });
         let inner = Arc::new(TcpInner {
             handlers: HashMap::new().into(),
             events: HashMap::new().into(),
             running: true.into(),
+            max_frame: cmd::MAX_FRAME.into(),
         });
 
         let inner_clone = inner.clone();
-        let socket_clone = socket.try_clone()?;
-        std::thread::spawn(|| Self::listen(inner_clone, socket_clone));
+        let socket_clone = socket.clone();
+        std::thread::spawn(move || {
+            let res = Self::listen(&inner_clone, &socket_clone.socket);
+            // wake up everyone waiting for a reply
+            inner_clone.handlers.lock().unwrap().clear();
+            res
+        });
 
-        Ok(Self {
+        Self {
             inner,
             id: super::ID_START.into(),
             socket,
-        })
+            timeout: None,
+            ack: false,
+            heartbeat: Arc::new(super::Heartbeat::new()),
+        }
     }
 
-    fn listen(inner: Arc<TcpInner>, mut socket: std::net::TcpStream) -> Result<()> {
+    /// Sets the default timeout for commands, see [`Transport::cmd`]
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Makes the server acknowledge every command, so the ones without a return value also report failures.
+    /// See [`cmd::Ack`].
+    pub fn with_ack(mut self) -> Self {
+        self.ack = true;
+        self
+    }
+
+    /// Sets the largest reply accepted, [`cmd::MAX_FRAME`] by default.
+    /// Larger ones are skipped, and fail the command with a [`cmd::ErrorCode::Decode`] error.
+    pub fn with_max_frame(self, max_frame: usize) -> Self {
+        self.inner.max_frame.store(max_frame, Ordering::Relaxed);
+        self
+    }
+
+    /// Sets how often heartbeats are sent while the robot is driving, [`super::HEARTBEAT_INTERVAL`] by default,
+    /// `None` turns them off. See [`cmd::Heartbeat`].
+    pub fn with_heartbeat(self, interval: Option<Duration>) -> Self {
+        self.heartbeat.set_interval(interval);
+        self
+    }
+
+    fn listen(inner: &TcpInner, mut socket: &Socket) -> Result<()> {
         let bin = bincode::options();
-        let mut buf = vec![0; 512];
+        let mut buf = Vec::new();
         loop {
             let running = inner.running.read().unwrap();
             if !*running {
@@ -62,21 +168,22 @@ impl Tcp {
             }
             drop(running);
 
-            socket.read_exact(&mut buf[..Self::HEADER])?;
-            let len = u32::from_be_bytes(buf[..Self::HEADER].try_into()?) as usize;
-            let end = Self::HEADER + len;
-            // log::debug!("Receiving {len} bytes");
-            if len > buf.len() {
-                buf.resize(len, 0);
-                log::debug!("Connection buffer resized to {len}");
-            }
-            socket.read_exact(&mut buf[Self::HEADER..end])?;
+            let max_frame = inner.max_frame.load(Ordering::Relaxed);
+            read_frame(&mut socket, &mut buf, max_frame)?;
 
-            let mut c = Cursor::new(&buf[Self::HEADER..end]);
+            let mut c = Cursor::new(&buf[..]);
             let id: u32 = bincode::Options::deserialize_from(bin, &mut c)?;
 
             let Some(mut handler) = inner.handlers.lock().unwrap().remove(&id) else {
-                return Err(anyhow::Error::msg("received response for unknown id"));
+                // commands without a return value don't wait for a reply,
+                // but the server still reports it if they fail
+                match Result::<(), cmd::Error>::deserialize(
+                    &mut bincode::Deserializer::with_reader(&mut c, bin),
+                ) {
+                    Ok(Err(e)) => log::error!("command {id} failed: {e}"),
+                    _ => log::error!("received response for unknown id: {id}"),
+                }
+                continue;
             };
 
             handler.0(bincode::Deserializer::with_reader(&mut c, bin))?;
@@ -87,30 +194,72 @@ impl Tcp {
         }
     }
 
-    fn cmd_id<C>(&self, cmd: C, id: u32) -> Result<C::Return>
+    fn cmd_id<C>(&self, cmd: C, id: u32, timeout: Option<Duration>, ack: bool) -> Result<C::Return>
     where
         C: Command,
     {
-        let concrete: cmd::Concrete = cmd.into();
-        let buf = bincode::Options::serialize(bincode::options(), &(id, concrete))?;
-        (&self.socket).write_all(&(buf.len() as u32).to_be_bytes())?;
-        (&self.socket).write_all(&buf)?;
+        let (concrete, replies) = super::prepare(cmd, ack);
+        let buf = frame(id, &concrete)?;
 
-        Ok(if has_return::<C>() {
+        // register the handler before sending, so the reply can't arrive without one
+        let rx = if replies {
             let (tx, rx) = std::sync::mpsc::sync_channel(1);
 
             let a: Handler = Box::new(move |mut des: D| {
-                let r = C::Return::deserialize(&mut des)?;
-                tx.send(r).unwrap();
+                let r = Result::<C::Return, cmd::Error>::deserialize(&mut des)?;
+                // the receiver is gone if the command timed out
+                let _ = tx.send(r);
                 Ok::<(), anyhow::Error>(())
             });
             self.inner.handlers.lock().unwrap().insert(id, (a, false));
 
-            rx.recv()?
+            Some(rx)
+        } else {
+            None
+        };
+
+        self.socket.send(&buf)?;
+        if let Some(drive) = self.heartbeat.update(&concrete) {
+            self.heartbeats(drive);
+        }
+
+        Ok(if let Some(rx) = rx {
+            match super::recv_reply(&rx, timeout) {
+                Ok(r) => r?,
+                Err(e) => {
+                    self.inner.handlers.lock().unwrap().remove(&id);
+                    return Err(e);
+                }
+            }
         } else {
-            unsafe { std::mem::zeroed() }
+            super::no_reply()?
         })
     }
+
+    /// Sends heartbeats from a new thread until `drive` is over, or the client is dropped
+    fn heartbeats(&self, drive: u64) {
+        let heartbeat = Arc::downgrade(&self.heartbeat);
+        let socket = self.socket.clone();
+        std::thread::spawn(move || {
+            let next = || heartbeat.upgrade().and_then(|h| h.next(drive));
+            let buf = frame(0, &cmd::Heartbeat.into())?;
+            while let Some(interval) = next() {
+                std::thread::sleep(interval);
+                if next().is_none() {
+                    break;
+                }
+                socket.send(&buf)?;
+            }
+            anyhow::Ok(())
+        });
+    }
+
+    fn next_id(&self) -> u32 {
+        let mut id_handle = self.id.lock().unwrap();
+        let id = *id_handle;
+        *id_handle = id + 1;
+        id
+    }
 }
 
 impl Transport for Tcp {
@@ -118,11 +267,14 @@ impl Transport for Tcp {
     where
         C: Command,
     {
-        let mut id_handle = self.id.lock().unwrap();
-        let id = *id_handle;
-        *id_handle = id + 1;
-        drop(id_handle);
-        self.cmd_id(cmd, id)
+        self.cmd_id(cmd, self.next_id(), self.timeout, self.ack)
+    }
+
+    fn cmd_timeout<C>(&self, cmd: C, timeout: Duration) -> anyhow::Result<C::Return>
+    where
+        C: Command,
+    {
+        self.cmd_id(cmd, self.next_id(), Some(timeout), self.ack)
     }
 }
 
@@ -132,23 +284,27 @@ impl Subscribable for Tcp {
         E: Event,
         F: (FnMut(E::Item) -> Result<()>) + Send + Sync + 'static,
     {
-        let mut id_handle = self.id.lock().unwrap();
-        let id = *id_handle;
-        *id_handle = id + 1;
-        drop(id_handle);
-
+        let id = self.next_id();
         let ev = ev.into();
 
         self.inner.handlers.lock().unwrap().insert(
             id,
             (
-                Box::new(move |mut des| handler(E::Item::deserialize(&mut des)?)),
+                Box::new(move |mut des| {
+                    match Result::<E::Item, cmd::Error>::deserialize(&mut des)? {
+                        Ok(item) => handler(item),
+                        Err(e) => {
+                            log::error!("subscription {id} failed: {e}");
+                            Ok(())
+                        }
+                    }
+                }),
                 true,
             ),
         );
         self.inner.events.lock().unwrap().insert(ev.clone(), id);
 
-        self.cmd_id(cmd::Subscribe(ev), id)?;
+        self.cmd_id(cmd::Subscribe(ev), id, self.timeout, false)?;
 
         Ok(())
     }
@@ -161,7 +317,7 @@ impl Subscribable for Tcp {
         match lock.entry(ev) {
             std::collections::hash_map::Entry::Occupied(v) => {
                 let id = v.remove();
-                self.cmd_id(cmd, id)?;
+                self.cmd_id(cmd, id, self.timeout, false)?;
                 self.inner.handlers.lock().unwrap().remove(&id);
             }
             std::collections::hash_map::Entry::Vacant(_) => anyhow::bail!("Subscription not found"),
@@ -171,22 +327,76 @@ impl Subscribable for Tcp {
     }
 }
 
+/// Serializes a command with its length in front, to be sent with [`Connection::send`]
+fn frame(id: u32, cmd: &cmd::Concrete) -> Result<Vec<u8>> {
+    let mut buf = vec![0; 4];
+    bincode::Options::serialize_into(bincode::options(), &mut buf, &(id, cmd))?;
+    let len = (buf.len() - 4) as u32;
+    buf[..4].copy_from_slice(&len.to_be_bytes());
+    Ok(buf)
+}
+
+/// The most bytes a varint encoded `u32` takes up
+const ID_LEN: usize = 5;
+
+/// Reads the next frame into `buf`.
+/// One over `max_frame` is skipped, and replaced with a `Decode` error for its id.
+fn read_frame(r: &mut impl Read, buf: &mut Vec<u8>, max_frame: usize) -> io::Result<()> {
+    let mut header = [0; 4];
+    r.read_exact(&mut header)?;
+    let len = u32::from_be_bytes(header) as usize;
+
+    if len > max_frame {
+        let mut head = vec![0; len.min(ID_LEN)];
+        r.read_exact(&mut head)?;
+        io::copy(&mut r.take((len - head.len()) as u64), &mut io::sink())?;
+        oversize(&head, len, max_frame, buf)
+    } else {
+        buf.resize(len, 0);
+        r.read_exact(buf)
+    }
+}
+
+/// Replaces a frame over the limit with an error reply for the same id
+fn oversize(head: &[u8], len: usize, max_frame: usize, buf: &mut Vec<u8>) -> io::Result<()> {
+    let bin = bincode::options();
+    let invalid = |e| io::Error::new(io::ErrorKind::InvalidData, e);
+
+    let id: u32 = bincode::Options::deserialize_from(bin, head).map_err(invalid)?;
+    log::error!("reply {id} of {len} bytes is over the limit of {max_frame}");
+
+    let err = cmd::Error::new(
+        cmd::ErrorCode::Decode,
+        format!("reply of {len} bytes is over the limit of {max_frame}"),
+    );
+    buf.clear();
+    bincode::Options::serialize_into(bin, buf, &(id, Err::<(), _>(err))).map_err(invalid)
+}
+
 #[cfg(feature = "async")]
 pub use tcp_async::*;
 #[cfg(feature = "async")]
 pub mod tcp_async {
-    use std::{collections::HashMap, io::Cursor, time::Duration};
+    use std::{
+        collections::HashMap,
+        io::Cursor,
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc, Weak,
+        },
+        time::Duration,
+    };
 
-    use crate::transports::{SubscribableAsync, TransportAsync};
+    use crate::transports::{Heartbeat, SubscribableAsync, TransportAsync};
     use anyhow::Result;
     use async_trait::async_trait;
     use roblib::{
-        cmd::{self, has_return, Command},
+        cmd::{self, Command},
         event::{self, Event},
     };
     use serde::{Deserialize, Serialize};
     use tokio::{
-        io::{AsyncReadExt, AsyncWriteExt, Interest},
+        io::{self, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt},
         net::{TcpStream, ToSocketAddrs},
         sync::{broadcast, mpsc, oneshot},
         task::JoinHandle,
@@ -197,103 +407,137 @@ pub mod tcp_async {
         bincode::DefaultOptions,
     >;
 
+    /// The halves of the connection, plain or TLS
+    type Reader = Box<dyn AsyncRead + Send + Unpin>;
+    type Writer = Box<dyn AsyncWrite + Send + Unpin>;
+
+    /// Reads frames until the server disconnects, see [`super::read_frame`]
+    async fn read_frames(
+        mut stream: Reader,
+        max_frame: Arc<AtomicUsize>,
+        tx: mpsc::Sender<Vec<u8>>,
+    ) -> io::Result<()> {
+        loop {
+            let len = match stream.read_u32().await {
+                Ok(len) => len as usize,
+                Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(()),
+                Err(e) => return Err(e),
+            };
+
+            let max_frame = max_frame.load(Ordering::Relaxed);
+            let mut buf = Vec::new();
+            if len > max_frame {
+                let mut head = vec![0; len.min(super::ID_LEN)];
+                stream.read_exact(&mut head).await?;
+                let rest = (len - head.len()) as u64;
+                io::copy(&mut (&mut stream).take(rest), &mut io::sink()).await?;
+                super::oversize(&head, len, max_frame, &mut buf)?;
+            } else {
+                buf.resize(len, 0);
+                stream.read_exact(&mut buf).await?;
+            }
+
+            if tx.send(buf).await.is_err() {
+                return Ok(());
+            }
+        }
+    }
+
     enum Action {
-        ServerMessage(usize),
+        ServerMessage(Vec<u8>),
         Cmd(cmd::Concrete, Option<oneshot::Sender<D>>),
         Sub(event::ConcreteType, Option<mpsc::UnboundedSender<D>>),
+        Heartbeat,
     }
 
     struct Worker {
-        stream: TcpStream,
+        stream: Writer,
+        frames: mpsc::Receiver<Vec<u8>>,
+        reader: JoinHandle<()>,
         cmd_rx: mpsc::UnboundedReceiver<(cmd::Concrete, Option<oneshot::Sender<D>>)>,
         sub_rx: mpsc::UnboundedReceiver<(event::ConcreteType, Option<mpsc::UnboundedSender<D>>)>,
+        heartbeat: Weak<Heartbeat>,
     }
 
     type SendCmd = mpsc::UnboundedSender<(cmd::Concrete, Option<oneshot::Sender<D>>)>;
     type SendEv = mpsc::UnboundedSender<(event::ConcreteType, Option<mpsc::UnboundedSender<D>>)>;
     impl Worker {
-        pub fn new(stream: TcpStream) -> (Self, SendCmd, SendEv) {
+        pub fn new(
+            read: Reader,
+            stream: Writer,
+            max_frame: Arc<AtomicUsize>,
+            heartbeat: Weak<Heartbeat>,
+        ) -> (Self, SendCmd, SendEv) {
             let (cmd_tx, cmd_rx) = mpsc::unbounded_channel();
             let (sub_tx, sub_rx) = mpsc::unbounded_channel();
+            let (frame_tx, frames) = mpsc::channel(1);
+            let reader = tokio::spawn(async move {
+                if let Err(e) = read_frames(read, max_frame, frame_tx).await {
+                    log::error!("tcp: read error: {e}");
+                }
+            });
             let s = Self {
                 stream,
+                frames,
+                reader,
                 cmd_rx,
                 sub_rx,
+                heartbeat,
             };
             (s, cmd_tx, sub_tx)
         }
         pub async fn worker(mut self) -> Result<()> {
-            const HEADER: usize = std::mem::size_of::<u32>();
-
             let mut next_id = super::super::ID_START;
             let bin = bincode::options();
-            let mut buf = vec![0; 512];
-            let mut len = 0; // no. of bytes read for the current command we're attempting to parse
-            let mut maybe_cmd_len = None;
             let mut cmds: HashMap<u32, oneshot::Sender<D>> = HashMap::new();
             let mut subs: HashMap<u32, mpsc::UnboundedSender<D>> = HashMap::new();
             let mut sub_ids: HashMap<event::ConcreteType, u32> = HashMap::new();
+            let mut ticks = None;
             loop {
+                match self.heartbeat.upgrade() {
+                    Some(heartbeat) => heartbeat.ticks(&mut ticks),
+                    None => ticks = None,
+                }
+
                 let action = tokio::select! {
-                    Ok(n) = self.stream.read(&mut buf[len..( HEADER + maybe_cmd_len.unwrap_or(0) )]) => Action::ServerMessage(n),
+                    frame = self.frames.recv() => match frame {
+                        Some(f) => Action::ServerMessage(f),
+                        None => {
+                            log::error!("Server disconnected!");
+                            anyhow::bail!("Server disconnected!");
+                        }
+                    },
                     Some(cmd) = self.cmd_rx.recv() => Action::Cmd(cmd.0, cmd.1),
                     Some(sub) = self.sub_rx.recv() => Action::Sub(sub.0, sub.1),
-                    // _ = tokio::time::sleep(Duration::from_secs(5)) => {
-                    //     self.check_disconnect().await;
-                    //     continue;
-                    // }
+                    Some(_) = async { Some(ticks.as_mut()?.tick().await) } => Action::Heartbeat,
                 };
 
                 match action {
-                    // adapted from server/src/transports/tcp.rs
-                    Action::ServerMessage(n) => {
-                        if n == 0 {
-                            log::debug!("tcp: received 0 sized msg, investigating disconnect");
-                            // give the socket some time to fully realize disconnect
-                            tokio::time::sleep(Duration::from_millis(100)).await;
-                            if self.check_disconnect().await {
-                                anyhow::bail!("Server disconnected!");
-                            }
-                        }
-
-                        len += n;
-                        if len < HEADER {
-                            continue;
-                        }
-                        let cmd_len = match maybe_cmd_len {
-                            Some(n) => n,
-                            None => {
-                                let cmd = u32::from_be_bytes((&buf[..HEADER]).try_into().unwrap())
-                                    as usize;
-                                // buf.resize(HEADER + cmd, 0);
-                                maybe_cmd_len = Some(cmd);
-                                // log::debug!("header received, cmdlen: {cmd}");
-                                cmd
-                            }
-                        };
-                        if len < HEADER + cmd_len {
-                            continue;
-                        }
-
-                        let mut c = Cursor::new(buf[HEADER..len].to_vec()); // clone :(
+                    Action::ServerMessage(frame) => {
+                        let mut c = Cursor::new(frame);
                         let id: u32 = bincode::Options::deserialize_from(bin, &mut c)?;
                         if let Some(tx) = subs.get(&id) {
                             tx.send(bincode::Deserializer::with_reader(c, bin))?;
                         } else if let Some(tx) = cmds.remove(&id) {
                             if tx.send(bincode::Deserializer::with_reader(c, bin)).is_err() {
-                                log::error!("cmd receiver dropped");
+                                log::debug!("cmd {id} was cancelled before its reply arrived");
                             }
                         } else {
-                            log::error!("server sent invalid id");
+                            // commands without a return value don't wait for a reply,
+                            // but the server still reports it if they fail
+                            let mut de = bincode::Deserializer::with_reader(c, bin);
+                            match Result::<(), cmd::Error>::deserialize(&mut de) {
+                                Ok(Err(e)) => log::error!("command {id} failed: {e}"),
+                                _ => log::error!("server sent invalid id"),
+                            }
                         }
-
-                        len = 0;
-                        maybe_cmd_len = None;
                     }
                     Action::Cmd(cmd, maybe_tx) => {
                         let id = next_id;
                         next_id += 1;
                         if let Some(tx) = maybe_tx {
+                            // forget the commands that timed out or were cancelled
+                            cmds.retain(|_, tx| !tx.is_closed());
                             cmds.insert(id, tx);
                         }
                         self.send((id, cmd)).await?;
@@ -302,6 +546,7 @@ pub mod tcp_async {
                         let id = next_id;
                         next_id += 1;
                         subs.insert(id, tx);
+                        sub_ids.insert(ev.clone(), id);
                         let cmd: cmd::Concrete = cmd::Subscribe(ev).into();
                         self.send((id, cmd)).await?;
                     }
@@ -315,23 +560,13 @@ pub mod tcp_async {
                         let cmd: cmd::Concrete = cmd::Unsubscribe(ev).into();
                         self.send((id, cmd)).await?;
                     }
+                    Action::Heartbeat => {
+                        let cmd: cmd::Concrete = cmd::Heartbeat.into();
+                        self.send((0, cmd)).await?;
+                    }
                 }
             }
         }
-        async fn check_disconnect(&mut self) -> bool {
-            let r = self
-                .stream
-                .ready(Interest::READABLE | Interest::WRITABLE)
-                .await;
-            if r.as_ref()
-                .map_or(true, |r| r.is_read_closed() || r.is_write_closed())
-            {
-                log::error!("Server disconnected!");
-                log::debug!("{r:#?}");
-                return true;
-            }
-            false
-        }
         async fn send(&mut self, data: impl Serialize) -> Result<()> {
             let buf = bincode::Options::serialize(bincode::options(), &data)?;
             log::debug!("{buf:?}");
@@ -339,53 +574,130 @@ pub mod tcp_async {
                 .write_all(&(buf.len() as u32).to_be_bytes())
                 .await?;
             self.stream.write_all(&buf).await?;
+            self.stream.flush().await?;
             Ok(())
         }
     }
 
+    impl Drop for Worker {
+        fn drop(&mut self) {
+            self.reader.abort();
+        }
+    }
+
     pub struct TcpAsync {
         _handle: Option<JoinHandle<Result<()>>>,
+        max_frame: Arc<AtomicUsize>,
         cmd_tx: mpsc::UnboundedSender<(cmd::Concrete, Option<oneshot::Sender<D>>)>,
         sub_tx: mpsc::UnboundedSender<(event::ConcreteType, Option<mpsc::UnboundedSender<D>>)>,
+        timeout: Option<Duration>,
+        ack: bool,
+        heartbeat: Arc<Heartbeat>,
     }
 
     impl TcpAsync {
         pub async fn connect(addr: impl ToSocketAddrs) -> Result<Self> {
+            let (read, write) = TcpStream::connect(addr).await?.into_split();
+            Ok(Self::start(Box::new(read), Box::new(write)))
+        }
+
+        /// Connects over TLS, checking the server's certificate with `tls`
+        #[cfg(feature = "tls")]
+        pub async fn connect_tls(addr: &str, tls: &crate::transports::tls::Tls) -> Result<Self> {
+            let connector = tokio_rustls::TlsConnector::from(Arc::new(tls.client_config()));
             let stream = TcpStream::connect(addr).await?;
-            let (worker, cmd_tx, sub_tx) = Worker::new(stream);
+            let stream = connector.connect(tls.server_name(addr)?, stream).await?;
+            let (read, write) = io::split(stream);
+            Ok(Self::start(Box::new(read), Box::new(write)))
+        }
+
+        fn start(read: Reader, write: Writer) -> Self {
+            let max_frame = Arc::new(AtomicUsize::new(cmd::MAX_FRAME));
+            let heartbeat = Arc::new(Heartbeat::new());
+            let (worker, cmd_tx, sub_tx) =
+                Worker::new(read, write, max_frame.clone(), Arc::downgrade(&heartbeat));
             let handle = Some(tokio::spawn(async {
                 let r = worker.worker().await;
                 log::debug!("worker dropped??");
                 r
             }));
 
-            Ok(Self {
+            Self {
                 _handle: handle,
+                max_frame,
                 cmd_tx,
                 sub_tx,
-            })
+                timeout: None,
+                ack: false,
+                heartbeat,
+            }
         }
-    }
 
-    #[async_trait]
-    impl TransportAsync for TcpAsync {
-        async fn cmd<C>(&self, cmd: C) -> Result<C::Return>
+        /// Sets the default timeout for commands, see [`TransportAsync::cmd`]
+        pub fn with_timeout(mut self, timeout: Duration) -> Self {
+            self.timeout = Some(timeout);
+            self
+        }
+
+        /// Makes the server acknowledge every command, so the ones without a return value also report failures.
+        /// See [`cmd::Ack`].
+        pub fn with_ack(mut self) -> Self {
+            self.ack = true;
+            self
+        }
+
+        /// Sets the largest reply accepted, [`cmd::MAX_FRAME`] by default.
+        /// Larger ones are skipped, and fail the command with a [`cmd::ErrorCode::Decode`] error.
+        pub fn with_max_frame(self, max_frame: usize) -> Self {
+            self.max_frame.store(max_frame, Ordering::Relaxed);
+            self
+        }
+
+        /// Sets how often heartbeats are sent while the robot is driving,
+        /// [`HEARTBEAT_INTERVAL`](crate::transports::HEARTBEAT_INTERVAL) by default, `None` turns them off.
+        /// See [`cmd::Heartbeat`].
+        pub fn with_heartbeat(self, interval: Option<Duration>) -> Self {
+            self.heartbeat.set_interval(interval);
+            self
+        }
+
+        async fn cmd_opt<C>(&self, cmd: C, timeout: Option<Duration>) -> Result<C::Return>
         where
             C: Command,
         {
-            let concr: cmd::Concrete = cmd.into();
-            if has_return::<C>() {
+            let (concr, replies) = crate::transports::prepare(cmd, self.ack);
+            self.heartbeat.update(&concr);
+            if replies {
                 let (tx, rx) = oneshot::channel();
                 self.cmd_tx.send((concr, Some(tx)))?;
-                let mut de = rx.await?;
-                Ok(C::Return::deserialize(&mut de)?)
+                let mut de = crate::transports::wait_reply(rx, timeout)
+                    .await?
+                    .map_err(|_| anyhow::anyhow!("Disconnected from the server"))?;
+                Ok(Result::<C::Return, cmd::Error>::deserialize(&mut de)??)
             } else {
                 self.cmd_tx.send((concr, None))?;
-                unsafe { std::mem::zeroed() }
+                crate::transports::no_reply()
             }
         }
     }
 
+    #[async_trait]
+    impl TransportAsync for TcpAsync {
+        async fn cmd<C>(&self, cmd: C) -> Result<C::Return>
+        where
+            C: Command,
+        {
+            self.cmd_opt(cmd, self.timeout).await
+        }
+
+        async fn cmd_timeout<C>(&self, cmd: C, timeout: Duration) -> Result<C::Return>
+        where
+            C: Command,
+        {
+            self.cmd_opt(cmd, Some(timeout)).await
+        }
+    }
+
     #[async_trait]
     impl SubscribableAsync for TcpAsync {
         async fn subscribe<E: Event>(&self, ev: E) -> Result<broadcast::Receiver<E::Item>> {
@@ -395,7 +707,13 @@ pub mod tcp_async {
             let (client_tx, client_rx) = broadcast::channel(128);
             tokio::spawn(async move {
                 while let Some(mut de) = worker_rx.recv().await {
-                    let item = E::Item::deserialize(&mut de)?;
+                    let item = match Result::<E::Item, cmd::Error>::deserialize(&mut de)? {
+                        Ok(item) => item,
+                        Err(e) => {
+                            log::error!("subscription failed: {e}");
+                            continue;
+                        }
+                    };
                     if client_tx.send(item).is_err() {
                         log::error!("no receiver for active subscription");
                     };
@@ -0,0 +1,169 @@
+use anyhow::{Context, Result};
+use rustls::{ClientConfig, OwnedTrustAnchor, RootCertStore};
+#[cfg(feature = "tcp")]
+use rustls::{ClientConnection, ServerName};
+use std::path::Path;
+#[cfg(feature = "tcp")]
+use std::{
+    io::{self, Read, Write},
+    net::TcpStream,
+    sync::{Arc, Mutex},
+};
+
+/// The certificates a client trusts when connecting over TLS, see the server's `[tls]` config
+#[derive(Clone)]
+pub struct Tls {
+    roots: RootCertStore,
+    server_name: Option<String>,
+}
+
+impl Default for Tls {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Tls {
+    /// Trusts the usual public certificate authorities
+    pub fn new() -> Self {
+        let mut roots = RootCertStore::empty();
+        roots.add_trust_anchors(webpki_roots::TLS_SERVER_ROOTS.iter().map(|ta| {
+            OwnedTrustAnchor::from_subject_spki_name_constraints(
+                ta.subject,
+                ta.spki,
+                ta.name_constraints,
+            )
+        }));
+        Self {
+            roots,
+            server_name: None,
+        }
+    }
+
+    /// Only trusts the certificates in `pem`, like a private CA or the server's self-signed certificate
+    pub fn with_ca(pem: &[u8]) -> Result<Self> {
+        let certs = rustls_pemfile::certs(&mut &pem[..])?;
+        if certs.is_empty() {
+            anyhow::bail!("No certificates in the CA");
+        }
+
+        let mut roots = RootCertStore::empty();
+        for cert in certs {
+            roots.add(&rustls::Certificate(cert))?;
+        }
+        Ok(Self {
+            roots,
+            server_name: None,
+        })
+    }
+
+    /// Same as [`Tls::with_ca`], with the certificates read from a file
+    pub fn with_ca_file(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let pem = std::fs::read(path).with_context(|| path.display().to_string())?;
+        Self::with_ca(&pem)
+    }
+
+    /// Sets the name the server's certificate has to be valid for, the host it's connected to by default
+    pub fn with_server_name(mut self, name: impl Into<String>) -> Self {
+        self.server_name = Some(name.into());
+        self
+    }
+
+    pub(crate) fn client_config(&self) -> ClientConfig {
+        ClientConfig::builder()
+            .with_safe_defaults()
+            .with_root_certificates(self.roots.clone())
+            .with_no_client_auth()
+    }
+
+    /// The name to check the certificate of the server at `addr` against
+    #[cfg(feature = "tcp")]
+    pub(crate) fn server_name(&self, addr: &str) -> Result<ServerName> {
+        let name = match &self.server_name {
+            Some(name) => &name[..],
+            None => host(addr),
+        };
+        ServerName::try_from(name).with_context(|| format!("Invalid server name: {name}"))
+    }
+}
+
+/// `localhost:1110` -> `localhost`, `[::1]:1110` -> `::1`
+#[cfg(feature = "tcp")]
+fn host(addr: &str) -> &str {
+    let host = match addr.rsplit_once(':') {
+        Some((host, port)) if port.parse::<u16>().is_ok() => host,
+        _ => addr,
+    };
+    host.trim_start_matches('[').trim_end_matches(']')
+}
+
+/// A TLS connection over a blocking socket, which can be read by one thread while others write to it.
+/// The connection is only locked while decrypting or encrypting, not while waiting for data.
+#[cfg(feature = "tcp")]
+pub(crate) struct TlsStream {
+    conn: Mutex<ClientConnection>,
+    socket: TcpStream,
+}
+
+#[cfg(feature = "tcp")]
+impl TlsStream {
+    pub fn connect(socket: TcpStream, tls: &Tls, addr: &str) -> Result<Self> {
+        let mut conn =
+            ClientConnection::new(Arc::new(tls.client_config()), tls.server_name(addr)?)?;
+        while conn.is_handshaking() {
+            conn.complete_io(&mut &socket)?;
+        }
+        Ok(Self {
+            conn: conn.into(),
+            socket,
+        })
+    }
+}
+
+#[cfg(feature = "tcp")]
+impl Read for &TlsStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut raw = [0; 4096];
+        loop {
+            match self.conn.lock().unwrap().reader().read(buf) {
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => {}
+                res => return res,
+            }
+
+            // nothing decrypted yet, wait for more
+            let n = (&self.socket).read(&mut raw)?;
+
+            let mut conn = self.conn.lock().unwrap();
+            let mut data = &raw[..n];
+            loop {
+                // reading nothing tells the connection the socket was closed
+                conn.read_tls(&mut data)?;
+                conn.process_new_packets()
+                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+                if data.is_empty() {
+                    break;
+                }
+            }
+            while conn.wants_write() {
+                conn.write_tls(&mut &self.socket)?;
+            }
+        }
+    }
+}
+
+#[cfg(feature = "tcp")]
+impl Write for &TlsStream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut conn = self.conn.lock().unwrap();
+        let n = conn.writer().write(buf)?;
+        while conn.wants_write() {
+            conn.write_tls(&mut &self.socket)?;
+        }
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
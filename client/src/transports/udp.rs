@@ -1,11 +1,11 @@
 use crate::Transport;
 use anyhow::Result;
 use roblib::{
-    cmd::{self, has_return, Command},
+    cmd::{self, Command},
     event::Event,
 };
-use serde::Deserialize;
-use std::{collections::HashMap, io::Cursor, sync::Arc};
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, io::Cursor, sync::Arc, time::Duration};
 
 use super::Subscribable;
 
@@ -23,6 +23,10 @@ pub struct Udp {
 
     sock: std::net::UdpSocket,
     id: std::sync::Mutex<u32>,
+    timeout: Option<Duration>,
+    ack: bool,
+    /// The session token sent after every command, once authenticated
+    token: std::sync::RwLock<Option<String>>,
 }
 
 impl Udp {
@@ -39,17 +43,41 @@ impl Udp {
         });
 
         let i2 = inner.clone();
-        std::thread::spawn(move || Self::recieve(i2, sock2));
+        std::thread::spawn(move || {
+            let res = Self::recieve(&i2, sock2);
+            // wake up everyone waiting for a reply
+            i2.handlers.lock().unwrap().clear();
+            res
+        });
 
         Ok(Self {
             id: super::ID_START.into(),
             inner,
             sock,
+            timeout: None,
+            ack: false,
+            token: None.into(),
         })
     }
 
-    fn recieve(inner: Arc<UdpInner>, sock: std::net::UdpSocket) -> Result<()> {
-        let mut buf = [0; 512];
+    /// Sets the default timeout for commands, see [`Transport::cmd`].
+    ///
+    /// Datagrams can get lost, so setting one is recommended.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Makes the server acknowledge every command, so the ones without a return value also report failures.
+    /// See [`cmd::Ack`].
+    pub fn with_ack(mut self) -> Self {
+        self.ack = true;
+        self
+    }
+
+    fn recieve(inner: &UdpInner, sock: std::net::UdpSocket) -> Result<()> {
+        // big enough for any datagram
+        let mut buf = vec![0; 1 << 16];
         loop {
             let running = inner.running.read().unwrap();
             if !*running {
@@ -61,42 +89,94 @@ impl Udp {
             let buf = &buf[..len];
 
             let mut curs = Cursor::new(buf);
-            let id: u32 = bincode::Options::deserialize_from(bincode::options(), &mut curs)?;
+            let id: u32 = match bincode::Options::deserialize_from(bincode::options(), &mut curs) {
+                Ok(id) => id,
+                Err(e) => {
+                    log::error!("received invalid datagram: {e}");
+                    continue;
+                }
+            };
+            let pos = curs.position() as usize;
+            let rest = &curs.into_inner()[pos..];
+            let des = bincode::Deserializer::from_slice(rest, bincode::options());
+
             if let Some(h) = inner.handlers.lock().unwrap().get_mut(&id) {
-                let pos = curs.position() as usize;
-                let rest = &curs.into_inner()[pos..];
-                h(bincode::Deserializer::from_slice(rest, bincode::options()))?;
+                if let Err(e) = h(des) {
+                    log::error!("failed to decode reply {id}: {e}");
+                }
+            } else {
+                // commands without a return value don't wait for a reply,
+                // but the server still reports it if they fail
+                let mut des = des;
+                if let Ok(Err(e)) = Result::<(), cmd::Error>::deserialize(&mut des) {
+                    log::error!("command {id} failed: {e}");
+                }
             }
         }
         Ok(())
     }
 
-    fn cmd_id<C>(&self, cmd: C, id: u32) -> Result<C::Return>
+    fn cmd_id<C>(&self, cmd: C, id: u32, timeout: Option<Duration>, ack: bool) -> Result<C::Return>
     where
         C: Command,
     {
-        let concrete: cmd::Concrete = cmd.into();
-        self.sock.send(&bincode::Options::serialize(
-            bincode::options(),
-            &(id, concrete),
-        )?)?;
+        let (concrete, replies) = super::prepare(cmd, ack);
 
-        Ok(if has_return::<C>() {
+        // register the handler before sending, so the reply can't arrive without one
+        let rx = if replies {
             let (tx, rx) = std::sync::mpsc::sync_channel(1);
 
             let a: Handler = Box::new(move |mut des: D| {
-                let r = C::Return::deserialize(&mut des)?;
-                tx.send(r).unwrap();
+                let r = Result::<C::Return, cmd::Error>::deserialize(&mut des)?;
+                // the receiver is gone if the command timed out
+                let _ = tx.send(r);
                 Ok::<(), anyhow::Error>(())
             });
 
             self.inner.handlers.lock().unwrap().insert(id, a);
 
-            rx.recv()?
+            Some(rx)
         } else {
-            unsafe { std::mem::zeroed() }
+            None
+        };
+
+        self.send(&(id, concrete))?;
+
+        Ok(if let Some(rx) = rx {
+            super::recv_reply(&rx, timeout)??
+        } else {
+            super::no_reply()?
         })
     }
+
+    /// Sends a datagram, with the session token appended if there's one,
+    /// the server can't tell who's authenticated from the address alone
+    fn send(&self, msg: &impl Serialize) -> Result<()> {
+        let mut datagram = bincode::Options::serialize(bincode::options(), msg)?;
+        if let Some(token) = &*self.token.read().unwrap() {
+            datagram.extend_from_slice(token.as_bytes());
+        }
+        self.sock.send(&datagram)?;
+        Ok(())
+    }
+
+    fn next_id(&self) -> u32 {
+        let mut id_handle = self.id.lock().unwrap();
+        let id = *id_handle;
+        *id_handle = id + 1;
+        id
+    }
+
+    /// Runs a command that doesn't need its handler after the reply (or the timeout)
+    fn cmd_once<C>(&self, cmd: C, timeout: Option<Duration>) -> Result<C::Return>
+    where
+        C: Command,
+    {
+        let id = self.next_id();
+        let res = self.cmd_id(cmd, id, timeout, self.ack);
+        self.inner.handlers.lock().unwrap().remove(&id);
+        res
+    }
 }
 
 impl Transport for Udp {
@@ -104,14 +184,22 @@ impl Transport for Udp {
     where
         C: Command,
     {
-        let mut id_handle = self.id.lock().unwrap();
-        let id = *id_handle;
-        *id_handle = id + 1;
-        drop(id_handle);
+        self.cmd_once(cmd, self.timeout)
+    }
 
-        let res = self.cmd_id(cmd, id);
-        self.inner.handlers.lock().unwrap().remove(&id);
-        res
+    fn cmd_timeout<C>(&self, cmd: C, timeout: Duration) -> Result<C::Return>
+    where
+        C: Command,
+    {
+        self.cmd_once(cmd, Some(timeout))
+    }
+
+    /// The session token is kept and sent with every later datagram
+    fn authenticate(&self, secret: &str) -> Result<()> {
+        let challenge = self.cmd(cmd::GetChallenge)?;
+        let token = self.cmd(cmd::Authenticate::new(secret, challenge))?;
+        *self.token.write().unwrap() = Some(token);
+        Ok(())
     }
 }
 
@@ -121,17 +209,22 @@ impl Subscribable for Udp {
         E: Event,
         F: (FnMut(E::Item) -> Result<()>) + Send + Sync + 'static,
     {
-        let mut id_handle = self.id.lock().unwrap();
-        let id = *id_handle;
-        *id_handle = id + 1;
-        drop(id_handle);
+        let id = self.next_id();
 
         self.inner.handlers.lock().unwrap().insert(
             id,
-            Box::new(move |mut des| handler(E::Item::deserialize(&mut des)?)),
+            Box::new(
+                move |mut des| match Result::<E::Item, cmd::Error>::deserialize(&mut des)? {
+                    Ok(item) => handler(item),
+                    Err(e) => {
+                        log::error!("subscription {id} failed: {e}");
+                        Ok(())
+                    }
+                },
+            ),
         );
 
-        self.cmd_id(cmd::Subscribe(ev.into()), id)?;
+        self.cmd_id(cmd::Subscribe(ev.into()), id, self.timeout, false)?;
 
         Ok(())
     }
@@ -140,8 +233,7 @@ impl Subscribable for Udp {
         let ev = ev.into();
         let cmd: cmd::Concrete = cmd::Unsubscribe(ev.clone()).into();
 
-        self.sock
-            .send(&bincode::Options::serialize(bincode::options(), &cmd)?)?;
+        self.send(&cmd)?;
 
         let id = self.inner.events.lock().unwrap().remove(&ev).unwrap();
 
@@ -3,12 +3,17 @@ use anyhow::Result;
 use async_trait::async_trait;
 use futures::{executor::block_on, SinkExt, TryStreamExt};
 use roblib::{
-    cmd::{self, has_return},
+    cmd,
     event::{ConcreteType, Event},
-    text_format,
+    json,
+};
+use serde::{de::DeserializeOwned, Deserialize};
+use std::{
+    collections::HashMap,
+    io::Cursor,
+    sync::{Arc, Weak},
+    time::Duration,
 };
-use serde::Deserialize;
-use std::{collections::HashMap, io::Cursor, sync::Arc};
 use tokio::{
     net::TcpStream,
     sync::{
@@ -18,12 +23,36 @@ use tokio::{
     },
     task::JoinHandle,
 };
-use tokio_tungstenite::{connect_async, tungstenite::Message, MaybeTlsStream, WebSocketStream};
+use tokio_tungstenite::{
+    tungstenite::{client::IntoClientRequest, http::HeaderValue, Message},
+    MaybeTlsStream, WebSocketStream,
+};
 
 type WsConn = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+#[cfg(feature = "tls")]
+use super::tls::Tls;
+/// Only ever `None` without TLS support
+#[cfg(not(feature = "tls"))]
+enum Tls {}
 type D =
     bincode::Deserializer<bincode::de::read::IoReader<Cursor<Vec<u8>>>, bincode::DefaultOptions>;
-type Handler = mpsc::Sender<D>;
+type Handler = mpsc::Sender<Reply>;
+
+/// A reply from the server, only its handler knows what type it is
+enum Reply {
+    Bincode(D),
+    Json(serde_json::Value),
+}
+
+impl Reply {
+    fn decode<T: DeserializeOwned>(self) -> Result<Result<T, cmd::Error>> {
+        Ok(match self {
+            Reply::Bincode(mut de) => Result::<T, cmd::Error>::deserialize(&mut de)?,
+            Reply::Json(v) => serde_json::from_value::<json::Reply<T>>(v)?.into_result(),
+        })
+    }
+}
 
 #[derive(Default)]
 struct WsInner {
@@ -35,57 +64,141 @@ pub struct Ws {
     handle: Option<JoinHandle<Result<()>>>,
     id: Mutex<u32>,
     sender: UnboundedSender<Message>,
+    timeout: Option<Duration>,
+    ack: bool,
+    json: bool,
+    heartbeat: Arc<super::Heartbeat>,
 }
 
 enum Action {
     Recv(Message),
     Send(Message),
+    Heartbeat,
 }
 impl Ws {
     pub async fn connect(addr: &str) -> Result<Self> {
-        let url = format!("ws://{addr}/ws");
-        let (ws, _) = connect_async(url).await?;
+        Self::connect_with(addr, false, None).await
+    }
+
+    /// Connects using the json encoding instead of bincode, see [`roblib::json`]
+    pub async fn connect_json(addr: &str) -> Result<Self> {
+        Self::connect_with(addr, true, None).await
+    }
+
+    /// Connects with `wss://`, checking the server's certificate with `tls`
+    #[cfg(feature = "tls")]
+    pub async fn connect_tls(addr: &str, tls: &super::tls::Tls) -> Result<Self> {
+        Self::connect_with(addr, false, Some(tls)).await
+    }
+
+    /// Same as [`Ws::connect_tls`], with the json encoding
+    #[cfg(feature = "tls")]
+    pub async fn connect_json_tls(addr: &str, tls: &super::tls::Tls) -> Result<Self> {
+        Self::connect_with(addr, true, Some(tls)).await
+    }
+
+    async fn connect_with(addr: &str, json: bool, tls: Option<&Tls>) -> Result<Self> {
+        let scheme = if tls.is_some() { "wss" } else { "ws" };
+        let mut req = format!("{scheme}://{addr}/ws").into_client_request()?;
+        if json {
+            req.headers_mut().insert(
+                "Sec-WebSocket-Protocol",
+                HeaderValue::from_static(json::PROTOCOL),
+            );
+        }
+
+        #[cfg(feature = "tls")]
+        let (ws, _) = {
+            let connector =
+                tls.map(|tls| tokio_tungstenite::Connector::Rustls(Arc::new(tls.client_config())));
+            tokio_tungstenite::connect_async_tls_with_config(req, None, false, connector).await?
+        };
+        #[cfg(not(feature = "tls"))]
+        let (ws, _) = tokio_tungstenite::connect_async(req).await?;
+
         let inner = Arc::new(WsInner::default());
+        let heartbeat = Arc::new(super::Heartbeat::new());
 
         let (tx, rx) = unbounded_channel();
-        let handle = tokio::spawn(Self::worker(ws, rx, inner.clone()));
+        let handle = tokio::spawn(Self::worker(
+            ws,
+            rx,
+            inner.clone(),
+            Arc::downgrade(&heartbeat),
+            json,
+        ));
         Ok(Self {
             inner,
             handle: Some(handle),
             id: super::ID_START.into(),
             sender: tx,
+            timeout: None,
+            ack: false,
+            json,
+            heartbeat,
         })
     }
 
+    /// Sets the default timeout for commands, see [`TransportAsync::cmd`]
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Makes the server acknowledge every command, so the ones without a return value also report failures.
+    /// See [`cmd::Ack`].
+    pub fn with_ack(mut self) -> Self {
+        self.ack = true;
+        self
+    }
+
+    /// Sets how often heartbeats are sent while the robot is driving, [`super::HEARTBEAT_INTERVAL`] by default,
+    /// `None` turns them off. See [`cmd::Heartbeat`].
+    pub fn with_heartbeat(self, interval: Option<Duration>) -> Self {
+        self.heartbeat.set_interval(interval);
+        self
+    }
+
     async fn worker(
         mut ws: WsConn,
         mut rx: UnboundedReceiver<Message>,
         inner: Arc<WsInner>,
+        heartbeat: Weak<super::Heartbeat>,
+        json: bool,
     ) -> Result<()> {
         let bin = bincode::options();
+        let mut ticks = None;
         loop {
+            match heartbeat.upgrade() {
+                Some(heartbeat) => heartbeat.ticks(&mut ticks),
+                None => ticks = None,
+            }
+
             let action = tokio::select! {
-                Ok(Some(msg)) = ws.try_next() => Action::Recv(msg),
-                Some(msg) = rx.recv() => Action::Send(msg)
+                msg = ws.try_next() => match msg? {
+                    Some(msg) => Action::Recv(msg),
+                    // the connection is closed, also after dropping the client
+                    None => return Ok(()),
+                },
+                Some(msg) = rx.recv() => Action::Send(msg),
+                Some(_) = async { Some(ticks.as_mut()?.tick().await) } => Action::Heartbeat,
             };
             match action {
                 Action::Recv(msg) => match msg {
                     Message::Text(s) => {
-                        let _de = text_format::de::new_deserializer(&s);
-                        todo!()
+                        let v: serde_json::Value = serde_json::from_str(&s)?;
+                        let Some(id) = v.get("id").and_then(|id| id.as_u64()) else {
+                            log::error!("received json without an id: {s}");
+                            continue;
+                        };
+                        Self::handle(&inner, id as u32, Reply::Json(v)).await;
                     }
                     Message::Binary(b) => {
                         let mut c = Cursor::new(b);
                         let id: u32 = bincode::Options::deserialize_from(bin, &mut c)?;
 
-                        let mut handlers = inner.handlers.lock().await;
-                        let Some(handler) = handlers.get_mut(&id) else {
-                            return Err(anyhow::Error::msg("received response for unknown id"));
-                        };
-
-                        handler
-                            .send(bincode::Deserializer::with_reader(c, bin))
-                            .await?;
+                        let de = bincode::Deserializer::with_reader(c, bin);
+                        Self::handle(&inner, id, Reply::Bincode(de)).await;
                     }
                     Message::Ping(p) => ws.send(Message::Pong(p)).await?,
                     Message::Close(close) => {
@@ -99,10 +212,31 @@ impl Ws {
                 Action::Send(msg) => {
                     ws.send(msg).await?;
                 }
+                Action::Heartbeat => {
+                    ws.send(message(json, 0, cmd::Heartbeat.into())?).await?;
+                }
             }
         }
     }
 
+    async fn handle(inner: &WsInner, id: u32, reply: Reply) {
+        let mut handlers = inner.handlers.lock().await;
+        let Some(handler) = handlers.get_mut(&id) else {
+            // commands without a return value don't wait for a reply,
+            // but the server still reports it if they fail
+            match reply.decode::<()>() {
+                Ok(Err(e)) => log::error!("command {id} failed: {e}"),
+                _ => log::error!("received response for unknown id: {id}"),
+            }
+            return;
+        };
+
+        if handler.send(reply).await.is_err() {
+            log::debug!("cmd {id} was cancelled before its reply arrived");
+            handlers.remove(&id);
+        }
+    }
+
     async fn incr_id(&self) -> u32 {
         let mut id_handle = self.id.lock().await;
         let id = *id_handle;
@@ -110,28 +244,62 @@ impl Ws {
         id
     }
 
-    async fn send<C: cmd::Command>(&self, id: u32, cmd: C) -> Result<C::Return> {
-        let cmd: cmd::Concrete = cmd.into();
-        let data = bincode::Options::serialize(bincode::options(), &(id, cmd))?;
-        self.sender.send(Message::Binary(data))?;
+    async fn send<C: cmd::Command>(
+        &self,
+        id: u32,
+        cmd: C,
+        timeout: Option<Duration>,
+        ack: bool,
+    ) -> Result<C::Return> {
+        let (cmd, replies) = super::prepare(cmd, ack);
+        self.heartbeat.update(&cmd);
+        let msg = message(self.json, id, cmd)?;
 
-        if has_return::<C>() {
-            let (tx, mut rx) = mpsc::channel(1);
-            self.inner.handlers.lock().await.insert(id, tx);
-            let mut de = rx.recv().await.unwrap();
-            let re = C::Return::deserialize(&mut de)?;
-            Ok(re)
+        // register the handler before sending, so the reply can't arrive without one
+        let rx = if replies {
+            let (tx, rx) = mpsc::channel(1);
+            let mut handlers = self.inner.handlers.lock().await;
+            // forget the commands that timed out or were cancelled
+            handlers.retain(|_, h| !h.is_closed());
+            handlers.insert(id, tx);
+            Some(rx)
         } else {
-            unsafe { std::mem::zeroed() }
+            None
+        };
+
+        self.sender.send(msg)?;
+
+        if let Some(mut rx) = rx {
+            let res = super::wait_reply(rx.recv(), timeout).await;
+            self.inner.handlers.lock().await.remove(&id);
+
+            let reply = res?.ok_or_else(|| anyhow::anyhow!("Disconnected from the server"))?;
+            Ok(reply.decode::<C::Return>()??)
+        } else {
+            super::no_reply()
         }
     }
 }
 
+/// Encodes a command in the format of the connection
+fn message(json: bool, id: u32, cmd: cmd::Concrete) -> Result<Message> {
+    Ok(if json {
+        Message::Text(serde_json::to_string(&json::Request { id, cmd })?)
+    } else {
+        Message::Binary(bincode::Options::serialize(bincode::options(), &(id, cmd))?)
+    })
+}
+
 #[async_trait]
 impl TransportAsync for Ws {
     async fn cmd<C: cmd::Command>(&self, cmd: C) -> Result<C::Return> {
         let id = self.incr_id().await;
-        self.send(id, cmd).await
+        self.send(id, cmd, self.timeout, self.ack).await
+    }
+
+    async fn cmd_timeout<C: cmd::Command>(&self, cmd: C, timeout: Duration) -> Result<C::Return> {
+        let id = self.incr_id().await;
+        self.send(id, cmd, Some(timeout), self.ack).await
     }
 }
 
@@ -144,12 +312,19 @@ impl SubscribableAsync for Ws {
         let (tx, mut worker_rx) = mpsc::channel(1);
         self.inner.handlers.lock().await.insert(id, tx);
         self.inner.events.lock().await.insert(ev.clone(), id);
-        self.send(id, cmd::Subscribe(ev)).await?;
+        self.send(id, cmd::Subscribe(ev), self.timeout, false)
+            .await?;
 
         let (client_tx, client_rx) = broadcast::channel(128);
         tokio::spawn(async move {
-            while let Some(mut de) = worker_rx.recv().await {
-                let item = E::Item::deserialize(&mut de)?;
+            while let Some(reply) = worker_rx.recv().await {
+                let item = match reply.decode::<E::Item>()? {
+                    Ok(item) => item,
+                    Err(e) => {
+                        log::error!("subscription failed: {e}");
+                        continue;
+                    }
+                };
                 if client_tx.send(item).is_err() {
                     log::error!("no receiver for active subscription");
                 };
@@ -170,7 +345,8 @@ impl SubscribableAsync for Ws {
         match lock.entry(ev.clone()) {
             std::collections::hash_map::Entry::Occupied(v) => {
                 let id = v.remove();
-                self.send(id, cmd::Unsubscribe(ev)).await?;
+                self.send(id, cmd::Unsubscribe(ev), self.timeout, false)
+                    .await?;
                 self.inner.handlers.lock().await.remove(&id);
             }
             std::collections::hash_map::Entry::Vacant(_) => anyhow::bail!("Subscription not found"),
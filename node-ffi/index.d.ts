@@ -14,7 +14,8 @@ export const enum EventType {
   CamlocConnect = 'CamlocConnect',
   CamlocDisconnect = 'CamlocDisconnect',
   CamlocPosition = 'CamlocPosition',
-  CamlocInfoUpdate = 'CamlocInfoUpdate'
+  CamlocInfoUpdate = 'CamlocInfoUpdate',
+  ControlChanged = 'ControlChanged'
 }
 export const enum PinMode {
   input = 'input',
@@ -27,10 +28,15 @@ export interface Position {
 }
 export class Robot {
   constructor()
-  static connect(addr: string): Promise<Robot>
+  /** `secret` is needed if the server requires authentication */
+  static connect(addr: string, secret?: string | undefined | null): Promise<Robot>
   disconnect(): void
   nop(): Promise<void>
   getUptime(): Promise<number>
+  /** Returns the lease in seconds */
+  acquireControl(): Promise<number>
+  takeControl(adminToken: string): Promise<number>
+  releaseControl(): Promise<void>
   drive(left: number, right: number): Promise<void>
   stop(): Promise<void>
   led(r: boolean, g: boolean, b: boolean): Promise<void>
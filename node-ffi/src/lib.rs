@@ -14,7 +14,7 @@ use roblib_client::{
         roland::RolandAsync,
         RoblibBuiltinAsync,
     },
-    transports::tcp::TcpAsync,
+    transports::{tcp::TcpAsync, TransportAsync},
     RobotAsync,
 };
 use std::{sync::Arc, time::Duration};
@@ -101,8 +101,9 @@ impl Robot {
         panic!("Use Robot.connect instead of new Robot")
     }
 
+    /// `secret` is needed if the server requires authentication
     #[napi]
-    pub async fn connect(addr: String) -> Result<Robot> {
+    pub async fn connect(addr: String, secret: Option<String>) -> Result<Robot> {
         roblib_client::logger::init_log(Some("info"));
         let rt = Arc::new(
             tokio::runtime::Builder::new_multi_thread()
@@ -111,7 +112,10 @@ impl Robot {
                 .build()?,
         );
 
-        let tcp = rt.spawn(TcpAsync::connect(addr)).await.unwrap().unwrap();
+        let tcp = rt.spawn(TcpAsync::connect(addr)).await.unwrap()?;
+        if let Some(secret) = secret {
+            tcp.authenticate(&secret).await?;
+        }
 
         Ok(Self {
             robot: Arc::new(RobotAsync::new(tcp)),
@@ -143,6 +147,22 @@ impl Robot {
         Ok(self.robot.get_uptime().await?.as_secs() as u32)
     }
 
+    /// Returns the lease in seconds
+    #[napi]
+    pub async fn acquire_control(&self) -> Result<u32> {
+        Ok(self.robot.acquire_control().await?.as_secs() as u32)
+    }
+
+    #[napi]
+    pub async fn take_control(&self, admin_token: String) -> Result<u32> {
+        Ok(self.robot.take_control(&admin_token).await?.as_secs() as u32)
+    }
+
+    #[napi]
+    pub async fn release_control(&self) -> Result<()> {
+        Ok(self.robot.release_control().await?)
+    }
+
     // roblib::roland::Roland
     #[napi]
     pub async fn drive(&self, left: f64, right: f64) -> Result<()> {
@@ -255,6 +275,9 @@ impl Robot {
                 EventType::CamlocInfoUpdate => {
                     sub_recv!(robot, tsfn, event::CamlocInfoUpdate);
                 }
+                EventType::ControlChanged => {
+                    sub_recv!(robot, tsfn, event::ControlChanged);
+                }
             }
             // force return type
             #[allow(unreachable_code)]
@@ -277,6 +300,8 @@ pub enum EventType {
     CamlocDisconnect,
     CamlocPosition,
     CamlocInfoUpdate,
+
+    ControlChanged,
 }
 impl EventType {
     pub fn to_concrete(self, value: serde_json::Value) {
@@ -302,6 +327,9 @@ impl EventType {
             EventType::CamlocInfoUpdate => ConcreteValue::CamlocInfoUpdate(
                 serde_json::from_value(value).expect("invalid event value"),
             ),
+            EventType::ControlChanged => ConcreteValue::ControlChanged(
+                serde_json::from_value(value).expect("invalid event value"),
+            ),
         };
     }
 }
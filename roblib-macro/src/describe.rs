@@ -0,0 +1,126 @@
+use proc_macro2::TokenStream;
+use quote::quote;
+use syn::{Attribute, Data, DeriveInput, Fields, LitStr};
+
+/// `#[serde(skip)]` fields aren't sent over the wire, so they're left out of the schema
+fn is_skipped(attrs: &[Attribute]) -> bool {
+    attrs.iter().any(|attr| {
+        let mut skip = false;
+        if attr.path().is_ident("serde") {
+            let _ = attr.parse_nested_meta(|meta| {
+                skip |= meta.path.is_ident("skip");
+                Ok(())
+            });
+        }
+        skip
+    })
+}
+
+/// `#[serde(rename_all = "snake_case")]`, the only renaming used on described types
+fn is_snake_case(attrs: &[Attribute]) -> bool {
+    attrs.iter().any(|attr| {
+        let mut snake = false;
+        if attr.path().is_ident("serde") {
+            let _ = attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("rename_all") {
+                    snake |= meta.value()?.parse::<LitStr>()?.value() == "snake_case";
+                }
+                Ok(())
+            });
+        }
+        snake
+    })
+}
+
+/// `vec![Field { .. }, ..]` for a struct's or variant's fields
+fn fields(fields: &Fields) -> TokenStream {
+    let fields = fields.iter().filter(|f| !is_skipped(&f.attrs)).map(|f| {
+        let name = match &f.ident {
+            Some(ident) => {
+                let name = LitStr::new(&ident.to_string(), ident.span());
+                quote!(Some(#name.into()))
+            }
+            None => quote!(None),
+        };
+        let ty = &f.ty;
+
+        quote! {
+            crate::schema::Field {
+                name: #name,
+                ty: <#ty as crate::schema::Describe>::describe(types),
+            }
+        }
+    });
+
+    quote!(vec![#(#fields),*])
+}
+
+/// Defines a struct or enum as a named type
+pub fn describe(inp: &DeriveInput) -> TokenStream {
+    let ident = &inp.ident;
+    let name = LitStr::new(&ident.to_string(), ident.span());
+
+    let def = match &inp.data {
+        Data::Struct(s) => {
+            let fields = fields(&s.fields);
+            quote!(crate::schema::Def::Struct(#fields))
+        }
+        Data::Enum(e) => {
+            let snake = is_snake_case(&inp.attrs);
+            let variants = e.variants.iter().map(|v| {
+                let name = match snake {
+                    true => crate::registry::snake_case(&v.ident),
+                    false => v.ident.to_string(),
+                };
+                let name = LitStr::new(&name, v.ident.span());
+                let fields = fields(&v.fields);
+                quote! {
+                    crate::schema::Variant {
+                        name: #name.into(),
+                        fields: #fields,
+                    }
+                }
+            });
+            quote!(crate::schema::Def::Enum(vec![#(#variants),*]))
+        }
+        Data::Union(u) => {
+            return syn::Error::new(u.union_token.span, "unions can't be described")
+                .to_compile_error()
+        }
+    };
+
+    quote! {
+        impl crate::schema::Describe for #ident {
+            #[allow(unused_variables)]
+            fn describe(types: &mut crate::schema::Types) -> crate::schema::Type {
+                types.define(#name, |types| #def)
+            }
+        }
+    }
+}
+
+/// Lists a command's or event's fields as its arguments, `returns` is what comes back
+pub fn signature(inp: &DeriveInput, returns: TokenStream) -> TokenStream {
+    let ident = &inp.ident;
+
+    let args = match &inp.data {
+        Data::Struct(s) => fields(&s.fields),
+        _ => {
+            return syn::Error::new(ident.span(), "commands and events must be structs")
+                .to_compile_error()
+        }
+    };
+
+    quote! {
+        impl crate::schema::Signature for #ident {
+            #[allow(unused_variables)]
+            fn args(types: &mut crate::schema::Types) -> Vec<crate::schema::Field> {
+                #args
+            }
+
+            fn returns(types: &mut crate::schema::Types) -> crate::schema::Type {
+                <#returns as crate::schema::Describe>::describe(types)
+            }
+        }
+    }
+}
@@ -3,10 +3,39 @@ extern crate proc_macro;
 use quote::quote;
 use syn::{parse_macro_input, DeriveInput};
 
+mod describe;
+mod registry;
+
+/// Generates `Concrete` and its serde impls from a list of commands,
+/// as well as a `for_each_command!` macro for dispatching on them.
+///
+/// ```ignore
+/// concrete_commands! {
+///     #[cfg(feature = "gpio")]
+///     ReadPin,
+///     Nop,
+/// }
+/// ```
+#[proc_macro]
+pub fn concrete_commands(item: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let reg = parse_macro_input!(item as registry::Registry);
+    proc_macro::TokenStream::from(registry::commands(reg))
+}
+
+/// Generates `ConcreteType`, `ConcreteValue` and their serde impls from a list of events,
+/// as well as a `for_each_event!` macro.
+#[proc_macro]
+pub fn concrete_events(item: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let reg = parse_macro_input!(item as registry::Registry);
+    proc_macro::TokenStream::from(registry::events(reg))
+}
+
+/// Also implements `schema::Signature`, with the command's fields as its arguments
 #[proc_macro_derive(Command)]
 pub fn derive_command(item: proc_macro::TokenStream) -> proc_macro::TokenStream {
     let inp = parse_macro_input!(item as DeriveInput);
     let ident = &inp.ident;
+    let signature = describe::signature(&inp, quote!(<#ident as crate::cmd::Command>::Return));
 
     let res = quote! {
         impl From<#ident> for crate::cmd::Concrete {
@@ -23,16 +52,20 @@ pub fn derive_command(item: proc_macro::TokenStream) -> proc_macro::TokenStream
                 }
             }
         }
+
+        #signature
     };
 
     // println!("{res}");
     proc_macro::TokenStream::from(res)
 }
 
+/// Also implements `schema::Signature`, with the event's fields as its arguments
 #[proc_macro_derive(Event)]
 pub fn derive_event(item: proc_macro::TokenStream) -> proc_macro::TokenStream {
     let inp = parse_macro_input!(item as DeriveInput);
     let ident = &inp.ident;
+    let signature = describe::signature(&inp, quote!(<#ident as crate::event::Event>::Item));
 
     let res = quote! {
         impl From<#ident> for crate::event::ConcreteType {
@@ -49,8 +82,17 @@ pub fn derive_event(item: proc_macro::TokenStream) -> proc_macro::TokenStream {
                 }
             }
         }
+
+        #signature
     };
 
     // println!("{res}");
     proc_macro::TokenStream::from(res)
 }
+
+/// Describes a type used by commands or events, see `roblib::schema`
+#[proc_macro_derive(Describe)]
+pub fn derive_describe(item: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let inp = parse_macro_input!(item as DeriveInput);
+    proc_macro::TokenStream::from(describe::describe(&inp))
+}
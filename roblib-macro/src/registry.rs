@@ -0,0 +1,411 @@
+use proc_macro2::TokenStream;
+use quote::{format_ident, quote};
+use syn::{
+    parse::{Parse, ParseStream},
+    punctuated::Punctuated,
+    Attribute, Ident, Token,
+};
+
+/// A single line of a registry: `#[cfg(...)] Name`
+pub struct Entry {
+    pub attrs: Vec<Attribute>,
+    pub ident: Ident,
+}
+
+impl Parse for Entry {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        Ok(Self {
+            attrs: input.call(Attribute::parse_outer)?,
+            ident: input.parse()?,
+        })
+    }
+}
+
+pub struct Registry(pub Vec<Entry>);
+
+impl Parse for Registry {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let entries = Punctuated::<Entry, Token![,]>::parse_terminated(input)?;
+        Ok(Self(entries.into_iter().collect()))
+    }
+}
+
+/// `macro_rules!` that hands the registry to another macro,
+/// so other crates can generate code for every entry (eg. the server's dispatch)
+fn for_each(name: &Ident, entries: &[Entry]) -> TokenStream {
+    let lines = entries
+        .iter()
+        .map(|Entry { attrs, ident }| quote!(#(#attrs)* #ident));
+
+    quote! {
+        #[macro_export]
+        macro_rules! #name {
+            ($callback:ident) => {
+                $callback! { #(#lines),* }
+            };
+        }
+    }
+}
+
+/// `MoveRobot` -> `move_robot`, the names used by human readable formats
+pub(crate) fn snake_case(ident: &Ident) -> String {
+    let mut s = String::new();
+    for (i, c) in ident.to_string().chars().enumerate() {
+        if c.is_uppercase() {
+            if i != 0 {
+                s.push('_');
+            }
+            s.extend(c.to_lowercase());
+        } else {
+            s.push(c);
+        }
+    }
+    s
+}
+
+/// `Some("roland")` for an entry behind `#[cfg(feature = "roland")]`, `None` otherwise
+fn feature(entry: &Entry) -> TokenStream {
+    for attr in entry.attrs.iter().filter(|a| a.path().is_ident("cfg")) {
+        if let Ok(syn::Meta::NameValue(nv)) = attr.parse_args::<syn::Meta>() {
+            if nv.path.is_ident("feature") {
+                let value = nv.value;
+                return quote!(Some(#value));
+            }
+        }
+    }
+    quote!(None)
+}
+
+pub fn commands(Registry(entries): Registry) -> TokenStream {
+    let attrs: Vec<_> = entries.iter().map(|e| &e.attrs).collect();
+    let idents: Vec<_> = entries.iter().map(|e| &e.ident).collect();
+    let names: Vec<_> = entries.iter().map(|e| snake_case(&e.ident)).collect();
+    let features: Vec<_> = entries.iter().map(feature).collect();
+    let attrs = &attrs;
+    let idents = &idents;
+    let names = &names;
+
+    let for_each = for_each(&format_ident!("for_each_command"), &entries);
+
+    quote! {
+        pub enum Concrete {
+            #( #(#attrs)* #idents(crate::cmd::#idents), )*
+        }
+
+        impl std::fmt::Debug for Concrete {
+            fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                match self {
+                    #( #(#attrs)* Self::#idents(v) => v.fmt(f), )*
+                }
+            }
+        }
+
+        impl Concrete {
+            /// The prefixes of every command compiled in
+            pub const PREFIXES: &'static [char] = &[
+                #( #(#attrs)* <crate::cmd::#idents as crate::cmd::Command>::PREFIX, )*
+            ];
+
+            pub fn get_prefix(&self) -> char {
+                match self {
+                    #( #(#attrs)* Self::#idents(_) => <crate::cmd::#idents as crate::cmd::Command>::PREFIX, )*
+                }
+            }
+
+            /// The name of the command with the given prefix, like `MoveRobot`
+            pub fn name_of(prefix: char) -> Option<&'static str> {
+                match prefix {
+                    #( #(#attrs)* <crate::cmd::#idents as crate::cmd::Command>::PREFIX => Some(stringify!(#idents)), )*
+                    _ => None,
+                }
+            }
+
+            pub fn has_return(&self) -> bool {
+                match self {
+                    #( #(#attrs)* Self::#idents(_) => crate::cmd::has_return::<crate::cmd::#idents>(), )*
+                }
+            }
+
+            /// Whether the server replies when the command succeeds, see [`crate::cmd::Command::REPLIES`]
+            pub fn replies(&self) -> bool {
+                match self {
+                    #( #(#attrs)* Self::#idents(_) => <crate::cmd::#idents as crate::cmd::Command>::REPLIES, )*
+                }
+            }
+
+            /// Describes every command compiled in, see [`crate::schema`]
+            pub fn describe(types: &mut crate::schema::Types) -> Vec<crate::schema::CommandDef> {
+                let mut v = Vec::new();
+                #(
+                    #(#attrs)*
+                    v.push(crate::schema::CommandDef::new::<crate::cmd::#idents>(#names, #features, types));
+                )*
+                v
+            }
+        }
+
+        impl serde::Serialize for Concrete {
+            fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+            where
+                S: serde::Serializer,
+            {
+                use serde::ser::SerializeStruct;
+
+                /// `{"cmd": "move_robot", "args": [0.5, 0.5]}`
+                #[derive(serde::Serialize)]
+                #[serde(tag = "cmd", content = "args")]
+                enum Readable<'a> {
+                    #( #(#attrs)* #[serde(rename = #names)] #idents(&'a crate::cmd::#idents), )*
+                }
+
+                if serializer.is_human_readable() {
+                    let r = match self {
+                        #( #(#attrs)* Self::#idents(c) => Readable::#idents(c), )*
+                    };
+                    return serde::Serialize::serialize(&r, serializer);
+                }
+
+                let mut s = serializer.serialize_struct("Concrete", 2)?;
+                match self {
+                    #(
+                        #(#attrs)*
+                        Self::#idents(c) => {
+                            s.serialize_field("prefix", &<crate::cmd::#idents as crate::cmd::Command>::PREFIX)?;
+                            s.serialize_field("cmd", &c)?;
+                        }
+                    )*
+                }
+                s.end()
+            }
+        }
+
+        impl<'de> serde::Deserialize<'de> for Concrete {
+            fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+            where
+                D: serde::Deserializer<'de>,
+            {
+                use serde::de;
+
+                #[derive(serde::Deserialize)]
+                #[serde(tag = "cmd", content = "args")]
+                enum Readable {
+                    #( #(#attrs)* #[serde(rename = #names)] #idents(crate::cmd::#idents), )*
+                }
+
+                if deserializer.is_human_readable() {
+                    return Ok(match <Readable as serde::Deserialize>::deserialize(deserializer)? {
+                        #( #(#attrs)* Readable::#idents(c) => Concrete::#idents(c), )*
+                    });
+                }
+
+                struct ConcreteVisitor;
+                impl<'de> de::Visitor<'de> for ConcreteVisitor {
+                    type Value = Concrete;
+
+                    fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+                        formatter.write_str("a prefix and a command body")
+                    }
+
+                    fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Error>
+                    where
+                        A: de::SeqAccess<'de>,
+                    {
+                        let prefix: char = seq
+                            .next_element()?
+                            .ok_or_else(|| de::Error::invalid_length(0, &self))?;
+
+                        let v: Option<Concrete> = match prefix {
+                            #(
+                                #(#attrs)*
+                                <crate::cmd::#idents as crate::cmd::Command>::PREFIX => {
+                                    seq.next_element()?.map(Concrete::#idents)
+                                }
+                            )*
+
+                            _ => {
+                                return Err(de::Error::invalid_value(
+                                    de::Unexpected::Char(prefix),
+                                    &"a command prefix",
+                                ))
+                            }
+                        };
+
+                        v.ok_or_else(|| de::Error::invalid_length(1, &self))
+                    }
+                }
+
+                deserializer.deserialize_struct("Concrete", &["prefix", "cmd"], ConcreteVisitor)
+            }
+        }
+
+        #for_each
+    }
+}
+
+pub fn events(Registry(entries): Registry) -> TokenStream {
+    let attrs: Vec<_> = entries.iter().map(|e| &e.attrs).collect();
+    let idents: Vec<_> = entries.iter().map(|e| &e.ident).collect();
+    let names: Vec<_> = entries.iter().map(|e| snake_case(&e.ident)).collect();
+    let features: Vec<_> = entries.iter().map(feature).collect();
+    let attrs = &attrs;
+    let idents = &idents;
+    let names = &names;
+
+    let for_each = for_each(&format_ident!("for_each_event"), &entries);
+
+    quote! {
+        #[derive(PartialEq, Eq, Hash, Clone, Debug)]
+        pub enum ConcreteType {
+            #( #(#attrs)* #idents(crate::event::#idents), )*
+
+            None,
+        }
+
+        #[derive(Clone, Debug)]
+        pub enum ConcreteValue {
+            #( #(#attrs)* #idents(<crate::event::#idents as crate::event::Event>::Item), )*
+
+            None,
+        }
+
+        impl ConcreteType {
+            /// The names of every event compiled in
+            pub const NAMES: &'static [&'static str] = &[
+                #( #(#attrs)* <crate::event::#idents as crate::event::Event>::NAME, )*
+            ];
+
+            pub fn get_name(&self) -> &'static str {
+                match self {
+                    #( #(#attrs)* Self::#idents(_) => <crate::event::#idents as crate::event::Event>::NAME, )*
+
+                    Self::None => unreachable!(),
+                }
+            }
+
+            /// Describes every event compiled in, see [`crate::schema`]
+            pub fn describe(types: &mut crate::schema::Types) -> Vec<crate::schema::EventDef> {
+                let mut v = Vec::new();
+                #(
+                    #(#attrs)*
+                    v.push(crate::schema::EventDef::new::<crate::event::#idents>(#names, #features, types));
+                )*
+                v
+            }
+        }
+
+        impl serde::Serialize for ConcreteType {
+            fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+            where
+                S: serde::Serializer,
+            {
+                use serde::ser::SerializeStruct;
+
+                /// `{"event": "gpio_pin", "args": 4}`
+                #[derive(serde::Serialize)]
+                #[serde(tag = "event", content = "args")]
+                enum Readable<'a> {
+                    #( #(#attrs)* #[serde(rename = #names)] #idents(&'a crate::event::#idents), )*
+                }
+
+                if serializer.is_human_readable() {
+                    let r = match self {
+                        #( #(#attrs)* Self::#idents(c) => Readable::#idents(c), )*
+
+                        Self::None => unreachable!(),
+                    };
+                    return serde::Serialize::serialize(&r, serializer);
+                }
+
+                let mut s = serializer.serialize_struct("ConcreteType", 2)?;
+                match self {
+                    #(
+                        #(#attrs)*
+                        Self::#idents(c) => {
+                            s.serialize_field("name", <crate::event::#idents as crate::event::Event>::NAME)?;
+                            s.serialize_field("ev", &c)?;
+                        }
+                    )*
+
+                    Self::None => unreachable!(),
+                }
+                s.end()
+            }
+        }
+
+        impl<'de> serde::Deserialize<'de> for ConcreteType {
+            fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+            where
+                D: serde::Deserializer<'de>,
+            {
+                use serde::de;
+
+                #[derive(serde::Deserialize)]
+                #[serde(tag = "event", content = "args")]
+                enum Readable {
+                    #( #(#attrs)* #[serde(rename = #names)] #idents(crate::event::#idents), )*
+                }
+
+                if deserializer.is_human_readable() {
+                    return Ok(match <Readable as serde::Deserialize>::deserialize(deserializer)? {
+                        #( #(#attrs)* Readable::#idents(c) => ConcreteType::#idents(c), )*
+                    });
+                }
+
+                struct ConcreteTypeVisitor;
+                impl<'de> de::Visitor<'de> for ConcreteTypeVisitor {
+                    type Value = ConcreteType;
+
+                    fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+                        formatter.write_str("an event name and arguments")
+                    }
+
+                    fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Error>
+                    where
+                        A: de::SeqAccess<'de>,
+                    {
+                        // TODO: &str should be fine...
+                        let name: String = seq
+                            .next_element()?
+                            .ok_or_else(|| de::Error::invalid_length(0, &self))?;
+
+                        let v: Option<ConcreteType> = match &name[..] {
+                            #(
+                                #(#attrs)*
+                                <crate::event::#idents as crate::event::Event>::NAME => {
+                                    seq.next_element()?.map(ConcreteType::#idents)
+                                }
+                            )*
+
+                            _ => {
+                                return Err(de::Error::invalid_value(
+                                    de::Unexpected::Str(&name),
+                                    &"an event name",
+                                ))
+                            }
+                        };
+
+                        v.ok_or_else(|| de::Error::invalid_length(1, &self))
+                    }
+                }
+
+                deserializer.deserialize_struct("ConcreteType", &["name", "ev"], ConcreteTypeVisitor)
+            }
+        }
+
+        /// Serializes only the event's item, without any tag
+        impl serde::Serialize for ConcreteValue {
+            fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+            where
+                S: serde::Serializer,
+            {
+                match self {
+                    #( #(#attrs)* Self::#idents(v) => serde::Serialize::serialize(v, serializer), )*
+
+                    Self::None => serializer.serialize_unit(),
+                }
+            }
+        }
+
+        #for_each
+    }
+}
@@ -1,7 +1,9 @@
 pub mod cmd;
 pub mod event;
 
-pub use camloc_server::{service, MotionHint, PlacedCamera, Position};
+use crate::schema::{Def, Describe, Field, Type, Types};
+
+pub use camloc_server::{service, MotionHint, PlacedCamera, Position, MAIN_PORT};
 
 pub trait Camloc {
     fn get_position(&self) -> anyhow::Result<Option<Position>>;
@@ -13,6 +15,39 @@ pub trait CamlocAsync {
     async fn get_position(&self) -> anyhow::Result<Option<Position>>;
 }
 
+impl Describe for Position {
+    fn describe(types: &mut Types) -> Type {
+        types.define("Position", |types| {
+            let field = |name: &str, types: &mut Types| Field {
+                name: Some(name.into()),
+                ty: f64::describe(types),
+            };
+            Def::Struct(vec![
+                field("x", types),
+                field("y", types),
+                field("rotation", types),
+            ])
+        })
+    }
+}
+
+impl Describe for PlacedCamera {
+    fn describe(types: &mut Types) -> Type {
+        types.define("PlacedCamera", |types| {
+            Def::Struct(vec![
+                Field {
+                    name: Some("fov".into()),
+                    ty: f64::describe(types),
+                },
+                Field {
+                    name: Some("position".into()),
+                    ty: Position::describe(types),
+                },
+            ])
+        })
+    }
+}
+
 #[cfg(all(feature = "roland", feature = "gpio-backend"))]
 pub fn get_motion_hint(left: f64, right: f64) -> Option<MotionHint> {
     let left_sign = left.signum() as isize;
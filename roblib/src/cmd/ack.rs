@@ -0,0 +1,63 @@
+use super::{Command, Concrete};
+use crate::schema::{Describe, Field, Signature, Type, Types};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::marker::PhantomData;
+
+/// Runs a command, with the server replying even if it has no return value,
+/// so the client knows that it was executed.
+///
+/// The reply carries the command's return value, or `()` if it doesn't have one.
+/// `R` is only used to decode this on the client.
+#[derive(Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Ack<R = ()>(pub Box<Concrete>, #[serde(skip)] pub PhantomData<R>);
+
+impl Ack {
+    pub fn new(cmd: impl Into<Concrete>) -> Self {
+        Self(Box::new(cmd.into()), PhantomData)
+    }
+}
+
+impl<R> Command for Ack<R>
+where
+    R: Serialize + DeserializeOwned + Send + Sync + 'static,
+{
+    const PREFIX: char = 'A';
+    const REPLIES: bool = true;
+    type Return = R;
+}
+
+impl<R> Signature for Ack<R> {
+    fn args(types: &mut Types) -> Vec<Field> {
+        vec![Field {
+            name: None,
+            ty: <Concrete as Describe>::describe(types),
+        }]
+    }
+
+    /// `R` is only known by the client
+    fn returns(_: &mut Types) -> Type {
+        Type::Dynamic
+    }
+}
+
+impl<R> std::fmt::Debug for Ack<R> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("Ack").field(&self.0).finish()
+    }
+}
+
+impl<R> From<Ack<R>> for Concrete {
+    fn from(Ack(cmd, _): Ack<R>) -> Self {
+        Concrete::Ack(Ack(cmd, PhantomData))
+    }
+}
+impl<R> From<Concrete> for Ack<R> {
+    fn from(value: Concrete) -> Self {
+        if let Concrete::Ack(Ack(cmd, _)) = value {
+            Ack(cmd, PhantomData)
+        } else {
+            panic!("Tried to convert an unknown command to a concrete command")
+        }
+    }
+}
@@ -0,0 +1,82 @@
+use super::Command;
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+
+/// Asks the server for a random challenge to answer with [`Authenticate`].
+/// A challenge can only be answered once, and only for a minute.
+/// Asking for a new one makes the previous one invalid.
+#[derive(Command, Serialize, Deserialize, Debug)]
+pub struct GetChallenge;
+impl Command for GetChallenge {
+    const PREFIX: char = 'H';
+    type Return = String;
+}
+
+/// Answers a challenge, proving that the client knows the server's shared secret.
+/// Takes the challenge and the response to it, see [`respond`].
+/// Returns a session token, which HTTP clients send as `Authorization: Bearer <token>`.
+///
+/// Servers with a secret only accept [`GetChallenge`], [`Authenticate`] and
+/// [`GetCapabilities`](super::GetCapabilities) from a client until it authenticates.
+/// TCP and WebSocket keep the connection authenticated,
+/// UDP clients append the token to every datagram, after the encoded command.
+#[derive(Command, Serialize, Deserialize, Debug)]
+pub struct Authenticate(pub String, pub String);
+impl Command for Authenticate {
+    const PREFIX: char = 'h';
+    type Return = String;
+}
+
+impl Authenticate {
+    pub fn new(secret: &str, challenge: String) -> Self {
+        let response = respond(secret, &challenge);
+        Self(challenge, response)
+    }
+}
+
+/// The answer to a challenge, the hex encoded HMAC-SHA256 of it keyed with the secret
+pub fn respond(secret: &str, challenge: &str) -> String {
+    let mac = mac(secret, challenge).finalize().into_bytes();
+    mac.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+/// Checks the answer to a challenge, in constant time
+pub fn verify(secret: &str, challenge: &str, response: &str) -> bool {
+    let Some(response) = decode_hex(response) else {
+        return false;
+    };
+    mac(secret, challenge).verify_slice(&response).is_ok()
+}
+
+fn mac(secret: &str, challenge: &str) -> Hmac<Sha256> {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC takes keys of any size");
+    mac.update(challenge.as_bytes());
+    mac
+}
+
+/// `None` if it's not hex, or has an odd length
+fn decode_hex(s: &str) -> Option<Vec<u8>> {
+    (0..s.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(s.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn response_roundtrip() {
+        let response = respond("secret", "challenge");
+        assert_eq!(response.len(), 64);
+        assert!(verify("secret", "challenge", &response));
+
+        assert!(!verify("wrong", "challenge", &response));
+        assert!(!verify("secret", "other", &response));
+        assert!(!verify("secret", "challenge", &response[1..]));
+        assert!(!verify("secret", "challenge", "not hex"));
+    }
+}
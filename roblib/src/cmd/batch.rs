@@ -0,0 +1,67 @@
+use super::{Command, Concrete};
+use crate::schema::{Describe, Field, Signature, Type, Types};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::marker::PhantomData;
+
+/// Several commands, executed by the server in order, in one go.
+///
+/// Execution stops at the first failing command, which fails the whole batch.
+/// It isn't atomic though: the commands before the failing one have already run, and aren't undone.
+///
+/// The server always replies, with the error or the return values in a flat tuple like `(a, (), c)`,
+/// `()` standing in for the commands without one, and `()` for an empty batch.
+/// An [`Ack`](super::Ack) of a command that replies anyway is rejected, as the client would decode `()` in its place.
+/// `R` is only used to decode this on the client, `roblib_client`'s batch builder keeps track of it.
+#[derive(Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Batch<R = ()>(pub Vec<Concrete>, #[serde(skip)] pub PhantomData<R>);
+
+impl<R> Batch<R> {
+    pub fn new(cmds: Vec<Concrete>) -> Self {
+        Self(cmds, PhantomData)
+    }
+}
+
+impl<R> Command for Batch<R>
+where
+    R: Serialize + DeserializeOwned + Send + Sync + 'static,
+{
+    const PREFIX: char = 'B';
+    const REPLIES: bool = true;
+    type Return = R;
+}
+
+impl<R> Signature for Batch<R> {
+    fn args(types: &mut Types) -> Vec<Field> {
+        vec![Field {
+            name: None,
+            ty: <Vec<Concrete> as Describe>::describe(types),
+        }]
+    }
+
+    /// `R` is only known by the client
+    fn returns(_: &mut Types) -> Type {
+        Type::Dynamic
+    }
+}
+
+impl<R> std::fmt::Debug for Batch<R> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("Batch").field(&self.0).finish()
+    }
+}
+
+impl<R> From<Batch<R>> for Concrete {
+    fn from(Batch(cmds, _): Batch<R>) -> Self {
+        Concrete::Batch(Batch::new(cmds))
+    }
+}
+impl<R> From<Concrete> for Batch<R> {
+    fn from(value: Concrete) -> Self {
+        if let Concrete::Batch(Batch(cmds, _)) = value {
+            Batch::new(cmds)
+        } else {
+            panic!("Tried to convert an unknown command to a concrete command")
+        }
+    }
+}
@@ -0,0 +1,35 @@
+use super::Command;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+/// Takes the exclusive control of the robot, making every other client an observer.
+/// Observers can still read the sensors and subscribe to events, but can't move the robot.
+///
+/// Returns how long the lease lasts, every command from the client in control renews it.
+/// Fails if another client is in control, see [`TakeControl`] for taking it away from them.
+/// Every change of control is announced in the [`ControlChanged`](crate::event::ControlChanged) event.
+///
+/// While no one is in control, every client can move the robot, unless the server requires the lease.
+#[derive(Command, Serialize, Deserialize, Debug)]
+pub struct AcquireControl;
+impl Command for AcquireControl {
+    const PREFIX: char = 'D';
+    type Return = Duration;
+}
+
+/// Same as [`AcquireControl`], but takes the control even if another client is in control.
+/// Takes the server's admin token.
+#[derive(Command, Serialize, Deserialize, Debug)]
+pub struct TakeControl(pub String);
+impl Command for TakeControl {
+    const PREFIX: char = 'F';
+    type Return = Duration;
+}
+
+/// Gives up the control of the robot, fails if the client isn't in control
+#[derive(Command, Serialize, Deserialize, Debug)]
+pub struct ReleaseControl;
+impl Command for ReleaseControl {
+    const PREFIX: char = 'd';
+    type Return = ();
+}
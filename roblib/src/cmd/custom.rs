@@ -0,0 +1,51 @@
+use super::{Command, Concrete};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+/// A command defined outside of roblib, identified by its name.
+///
+/// The payload is the command encoded with bincode.
+/// Rather than building this by hand, implement [`CustomCommand`] and send it wrapped in [`Typed`].
+#[derive(Command, Serialize, Deserialize, Debug)]
+pub struct Custom(pub String, pub Vec<u8>);
+impl Command for Custom {
+    const PREFIX: char = '$';
+    type Return = ();
+}
+
+/// A command handled by a downstream crate,
+/// see `roblib_server::custom` for registering a handler on the server.
+pub trait CustomCommand:
+    Serialize + DeserializeOwned + Send + Sync + 'static + std::fmt::Debug
+{
+    /// Must be unique among the custom commands registered on the server
+    const NAME: &'static str;
+    type Return: Serialize + DeserializeOwned + Send + Sync + 'static;
+}
+
+/// Wraps a [`CustomCommand`] so it can be sent by any transport
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Typed<C>(pub C);
+
+impl<C: CustomCommand> Command for Typed<C> {
+    const PREFIX: char = Custom::PREFIX;
+    type Return = C::Return;
+}
+
+impl<C: CustomCommand> From<Typed<C>> for Concrete {
+    fn from(Typed(cmd): Typed<C>) -> Self {
+        let payload = bincode::Options::serialize(bincode::options(), &cmd)
+            .expect("Failed to encode custom command");
+        Concrete::Custom(Custom(C::NAME.into(), payload))
+    }
+}
+impl<C: CustomCommand> From<Concrete> for Typed<C> {
+    fn from(value: Concrete) -> Self {
+        match value {
+            Concrete::Custom(Custom(name, payload)) if name == C::NAME => Typed(
+                bincode::Options::deserialize(bincode::options(), &payload)
+                    .expect("Failed to decode custom command"),
+            ),
+            _ => panic!("Tried to convert an unknown command to a concrete command"),
+        }
+    }
+}
@@ -1,7 +1,13 @@
-use roblib_macro::Command;
+use roblib_macro::{Command, Describe};
 use serde::{de::DeserializeOwned, Serialize};
 
+mod ack;
+pub mod auth;
+mod batch;
 pub mod concrete;
+mod control;
+mod custom;
+mod schedule;
 
 use crate::event;
 
@@ -14,7 +20,13 @@ pub use crate::gpio::cmd::*;
 #[cfg(feature = "camloc")]
 pub use crate::camloc::cmd::*;
 
+pub use self::ack::Ack;
+pub use self::auth::{Authenticate, GetChallenge};
+pub use self::batch::Batch;
 pub use self::concrete::Concrete;
+pub use self::control::{AcquireControl, ReleaseControl, TakeControl};
+pub use self::custom::{Custom, CustomCommand, Typed};
+pub use self::schedule::{CancelScheduled, ListScheduled, Schedule, Scheduled, When};
 
 pub trait Command:
     Serialize
@@ -28,14 +40,59 @@ pub trait Command:
 {
     const PREFIX: char;
     type Return: Serialize + DeserializeOwned + Send + Sync + 'static;
+
+    /// Whether the server replies when the command succeeds.
+    /// Only the ones with a return value do, unless they're wrapped in [`Ack`].
+    const REPLIES: bool = has_return::<Self>();
 }
 
 pub const SEPARATOR: char = ' ';
 
+/// The default limit on the size of a binary frame, 1 MiB.
+/// A larger one is rejected without reading it into memory.
+pub const MAX_FRAME: usize = 1024 * 1024;
+
+/// Bumped on every breaking change to the wire format, see [`GetCapabilities`].
+/// A new command counts too, a peer without it can't parse its prefix.
+pub const PROTOCOL_VERSION: u32 = 9;
+
 pub const fn has_return<C: Command>() -> bool {
     std::mem::size_of::<C::Return>() != 0
 }
 
+/// Sent back by the server in place of a command's return value if the command failed.
+///
+/// Every reply is framed as `(id, Result<C::Return, Error>)`.
+#[derive(Describe, serde::Serialize, serde::Deserialize, Debug, Clone)]
+pub struct Error(pub ErrorCode, pub String);
+
+#[derive(Describe, serde::Serialize, serde::Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorCode {
+    /// The server couldn't decode the command
+    Decode,
+    /// The command was decoded, but failed to execute
+    Execute,
+    /// The server requires the client to [`Authenticate`] before running the command
+    Unauthorized,
+    /// Another client is in control of the robot, or the server requires [`AcquireControl`] first
+    NotInControl,
+}
+
+impl Error {
+    pub fn new(code: ErrorCode, msg: impl std::fmt::Display) -> Self {
+        Self(code, msg.to_string())
+    }
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let Self(code, msg) = self;
+        write!(f, "{code:?} error: {msg}")
+    }
+}
+
+impl std::error::Error for Error {}
+
 #[derive(Command, serde::Serialize, serde::Deserialize, Debug)]
 pub struct Subscribe(pub event::ConcreteType);
 impl Command for Subscribe {
@@ -56,6 +113,15 @@ impl Command for Nop {
     type Return = ();
 }
 
+/// Tells the server the client is still there, so its watchdog doesn't stop the robot.
+/// The clients send these on their own while the robot is driving.
+#[derive(Command, serde::Serialize, serde::Deserialize, Debug)]
+pub struct Heartbeat;
+impl Command for Heartbeat {
+    const PREFIX: char = 'k';
+    type Return = ();
+}
+
 #[derive(Command, serde::Serialize, serde::Deserialize, Debug)]
 pub struct GetUptime;
 impl Command for GetUptime {
@@ -63,6 +129,66 @@ impl Command for GetUptime {
     type Return = std::time::Duration;
 }
 
+#[derive(Command, serde::Serialize, serde::Deserialize, Debug)]
+pub struct GetCapabilities;
+impl Command for GetCapabilities {
+    const PREFIX: char = 'C';
+    type Return = Capabilities;
+}
+
+/// What a server supports, returned by [`GetCapabilities`]
+#[derive(Describe, serde::Serialize, serde::Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Capabilities {
+    pub version: u32,
+    /// The features the server was compiled with (`roland`, `gpio`, `camloc`, `backend`)
+    pub features: Vec<String>,
+    /// The backends that were successfully initialized
+    pub backends: Vec<String>,
+    /// The transports clients can connect with (`tcp`, `udp`, `http`, `ws`),
+    /// or `tcp+tls`, `https` and `wss` if they're encrypted
+    pub transports: Vec<String>,
+    pub commands: Vec<char>,
+    pub events: Vec<String>,
+}
+
+impl Capabilities {
+    /// Checks that the server understands every command and event this build of roblib can send
+    pub fn verify(&self) -> anyhow::Result<()> {
+        if self.version != PROTOCOL_VERSION {
+            anyhow::bail!(
+                "Protocol version mismatch: server is on {}, client is on {PROTOCOL_VERSION}",
+                self.version
+            );
+        }
+
+        let commands: Vec<_> = Concrete::PREFIXES
+            .iter()
+            .filter(|p| !self.commands.contains(p))
+            .collect();
+        let events: Vec<_> = event::ConcreteType::NAMES
+            .iter()
+            .filter(|n| !self.events.iter().any(|e| e == *n))
+            .collect();
+
+        if !commands.is_empty() || !events.is_empty() {
+            anyhow::bail!(
+                "Server doesn't support commands {commands:?} and events {events:?}, it was compiled with features {:?}",
+                self.features
+            );
+        }
+
+        Ok(())
+    }
+}
+
+/// Describes every command and event the server supports, see [`crate::schema`]
+#[derive(Command, serde::Serialize, serde::Deserialize, Debug)]
+pub struct GetSchema;
+impl Command for GetSchema {
+    const PREFIX: char = 'S';
+    type Return = crate::schema::Schema;
+}
+
 #[derive(Command, serde::Serialize, serde::Deserialize, Debug)]
 pub struct Abort;
 impl Command for Abort {
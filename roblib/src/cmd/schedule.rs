@@ -0,0 +1,56 @@
+use super::{Command, Concrete};
+use roblib_macro::Describe;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+/// When a [`Schedule`]d command should run
+#[derive(Describe, Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum When {
+    /// After the given delay, measured from when the server received the command
+    After(Duration),
+    /// At the given server uptime, as returned by [`GetUptime`](super::GetUptime).
+    /// Runs immediately if it's already in the past.
+    At(Duration),
+}
+
+/// Runs a command on the server at a later time, returns the id it was scheduled under.
+///
+/// The scheduled command's return value is discarded, and failures only get logged by the server.
+/// Pending commands are dropped when the server is aborted.
+#[derive(Command, Serialize, Deserialize, Debug)]
+pub struct Schedule(pub When, pub Box<Concrete>);
+impl Command for Schedule {
+    const PREFIX: char = 'T';
+    type Return = u32;
+}
+
+impl Schedule {
+    pub fn new(when: When, cmd: impl Into<Concrete>) -> Self {
+        Self(when, Box::new(cmd.into()))
+    }
+}
+
+/// Lists the commands that are scheduled, but haven't run yet
+#[derive(Command, Serialize, Deserialize, Debug)]
+pub struct ListScheduled;
+impl Command for ListScheduled {
+    const PREFIX: char = 'Q';
+    type Return = Vec<Scheduled>;
+}
+
+/// A pending command, returned by [`ListScheduled`]
+#[derive(Describe, Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Scheduled {
+    pub id: u32,
+    /// The server uptime it's going to run at
+    pub at: Duration,
+    pub prefix: char,
+}
+
+/// Cancels a scheduled command, returns `false` if it wasn't pending anymore
+#[derive(Command, Serialize, Deserialize, Debug)]
+pub struct CancelScheduled(pub u32);
+impl Command for CancelScheduled {
+    const PREFIX: char = 'K';
+    type Return = bool;
+}
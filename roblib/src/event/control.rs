@@ -0,0 +1,12 @@
+use roblib_macro::Event;
+use serde::{Deserialize, Serialize};
+
+/// Sent when a client acquires or releases the control of the robot, or its lease runs out,
+/// see [`AcquireControl`](crate::cmd::AcquireControl).
+/// The item is the client now in control, `None` if no one is.
+#[derive(Event, Serialize, Deserialize, PartialEq, Eq, Hash, Clone, Copy, Debug)]
+pub struct ControlChanged;
+impl super::Event for ControlChanged {
+    const NAME: &'static str = "control";
+    type Item = Option<String>;
+}
@@ -0,0 +1,89 @@
+use super::{ConcreteType, Event};
+use roblib_macro::Event;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::sync::Arc;
+
+/// An event defined outside of roblib, identified by its name.
+///
+/// The second field is the event encoded with bincode.
+/// Rather than building this by hand, implement [`CustomEvent`] and subscribe to it wrapped in [`Typed`].
+#[derive(Event, Serialize, Deserialize, PartialEq, Eq, Hash, Clone, Debug)]
+pub struct Custom(pub String, pub Vec<u8>);
+impl Event for Custom {
+    const NAME: &'static str = "custom";
+    type Item = CustomValue;
+}
+
+/// The item of a custom event, type erased so that the server can send it with any format.
+///
+/// Only the server produces these, clients receive the item through [`Typed`].
+#[derive(Clone)]
+pub struct CustomValue(Arc<dyn erased_serde::Serialize + Send + Sync>);
+
+impl CustomValue {
+    pub fn new(item: impl Serialize + Send + Sync + 'static) -> Self {
+        Self(Arc::new(item))
+    }
+}
+
+impl std::fmt::Debug for CustomValue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("CustomValue(..)")
+    }
+}
+
+impl Serialize for CustomValue {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        erased_serde::serialize(&*self.0, serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for CustomValue {
+    fn deserialize<D>(_: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        Err(serde::de::Error::custom(
+            "custom event items can only be received through `event::Typed`",
+        ))
+    }
+}
+
+/// An event emitted by a downstream crate,
+/// see `roblib_server::custom` for emitting them on the server.
+pub trait CustomEvent: Serialize + DeserializeOwned + Clone + Send + Sync + 'static {
+    /// Must be unique among the custom events emitted by the server
+    const NAME: &'static str;
+    type Item: Serialize + DeserializeOwned + Clone + Send + Sync + 'static;
+}
+
+/// Wraps a [`CustomEvent`] so it can be subscribed to by any transport
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Typed<E>(pub E);
+
+impl<E: CustomEvent> Event for Typed<E> {
+    const NAME: &'static str = E::NAME;
+    type Item = E::Item;
+}
+
+impl<E: CustomEvent> From<Typed<E>> for ConcreteType {
+    fn from(Typed(ev): Typed<E>) -> Self {
+        let args = bincode::Options::serialize(bincode::options(), &ev)
+            .expect("Failed to encode custom event");
+        ConcreteType::Custom(Custom(E::NAME.into(), args))
+    }
+}
+impl<E: CustomEvent> From<ConcreteType> for Typed<E> {
+    fn from(value: ConcreteType) -> Self {
+        match value {
+            ConcreteType::Custom(Custom(name, args)) if name == E::NAME => Typed(
+                bincode::Options::deserialize(bincode::options(), &args)
+                    .expect("Failed to decode custom event"),
+            ),
+            _ => panic!("Tried to convert an unknown event to a concrete event"),
+        }
+    }
+}
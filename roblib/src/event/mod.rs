@@ -3,6 +3,12 @@ use serde::{de::DeserializeOwned, Serialize};
 pub mod concrete;
 pub use concrete::{ConcreteType, ConcreteValue};
 
+mod control;
+pub use control::ControlChanged;
+
+mod custom;
+pub use custom::{Custom, CustomEvent, CustomValue, Typed};
+
 #[cfg(feature = "roland")]
 pub use crate::roland::event::*;
 
@@ -1,3 +1,4 @@
+pub use crate::gpio::Subscriber;
 use crate::{
     get_servo_pwm_durations,
     gpio::{event::Event, Mode},
@@ -11,10 +12,6 @@ use std::{
     },
 };
 
-pub trait Subscriber: Send + Sync {
-    fn handle(&self, event: Event);
-}
-
 enum Pin {
     Input(InputPin),
     Output(OutputPin),
@@ -1,4 +1,5 @@
 use anyhow::Result;
+use roblib_macro::Describe;
 
 pub mod cmd;
 pub mod event;
@@ -6,7 +7,10 @@ pub mod event;
 #[cfg(feature = "gpio-backend")]
 pub mod backend;
 
-#[derive(Debug, serde::Serialize, serde::Deserialize, Clone, Copy, PartialEq, Eq)]
+#[cfg(feature = "sim")]
+pub mod sim;
+
+#[derive(Describe, Debug, serde::Serialize, serde::Deserialize, Clone, Copy, PartialEq, Eq)]
 pub enum Mode {
     Input,
     Output,
@@ -21,6 +25,11 @@ pub trait Gpio {
     fn pin_mode(&self, pin: u8, mode: Mode) -> Result<()>;
 }
 
+/// Handles the changes of a pin subscribed to in a backend
+pub trait Subscriber: Send + Sync {
+    fn handle(&self, event: event::Event);
+}
+
 pub trait TypedGpio<'p> {
     type O: OutputPin + 'p;
     type I: InputPin + 'p;
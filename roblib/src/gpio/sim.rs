@@ -0,0 +1,162 @@
+//! GPIO pins simulated in software, see [`SimGpio`]
+
+use super::{event::Event, Gpio, Mode, Subscriber};
+use anyhow::Result;
+use std::{
+    collections::HashMap,
+    sync::{mpsc, Arc, RwLock},
+};
+
+struct Pin {
+    mode: Mode,
+    level: bool,
+}
+
+type Handlers = Arc<RwLock<HashMap<u8, Box<dyn Subscriber>>>>;
+
+/// A stand-in for the `SimpleGpioBackend` without a Raspberry Pi.
+///
+/// Output pins can be wired to input pins, which then read what's written to the output,
+/// and send events to their subscribers when it changes.
+/// Unwired input pins read high, like the pulled up ones on the real board.
+/// Output pins can be read too, returning what was last written to them.
+pub struct SimGpio {
+    pins: RwLock<HashMap<u8, Pin>>,
+    /// `(output, input)` pairs
+    wires: Vec<(u8, u8)>,
+    handlers: Handlers,
+    /// Events are handled on their own thread, like the interrupts on the real board
+    events: mpsc::Sender<Event>,
+}
+
+impl SimGpio {
+    /// `wires` are `(output, input)` pairs of pins
+    pub fn new(wires: Vec<(u8, u8)>) -> Self {
+        let handlers: Handlers = Default::default();
+        let (events, rx) = mpsc::channel();
+
+        let handlers_clone = handlers.clone();
+        std::thread::spawn(move || {
+            // until the backend is dropped
+            while let Ok(ev) = rx.recv() {
+                let Event::PinChanged(pin, _) = ev;
+                if let Some(h) = handlers_clone.read().unwrap().get(&pin) {
+                    h.handle(ev);
+                }
+            }
+        });
+
+        Self {
+            pins: Default::default(),
+            wires,
+            handlers,
+            events,
+        }
+    }
+
+    pub fn subscribe(&self, pin: u8, handler: Box<dyn Subscriber>) -> Result<()> {
+        match self.pins.read().unwrap().get(&pin) {
+            Some(Pin {
+                mode: Mode::Input, ..
+            }) => (),
+            _ => anyhow::bail!("Pin {pin} not set up for reading"),
+        }
+
+        let mut handlers = self.handlers.write().unwrap();
+        if handlers.contains_key(&pin) {
+            anyhow::bail!("Event handler already set on pin {pin}!!");
+        }
+        handlers.insert(pin, handler);
+        Ok(())
+    }
+
+    pub fn unsubscribe(&self, pin: u8) -> Result<()> {
+        self.handlers.write().unwrap().remove(&pin);
+        Ok(())
+    }
+
+    /// The level an input pin reads, driven by the output wired to it
+    fn input_level(&self, pins: &HashMap<u8, Pin>, pin: u8) -> bool {
+        self.wires
+            .iter()
+            .filter(|(_, to)| *to == pin)
+            .find_map(|(from, _)| match pins.get(from) {
+                Some(p) if p.mode == Mode::Output => Some(p.level),
+                _ => None,
+            })
+            .unwrap_or(true)
+    }
+
+    fn output_pin<F, R>(&self, pin: u8, f: F) -> Result<R>
+    where
+        F: FnOnce(&mut Pin) -> R,
+    {
+        match self.pins.write().unwrap().get_mut(&pin) {
+            Some(p) if p.mode == Mode::Output => Ok(f(p)),
+            _ => Err(anyhow::anyhow!("Pin {pin} not set up writing")),
+        }
+    }
+}
+
+impl Gpio for SimGpio {
+    fn read_pin(&self, pin: u8) -> Result<bool> {
+        match self.pins.read().unwrap().get(&pin) {
+            Some(p) => Ok(p.level),
+            None => Err(anyhow::anyhow!("Pin {pin} not set up for reading")),
+        }
+    }
+
+    fn write_pin(&self, pin: u8, value: bool) -> Result<()> {
+        self.output_pin(pin, |p| p.level = value)?;
+
+        let mut pins = self.pins.write().unwrap();
+        for &(_, to) in self.wires.iter().filter(|(from, _)| *from == pin) {
+            let Some(p) = pins.get_mut(&to) else {
+                continue;
+            };
+            if p.mode == Mode::Input && p.level != value {
+                p.level = value;
+                // the receiver only goes away with the backend
+                let _ = self.events.send(Event::PinChanged(to, value));
+            }
+        }
+        Ok(())
+    }
+
+    fn pwm(&self, pin: u8, hz: f64, cycle: f64) -> Result<()> {
+        // the signal doesn't go anywhere
+        self.output_pin(pin, |_| {
+            log::debug!("Simulated pwm on pin {pin}: {hz}Hz {cycle}")
+        })
+    }
+
+    fn servo(&self, pin: u8, degree: f64) -> Result<()> {
+        self.output_pin(pin, |_| {
+            log::debug!("Simulated servo on pin {pin}: {degree}")
+        })
+    }
+
+    fn pin_mode(&self, pin: u8, mode: Mode) -> Result<()> {
+        let mut pins = self.pins.write().unwrap();
+        if let Some(p) = pins.get(&pin) {
+            if p.mode == mode {
+                log::warn!("Pin {pin} mode already set to {mode:?}");
+                return Ok(());
+            }
+            log::warn!("Pin {pin} mode switched to {mode:?}");
+        }
+
+        let level = match mode {
+            Mode::Input => self.input_level(&pins, pin),
+            Mode::Output => false,
+        };
+        pins.insert(pin, Pin { mode, level });
+        drop(pins);
+
+        // the inputs it's wired to read low now
+        if mode == Mode::Output {
+            self.write_pin(pin, false)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,118 @@
+//! JSON encoding, for clients that can't deal with bincode or the text format, like browsers.
+//!
+//! Commands and events are tagged with their names in snake case,
+//! their arguments are in the same order as in the other formats, `null` if they don't have any.
+//! Over a WebSocket, like in the other formats, only the commands with a return value are replied to,
+//! unless they're wrapped in an `ack`. Errors are always sent back:
+//!
+//! ```text
+//! -> {"id": 1, "cmd": "move_robot", "args": [0.5, 0.5]}
+//!    (no reply if it worked)
+//! <- {"id": 1, "err": ["Execute", "message"]}
+//!
+//! -> {"id": 2, "cmd": "ack", "args": {"cmd": "move_robot", "args": [0.5, 0.5]}}
+//! <- {"id": 2, "ok": null}
+//!
+//! -> {"id": 3, "cmd": "get_uptime", "args": null}
+//! <- {"id": 3, "ok": {"secs": 12, "nanos": 345}}
+//!
+//! -> {"id": 4, "cmd": "subscribe", "args": {"event": "gpio_pin", "args": 4}}
+//!    (no reply, the events come with its id)
+//! <- {"id": 4, "ok": true}
+//! ```
+//!
+//! Over HTTP the body is a [`Request`], and the response the return value alone, `null` if there's none.
+
+use crate::cmd::{self, Concrete};
+use serde::{Deserialize, Serialize};
+
+/// The name the JSON encoding goes by, as a WebSocket subprotocol or the first message
+pub const PROTOCOL: &str = "json";
+
+/// The MIME type of the JSON encoding over HTTP
+pub const CONTENT_TYPE: &str = "application/json";
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Request {
+    /// Echoed back in the reply, optional over HTTP
+    #[serde(default)]
+    pub id: u32,
+
+    #[serde(flatten)]
+    pub cmd: Concrete,
+}
+
+/// The reply to a command, or an event sent under the id of the command that subscribed to it
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Reply<T> {
+    pub id: u32,
+
+    #[serde(flatten)]
+    pub res: Outcome<T>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum Outcome<T> {
+    Ok(T),
+    Err(cmd::Error),
+}
+
+impl<T> Reply<T> {
+    pub fn ok(id: u32, v: T) -> Self {
+        Self {
+            id,
+            res: Outcome::Ok(v),
+        }
+    }
+
+    pub fn err(id: u32, e: cmd::Error) -> Self {
+        Self {
+            id,
+            res: Outcome::Err(e),
+        }
+    }
+
+    pub fn into_result(self) -> Result<T, cmd::Error> {
+        match self.res {
+            Outcome::Ok(v) => Ok(v),
+            Outcome::Err(e) => Err(e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{cmd, event};
+
+    #[test]
+    fn readable_tags() -> anyhow::Result<()> {
+        let req = Request {
+            id: 2,
+            cmd: cmd::Subscribe(event::Custom("tick".into(), vec![1]).into()).into(),
+        };
+        let s = serde_json::to_string(&req)?;
+        assert_eq!(
+            s,
+            r#"{"id":2,"cmd":"subscribe","args":{"event":"custom","args":["tick",[1]]}}"#
+        );
+
+        // any key order
+        let req: Request = serde_json::from_str(r#"{"args":null,"cmd":"get_uptime","id":3}"#)?;
+        assert!(matches!(req.cmd, Concrete::GetUptime(_)));
+        let req: Request =
+            serde_json::from_str(r#"{"args":{"cmd":"nop","args":null},"cmd":"ack"}"#)?;
+        assert!(matches!(req.cmd, Concrete::Ack(cmd::Ack(c, _)) if matches!(*c, Concrete::Nop(_))));
+
+        let rep: Reply<u32> = serde_json::from_str(&serde_json::to_string(&Reply::ok(4, 42))?)?;
+        assert_eq!(rep.id, 4);
+        assert_eq!(rep.into_result()?, 42);
+
+        let err = cmd::Error::new(cmd::ErrorCode::Execute, "no");
+        let s = serde_json::to_string(&Reply::<()>::err(5, err))?;
+        assert_eq!(s, r#"{"id":5,"err":["Execute","no"]}"#);
+
+        Ok(())
+    }
+}
@@ -2,8 +2,12 @@ extern crate log;
 
 pub mod cmd;
 pub mod event;
+pub mod schema;
 pub mod text_format;
 
+#[cfg(feature = "json")]
+pub mod json;
+
 #[cfg(feature = "camloc")]
 pub mod camloc;
 
@@ -16,7 +20,17 @@ pub mod roland;
 pub trait RoblibBuiltin {
     fn nop(&self) -> anyhow::Result<()>;
     fn get_uptime(&self) -> anyhow::Result<std::time::Duration>;
+    fn get_capabilities(&self) -> anyhow::Result<cmd::Capabilities>;
+    fn get_schema(&self) -> anyhow::Result<schema::Schema>;
     fn abort(&self) -> anyhow::Result<()>;
+
+    fn schedule(&self, when: cmd::When, cmd: cmd::Concrete) -> anyhow::Result<u32>;
+    fn list_scheduled(&self) -> anyhow::Result<Vec<cmd::Scheduled>>;
+    fn cancel_scheduled(&self, id: u32) -> anyhow::Result<bool>;
+
+    fn acquire_control(&self) -> anyhow::Result<std::time::Duration>;
+    fn take_control(&self, admin_token: &str) -> anyhow::Result<std::time::Duration>;
+    fn release_control(&self) -> anyhow::Result<()>;
 }
 
 #[cfg(feature = "async")]
@@ -24,7 +38,17 @@ pub trait RoblibBuiltin {
 pub trait RoblibBuiltinAsync {
     async fn nop(&self) -> anyhow::Result<()>;
     async fn get_uptime(&self) -> anyhow::Result<std::time::Duration>;
+    async fn get_capabilities(&self) -> anyhow::Result<cmd::Capabilities>;
+    async fn get_schema(&self) -> anyhow::Result<schema::Schema>;
     async fn abort(&self) -> anyhow::Result<()>;
+
+    async fn schedule(&self, when: cmd::When, cmd: cmd::Concrete) -> anyhow::Result<u32>;
+    async fn list_scheduled(&self) -> anyhow::Result<Vec<cmd::Scheduled>>;
+    async fn cancel_scheduled(&self, id: u32) -> anyhow::Result<bool>;
+
+    async fn acquire_control(&self) -> anyhow::Result<std::time::Duration>;
+    async fn take_control(&self, admin_token: &str) -> anyhow::Result<std::time::Duration>;
+    async fn release_control(&self) -> anyhow::Result<()>;
 }
 
 #[allow(unused)]
@@ -58,18 +58,72 @@ pub mod constants {
     }
 }
 
+/// Which GPIO pins the parts of the robot are wired to, the [`constants`] by default
+#[derive(Debug, Clone, Copy, serde::Serialize, serde::Deserialize)]
+pub struct Pins {
+    pub fwd_l: u8,
+    pub bwd_l: u8,
+    pub fwd_r: u8,
+    pub bwd_r: u8,
+    pub pwm_l: u8,
+    pub pwm_r: u8,
+
+    pub led_r: u8,
+    pub led_g: u8,
+    pub led_b: u8,
+
+    pub servo: u8,
+    pub buzzer: u8,
+
+    pub track_l1: u8,
+    pub track_l2: u8,
+    pub track_r1: u8,
+    pub track_r2: u8,
+
+    pub echo: u8,
+    pub trig: u8,
+}
+
+impl Default for Pins {
+    fn default() -> Self {
+        use constants::*;
+        Self {
+            fwd_l: motors::FWD_L,
+            bwd_l: motors::BWD_L,
+            fwd_r: motors::FWD_R,
+            bwd_r: motors::BWD_R,
+            pwm_l: motors::PWM_L,
+            pwm_r: motors::PWM_R,
+
+            led_r: led::LED_R,
+            led_g: led::LED_G,
+            led_b: led::LED_B,
+
+            servo: servo::SERVO,
+            buzzer: buzzer::BUZZER,
+
+            track_l1: track_sensor::TRACK_L1,
+            track_l2: track_sensor::TRACK_L2,
+            track_r1: track_sensor::TRACK_R1,
+            track_r2: track_sensor::TRACK_R2,
+
+            echo: ultra_sensor::ECHO,
+            trig: ultra_sensor::TRIG,
+        }
+    }
+}
+
 struct Leds {
     r: OutputPin,
     g: OutputPin,
     b: OutputPin,
 }
 impl Leds {
-    fn new(gpio: &Gpio) -> Result<Self> {
-        use constants::led::*;
+    fn new(gpio: &Gpio, pins: &Pins) -> Result<Self> {
         Ok(Self {
-            r: gpio.get(LED_R)?.into_output_low(),
-            g: gpio.get(LED_G)?.into_output_low(),
-            b: gpio.get(LED_B)?.into_output_low(),
+            r: gpio.get(pins.led_r)?.into_output_low(),
+            g: gpio.get(pins.led_g)?.into_output_low(),
+            b: gpio.get(pins.led_b)?.into_output_low(),
         })
     }
 }
@@ -81,33 +135,27 @@ struct TrackSensor {
     r2: InputPin,
 }
 impl TrackSensor {
-    fn new(gpio: &Gpio) -> Result<Self> {
-        use constants::track_sensor::*;
+    fn new(gpio: &Gpio, pins: &Pins) -> Result<Self> {
         Ok(Self {
-            l1: gpio.get(TRACK_L1)?.into_input(),
-            l2: gpio.get(TRACK_L2)?.into_input(),
-            r1: gpio.get(TRACK_R1)?.into_input(),
-            r2: gpio.get(TRACK_R2)?.into_input(),
+            l1: gpio.get(pins.track_l1)?.into_input(),
+            l2: gpio.get(pins.track_l2)?.into_input(),
+            r1: gpio.get(pins.track_r1)?.into_input(),
+            r2: gpio.get(pins.track_r2)?.into_input(),
         })
     }
 }
 
 struct Buzzer(OutputPin);
 impl Buzzer {
-    pub fn new(gpio: &Gpio) -> Result<Self> {
-        Ok(Self(
-            gpio.get(constants::buzzer::BUZZER)?.into_output_high(),
-        ))
+    pub fn new(gpio: &Gpio, pins: &Pins) -> Result<Self> {
+        Ok(Self(gpio.get(pins.buzzer)?.into_output_high()))
     }
 }
 
 struct Servo(OutputPin, u32);
 impl Servo {
-    pub fn new(gpio: &Gpio) -> Result<Self> {
-        Ok(Self(
-            gpio.get(constants::servo::SERVO)?.into_output_high(),
-            0,
-        ))
+    pub fn new(gpio: &Gpio, pins: &Pins) -> Result<Self> {
+        Ok(Self(gpio.get(pins.servo)?.into_output_high(), 0))
     }
 }
 struct Motors {
@@ -120,16 +168,15 @@ struct Motors {
     pwm_r: OutputPin,
 }
 impl Motors {
-    fn new(gpio: &Gpio) -> Result<Self> {
-        use constants::motors::*;
+    fn new(gpio: &Gpio, pins: &Pins) -> Result<Self> {
         Ok(Self {
-            fwd_l: gpio.get(FWD_L)?.into_output_low(),
-            bwd_l: gpio.get(BWD_L)?.into_output_low(),
-            pwm_l: gpio.get(PWM_L)?.into_output_high(),
+            fwd_l: gpio.get(pins.fwd_l)?.into_output_low(),
+            bwd_l: gpio.get(pins.bwd_l)?.into_output_low(),
+            pwm_l: gpio.get(pins.pwm_l)?.into_output_high(),
 
-            fwd_r: gpio.get(FWD_R)?.into_output_low(),
-            bwd_r: gpio.get(BWD_R)?.into_output_low(),
-            pwm_r: gpio.get(PWM_R)?.into_output_high(),
+            fwd_r: gpio.get(pins.fwd_r)?.into_output_low(),
+            bwd_r: gpio.get(pins.bwd_r)?.into_output_low(),
+            pwm_r: gpio.get(pins.pwm_r)?.into_output_high(),
         })
     }
 }
@@ -140,11 +187,10 @@ struct UltraSensor {
 }
 
 impl UltraSensor {
-    fn new(gpio: &Gpio) -> Result<Self> {
-        use constants::ultra_sensor::{ECHO, TRIG};
+    fn new(gpio: &Gpio, pins: &Pins) -> Result<Self> {
         Ok(Self {
-            echo: gpio.get(ECHO)?.into_input(),
-            trig: gpio.get(TRIG)?.into_output_low(),
+            echo: gpio.get(pins.echo)?.into_input(),
+            trig: gpio.get(pins.trig)?.into_output_low(),
         })
     }
 }
@@ -170,17 +216,22 @@ impl Drop for RolandBackend {
 
 impl RolandBackend {
     pub fn try_init() -> Result<Self> {
+        Self::try_init_with(&Pins::default())
+    }
+
+    /// Same as [`Self::try_init`], for a robot wired differently
+    pub fn try_init_with(pins: &Pins) -> Result<Self> {
         let gpio = Gpio::new()?;
 
         let roland = Self {
-            motor: Motors::new(&gpio)?.into(),
-            servo: Servo::new(&gpio)?.into(),
+            motor: Motors::new(&gpio, pins)?.into(),
+            servo: Servo::new(&gpio, pins)?.into(),
 
-            buzzer: Buzzer::new(&gpio)?.into(),
-            leds: Leds::new(&gpio)?.into(),
+            buzzer: Buzzer::new(&gpio, pins)?.into(),
+            leds: Leds::new(&gpio, pins)?.into(),
 
-            ultra_sensor: UltraSensor::new(&gpio)?.into(),
-            track_sensor: TrackSensor::new(&gpio)?.into(),
+            ultra_sensor: UltraSensor::new(&gpio, pins)?.into(),
+            track_sensor: TrackSensor::new(&gpio, pins)?.into(),
 
             gpio,
         };
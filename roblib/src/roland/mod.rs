@@ -4,6 +4,9 @@ pub mod event;
 #[cfg(feature = "gpio-backend")]
 pub mod backend;
 
+#[cfg(feature = "sim")]
+pub mod sim;
+
 pub trait Roland: Sized {
     fn drive(&self, left: f64, right: f64) -> anyhow::Result<()>;
     fn led(&self, r: bool, g: bool, b: bool) -> anyhow::Result<()>;
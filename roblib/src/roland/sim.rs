@@ -0,0 +1,334 @@
+//! A Roland simulated in software, see [`SimRoland`]
+
+use super::Roland;
+use anyhow::Result;
+use std::{
+    collections::VecDeque,
+    f64::consts::PI,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+/// How far the track sensors are in front of the center of the robot, in meters
+const TRACK_SENSOR_AHEAD: f64 = 0.07;
+/// How far the track sensors are to the side of the center of the robot, in meters, `[l1, l2, r1, r2]`
+const TRACK_SENSOR_SIDE: [f64; 4] = [0.025, 0.005, -0.005, -0.025];
+/// How far the ultrasonic sensor is in front of the center of the robot, in meters
+const ULTRA_SENSOR_AHEAD: f64 = 0.08;
+/// The robot stops this close to a wall, in meters
+const ROBOT_RADIUS: f64 = 0.1;
+/// The longest step the movement is simulated in
+const STEP: Duration = Duration::from_millis(10);
+
+/// The world the simulated robot moves around in.
+/// Lengths are in meters, positions are `[x, y]` with `x` to the right and `y` up,
+/// the area from `[0, 0]` to `[width, height]` is walled in.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct Arena {
+    pub width: f64,
+    pub height: f64,
+    /// `[x, y, heading]`, heading in degrees counterclockwise from the `x` axis
+    pub start: [f64; 3],
+    /// Walls inside the arena, `[x1, y1, x2, y2]`
+    pub walls: Vec<[f64; 4]>,
+    /// The line to follow, `[x1, y1, x2, y2]` segments
+    pub lines: Vec<[f64; 4]>,
+    pub line_width: f64,
+    /// How fast a wheel turns at full power, in meters per second
+    pub max_speed: f64,
+    /// The distance between the wheels
+    pub wheel_base: f64,
+}
+
+impl Default for Arena {
+    /// A 2x2m arena with a 1x1m square line in the middle, the robot starting on it
+    fn default() -> Self {
+        Self {
+            width: 2.,
+            height: 2.,
+            start: [1., 0.5, 0.],
+            walls: vec![],
+            lines: vec![
+                [0.5, 0.5, 1.5, 0.5],
+                [1.5, 0.5, 1.5, 1.5],
+                [1.5, 1.5, 0.5, 1.5],
+                [0.5, 1.5, 0.5, 0.5],
+            ],
+            line_width: 0.02,
+            max_speed: 0.5,
+            wheel_base: 0.15,
+        }
+    }
+}
+
+impl Arena {
+    /// The walls, including the ones around the arena
+    fn all_walls(&self) -> impl Iterator<Item = [f64; 4]> + '_ {
+        let (w, h) = (self.width, self.height);
+        [
+            [0., 0., w, 0.],
+            [w, 0., w, h],
+            [w, h, 0., h],
+            [0., h, 0., 0.],
+        ]
+        .into_iter()
+        .chain(self.walls.iter().copied())
+    }
+
+    fn on_line(&self, p: (f64, f64)) -> bool {
+        self.lines
+            .iter()
+            .any(|l| distance_to_segment(p, l) <= self.line_width / 2.)
+    }
+
+    fn wall_distance(&self, p: (f64, f64)) -> f64 {
+        self.all_walls()
+            .map(|w| distance_to_segment(p, &w))
+            .fold(f64::INFINITY, f64::min)
+    }
+
+    /// The distance from `p` to the closest wall in direction `angle`
+    fn cast(&self, p: (f64, f64), angle: f64) -> f64 {
+        let d = (angle.cos(), angle.sin());
+        self.all_walls()
+            .filter_map(|w| ray_hits_segment(p, d, &w))
+            .fold(f64::INFINITY, f64::min)
+    }
+}
+
+fn distance_to_segment((px, py): (f64, f64), &[x1, y1, x2, y2]: &[f64; 4]) -> f64 {
+    let (dx, dy) = (x2 - x1, y2 - y1);
+    let len2 = dx * dx + dy * dy;
+    let t = if len2 == 0. {
+        0.
+    } else {
+        (((px - x1) * dx + (py - y1) * dy) / len2).clamp(0., 1.)
+    };
+    (px - (x1 + t * dx)).hypot(py - (y1 + t * dy))
+}
+
+/// How far along the ray the segment is, if it's hit at all
+fn ray_hits_segment(
+    (px, py): (f64, f64),
+    (dx, dy): (f64, f64),
+    &[x1, y1, x2, y2]: &[f64; 4],
+) -> Option<f64> {
+    let (sx, sy) = (x2 - x1, y2 - y1);
+    let denom = dx * sy - dy * sx;
+    if denom.abs() < f64::EPSILON {
+        return None;
+    }
+
+    let (qx, qy) = (x1 - px, y1 - py);
+    let t = (qx * sy - qy * sx) / denom;
+    let u = (qx * dy - qy * dx) / denom;
+    (t >= 0. && (0. ..=1.).contains(&u)).then_some(t)
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Pose {
+    pub x: f64,
+    pub y: f64,
+    /// In radians, counterclockwise from the `x` axis
+    pub heading: f64,
+}
+
+struct State {
+    pose: Pose,
+    /// `(left, right)` power, in `[-1, 1]`
+    speed: (f64, f64),
+    /// The pose is up to date until here
+    updated: Instant,
+    /// In degrees
+    servo: f64,
+    /// The track sensor changes not yet polled, if the interrupts are set up.
+    /// Recorded while moving, so the ones between two polls aren't missed.
+    interrupts: Option<VecDeque<(usize, bool)>>,
+    /// The track sensor values the interrupts were last recorded at
+    track_sensor: [bool; 4],
+}
+
+/// A stand-in for the `RolandBackend` without a Raspberry Pi.
+///
+/// The robot drives around an [`Arena`], its sensors see the walls and lines in it.
+/// The movement is simulated lazily, whenever something asks where the robot is.
+pub struct SimRoland {
+    arena: Arena,
+    state: Mutex<State>,
+}
+
+impl SimRoland {
+    pub fn new(arena: Arena) -> Self {
+        let [x, y, heading] = arena.start;
+        let state = State {
+            pose: Pose {
+                x,
+                y,
+                heading: heading.to_radians(),
+            },
+            speed: (0., 0.),
+            updated: Instant::now(),
+            servo: 0.,
+            interrupts: None,
+            track_sensor: [false; 4],
+        };
+
+        Self {
+            arena,
+            state: Mutex::new(state),
+        }
+    }
+
+    /// Where the robot is now
+    pub fn pose(&self) -> Pose {
+        self.update().pose
+    }
+
+    /// Moves the robot to where it is now, returning the state
+    fn update(&self) -> std::sync::MutexGuard<'_, State> {
+        let mut s = self.state.lock().unwrap();
+        let now = Instant::now();
+        let mut left = now - s.updated;
+        s.updated = now;
+
+        let (l, r) = s.speed;
+        if l == 0. && r == 0. {
+            return s;
+        }
+        let (vl, vr) = (l * self.arena.max_speed, r * self.arena.max_speed);
+        let v = (vl + vr) / 2.;
+        let w = (vr - vl) / self.arena.wheel_base;
+
+        while !left.is_zero() {
+            let dt = left.min(STEP);
+            left -= dt;
+            let dt = dt.as_secs_f64();
+
+            let Pose { x, y, heading } = s.pose;
+            let heading2 = heading + w * dt;
+            let (x2, y2) = if w.abs() < 1e-9 {
+                (x + v * dt * heading.cos(), y + v * dt * heading.sin())
+            } else {
+                // moving along an arc
+                let r = v / w;
+                (
+                    x + r * (heading2.sin() - heading.sin()),
+                    y - r * (heading2.cos() - heading.cos()),
+                )
+            };
+
+            // bumped into a wall, only turning or moving away from it is possible
+            let wall = self.arena.wall_distance((x2, y2));
+            if wall < ROBOT_RADIUS && wall < self.arena.wall_distance((x, y)) {
+                s.pose.heading = heading2.rem_euclid(2. * PI);
+                continue;
+            }
+
+            s.pose = Pose {
+                x: x2,
+                y: y2,
+                heading: heading2.rem_euclid(2. * PI),
+            };
+            self.record_interrupts(&mut s);
+        }
+
+        s
+    }
+
+    fn record_interrupts(&self, s: &mut State) {
+        let Some(interrupts) = &mut s.interrupts else {
+            return;
+        };
+
+        let current = self.read_track_sensor(s.pose);
+        for (i, v) in current.into_iter().enumerate() {
+            if v != s.track_sensor[i] {
+                interrupts.push_back((i, v));
+            }
+        }
+        s.track_sensor = current;
+    }
+
+    fn read_track_sensor(&self, pose: Pose) -> [bool; 4] {
+        let (sin, cos) = pose.heading.sin_cos();
+        TRACK_SENSOR_SIDE.map(|side| {
+            let x = pose.x + TRACK_SENSOR_AHEAD * cos - side * sin;
+            let y = pose.y + TRACK_SENSOR_AHEAD * sin + side * cos;
+            self.arena.on_line((x, y))
+        })
+    }
+
+    pub fn setup_tracksensor_interrupts(&self) -> Result<()> {
+        let mut s = self.update();
+        s.track_sensor = self.read_track_sensor(s.pose);
+        s.interrupts = Some(VecDeque::new());
+        Ok(())
+    }
+
+    /// Waits for one of the track sensors to change, like the interrupts on the real board.
+    /// The changes are looked for every few milliseconds.
+    pub fn poll_tracksensor(&self, timeout: Option<Duration>) -> Result<Option<(usize, bool)>> {
+        let start = Instant::now();
+        loop {
+            match self.update().interrupts.as_mut() {
+                Some(interrupts) => {
+                    if let Some(change) = interrupts.pop_front() {
+                        return Ok(Some(change));
+                    }
+                }
+                None => anyhow::bail!("Track sensor interrupts not set up"),
+            }
+
+            if timeout.is_some_and(|t| start.elapsed() >= t) {
+                return Ok(None);
+            }
+            std::thread::sleep(STEP);
+        }
+    }
+
+    pub fn clear_tracksensor_interrupts(&self) -> Result<()> {
+        self.state.lock().unwrap().interrupts = None;
+        Ok(())
+    }
+}
+
+impl Roland for SimRoland {
+    fn drive(&self, left: f64, right: f64) -> Result<()> {
+        let mut s = self.update();
+        s.speed = (left.clamp(-1., 1.), right.clamp(-1., 1.));
+        log::debug!("Simulated robot at {:?} driving {:?}", s.pose, s.speed);
+        Ok(())
+    }
+
+    fn led(&self, r: bool, g: bool, b: bool) -> Result<()> {
+        log::debug!("Simulated leds: {r} {g} {b}");
+        Ok(())
+    }
+
+    fn roland_servo(&self, degree: f64) -> Result<()> {
+        self.state.lock().unwrap().servo = degree.clamp(-90., 90.);
+        Ok(())
+    }
+
+    fn buzzer(&self, pw: f64) -> Result<()> {
+        log::debug!("Simulated buzzer: {pw}");
+        Ok(())
+    }
+
+    fn track_sensor(&self) -> Result<[bool; 4]> {
+        let pose = self.update().pose;
+        Ok(self.read_track_sensor(pose))
+    }
+
+    fn ultra_sensor(&self) -> Result<f64> {
+        let s = self.update();
+        let Pose { x, y, heading } = s.pose;
+        let from = (
+            x + ULTRA_SENSOR_AHEAD * heading.cos(),
+            y + ULTRA_SENSOR_AHEAD * heading.sin(),
+        );
+        // the sensor is mounted on the servo
+        Ok(self.arena.cast(from, heading + s.servo.to_radians()))
+    }
+}
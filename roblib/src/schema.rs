@@ -0,0 +1,329 @@
+//! A machine-readable description of the protocol, for generating clients in other languages.
+//!
+//! Generated from the same registries as [`cmd::Concrete`] and [`event::ConcreteType`],
+//! so it only contains the commands and events compiled in. Servers return theirs with [`cmd::GetSchema`].
+
+use crate::{cmd, event};
+use roblib_macro::Describe;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, HashMap},
+    marker::PhantomData,
+    net::SocketAddr,
+    time::Duration,
+};
+
+/// How a value is encoded, the same in every format unless noted otherwise
+#[derive(Describe, Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Type {
+    Unit,
+    Bool,
+    U8,
+    U16,
+    U32,
+    U64,
+    I8,
+    I16,
+    I32,
+    I64,
+    F32,
+    F64,
+    Char,
+    String,
+    /// An ip address and a port, a string like `127.0.0.1:1110` in human readable formats
+    SocketAddr,
+    Option(Box<Type>),
+    Seq(Box<Type>),
+    /// Keys and values, an object in json
+    Map(Box<Type>, Box<Type>),
+    /// Fixed length, also used for arrays
+    Tuple(Vec<Type>),
+    /// A struct or enum, defined in [`Schema::types`]
+    Named(String),
+    /// Any command, see [`Schema::commands`]
+    Command,
+    /// Any event, see [`Schema::events`]
+    Event,
+    /// Only known at runtime, like the return value of a [`cmd::Batch`]
+    Dynamic,
+}
+
+/// A field of a struct, an enum variant, or the arguments of a command or event.
+/// Tuple structs have no names, only the order of the fields matters.
+#[derive(Describe, Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Field {
+    pub name: Option<String>,
+    pub ty: Type,
+}
+
+#[derive(Describe, Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Variant {
+    pub name: String,
+    pub fields: Vec<Field>,
+}
+
+#[derive(Describe, Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Def {
+    Struct(Vec<Field>),
+    /// Encoded as the index of the variant followed by its fields,
+    /// or tagged with the name of the variant in human readable formats
+    Enum(Vec<Variant>),
+}
+
+#[derive(Describe, Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct TypeDef {
+    pub name: String,
+    pub def: Def,
+}
+
+#[derive(Describe, Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct CommandDef {
+    /// The name used in json, like `move_robot`
+    pub tag: String,
+    pub prefix: char,
+    pub args: Vec<Field>,
+    pub returns: Type,
+    /// Whether the server replies when the command succeeds, see [`cmd::Command::REPLIES`]
+    pub replies: bool,
+    /// The feature of roblib the command is behind
+    pub feature: Option<String>,
+}
+
+impl CommandDef {
+    pub fn new<C: cmd::Command + Signature>(
+        tag: &str,
+        feature: Option<&str>,
+        types: &mut Types,
+    ) -> Self {
+        Self {
+            tag: tag.into(),
+            prefix: C::PREFIX,
+            args: C::args(types),
+            returns: C::returns(types),
+            replies: C::REPLIES,
+            feature: feature.map(Into::into),
+        }
+    }
+}
+
+#[derive(Describe, Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct EventDef {
+    /// The name used in json, like `gpio_pin`
+    pub tag: String,
+    /// The name used in the other formats, see [`event::Event::NAME`]
+    pub name: String,
+    pub args: Vec<Field>,
+    pub item: Type,
+    /// The feature of roblib the event is behind
+    pub feature: Option<String>,
+}
+
+impl EventDef {
+    pub fn new<E: event::Event + Signature>(
+        tag: &str,
+        feature: Option<&str>,
+        types: &mut Types,
+    ) -> Self {
+        Self {
+            tag: tag.into(),
+            name: E::NAME.into(),
+            args: E::args(types),
+            item: E::returns(types),
+            feature: feature.map(Into::into),
+        }
+    }
+}
+
+#[derive(Describe, Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Schema {
+    pub version: u32,
+    pub commands: Vec<CommandDef>,
+    pub events: Vec<EventDef>,
+    /// Every struct and enum used by the commands and events, and [`cmd::Error`]
+    pub types: Vec<TypeDef>,
+}
+
+/// Describes every command and event compiled in
+pub fn export() -> Schema {
+    let mut types = Types::default();
+    let commands = cmd::Concrete::describe(&mut types);
+    let events = event::ConcreteType::describe(&mut types);
+    cmd::Error::describe(&mut types);
+
+    Schema {
+        version: cmd::PROTOCOL_VERSION,
+        commands,
+        events,
+        types: types.0,
+    }
+}
+
+/// The named types collected while describing
+#[derive(Default)]
+pub struct Types(Vec<TypeDef>);
+
+impl Types {
+    /// Defines `name` the first time it's seen, and refers to it by name
+    pub fn define(&mut self, name: &str, def: impl FnOnce(&mut Self) -> Def) -> Type {
+        if !self.0.iter().any(|t| t.name == name) {
+            // reserve the name first, in case the type refers to itself
+            let i = self.0.len();
+            self.0.push(TypeDef {
+                name: name.into(),
+                def: Def::Struct(vec![]),
+            });
+            self.0[i].def = def(self);
+        }
+        Type::Named(name.into())
+    }
+}
+
+/// Implemented by every type used in commands and events, usually with `#[derive(Describe)]`
+pub trait Describe {
+    fn describe(types: &mut Types) -> Type;
+}
+
+/// The arguments of a command or an event, and its return value or item.
+/// Implemented by `#[derive(Command)]` and `#[derive(Event)]`.
+pub trait Signature {
+    fn args(types: &mut Types) -> Vec<Field>;
+    fn returns(types: &mut Types) -> Type;
+}
+
+macro_rules! describe_as {
+    ($($t:ty => $v:ident),* $(,)?) => {
+        $(
+            impl Describe for $t {
+                fn describe(_: &mut Types) -> Type {
+                    Type::$v
+                }
+            }
+        )*
+    };
+}
+
+describe_as! {
+    () => Unit,
+    bool => Bool,
+    u8 => U8,
+    u16 => U16,
+    u32 => U32,
+    u64 => U64,
+    i8 => I8,
+    i16 => I16,
+    i32 => I32,
+    i64 => I64,
+    f32 => F32,
+    f64 => F64,
+    char => Char,
+    String => String,
+    SocketAddr => SocketAddr,
+    cmd::Concrete => Command,
+    event::ConcreteType => Event,
+    event::CustomValue => Dynamic,
+}
+
+macro_rules! describe_tuple {
+    ($($t:ident),+) => {
+        impl<$($t: Describe),+> Describe for ($($t,)+) {
+            fn describe(types: &mut Types) -> Type {
+                Type::Tuple(vec![$($t::describe(types)),+])
+            }
+        }
+    };
+}
+
+describe_tuple!(A);
+describe_tuple!(A, B);
+describe_tuple!(A, B, C);
+describe_tuple!(A, B, C, D);
+describe_tuple!(A, B, C, D, E);
+describe_tuple!(A, B, C, D, E, F);
+
+impl<T: Describe, const N: usize> Describe for [T; N] {
+    fn describe(types: &mut Types) -> Type {
+        let t = T::describe(types);
+        Type::Tuple(vec![t; N])
+    }
+}
+
+impl<T: Describe> Describe for Vec<T> {
+    fn describe(types: &mut Types) -> Type {
+        Type::Seq(Box::new(T::describe(types)))
+    }
+}
+
+impl<K: Describe, V: Describe, S> Describe for HashMap<K, V, S> {
+    fn describe(types: &mut Types) -> Type {
+        Type::Map(Box::new(K::describe(types)), Box::new(V::describe(types)))
+    }
+}
+
+impl<K: Describe, V: Describe> Describe for BTreeMap<K, V> {
+    fn describe(types: &mut Types) -> Type {
+        Type::Map(Box::new(K::describe(types)), Box::new(V::describe(types)))
+    }
+}
+
+impl<T: Describe> Describe for Option<T> {
+    fn describe(types: &mut Types) -> Type {
+        Type::Option(Box::new(T::describe(types)))
+    }
+}
+
+impl<T: Describe> Describe for Box<T> {
+    fn describe(types: &mut Types) -> Type {
+        T::describe(types)
+    }
+}
+
+impl<T> Describe for PhantomData<T> {
+    fn describe(_: &mut Types) -> Type {
+        Type::Unit
+    }
+}
+
+impl Describe for Duration {
+    fn describe(types: &mut Types) -> Type {
+        types.define("Duration", |types| {
+            Def::Struct(vec![
+                Field {
+                    name: Some("secs".into()),
+                    ty: u64::describe(types),
+                },
+                Field {
+                    name: Some("nanos".into()),
+                    ty: u32::describe(types),
+                },
+            ])
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn export_covers_registry() {
+        let schema = export();
+
+        let prefixes: Vec<_> = schema.commands.iter().map(|c| c.prefix).collect();
+        assert_eq!(prefixes, cmd::Concrete::PREFIXES);
+        let names: Vec<_> = schema.events.iter().map(|e| &e.name[..]).collect();
+        assert_eq!(names, event::ConcreteType::NAMES);
+
+        // every named type is defined
+        let s = format!("{schema:?}");
+        for name in s.split("Named(\"").skip(1) {
+            let name = name.split('"').next().unwrap();
+            assert!(schema.types.iter().any(|t| t.name == name), "{name}");
+        }
+
+        // goes over the wire like any other return value
+        let text = crate::text_format::ser::to_string(&schema).unwrap();
+        assert_eq!(schema, crate::text_format::de::from_str(&text).unwrap());
+    }
+}
@@ -1,18 +1,27 @@
 use crate::cmd::SEPARATOR;
 
-use super::error::{Error, Result};
+use super::error::{CommandPosition, Error, Position, Result};
+use crate::cmd::Concrete;
 use serde::{
     de::{self, DeserializeSeed, EnumAccess, MapAccess, SeqAccess, VariantAccess},
     Deserialize,
 };
-
-pub struct Deserializer<I> {
-    iter: I,
+use std::str::FromStr;
+
+pub struct Deserializer<'de> {
+    /// What's left of the input, `None` once the last token is read
+    rest: Option<&'de str>,
+    /// The number of tokens read so far
+    read: usize,
+    /// The commands being parsed, the token their prefix is at and the prefix once it's read
+    commands: Vec<(usize, Option<char>)>,
 }
 
-pub fn new_deserializer(s: &str) -> Deserializer<std::str::Split<'_, char>> {
+pub fn new_deserializer(s: &str) -> Deserializer<'_> {
     Deserializer {
-        iter: s.split(SEPARATOR),
+        rest: Some(s),
+        read: 0,
+        commands: Vec::new(),
     }
 }
 
@@ -22,33 +31,181 @@ where
 {
     let mut deserializer = new_deserializer(s);
 
-    let t = T::deserialize(&mut deserializer)?;
-    if deserializer.iter.next().is_none() {
+    let t = T::deserialize(&mut deserializer).map_err(|e| deserializer.locate(e))?;
+    if deserializer.rest.is_none() {
         Ok(t)
     } else {
-        Err(Error::Trailing)
+        deserializer.read += 1;
+        Err(deserializer.locate(Error::Trailing))
     }
 }
 
-impl<'de, I: Iterator<Item = &'de str>> Deserializer<I> {
-    fn next(&mut self) -> Result<I::Item> {
-        let v = self.iter.next();
+impl<'de> Deserializer<'de> {
+    /// Splits off the next token, the same as splitting the input at every separator
+    fn token(&mut self) -> Option<&'de str> {
+        let rest = self.rest?;
+        Some(match rest.split_once(SEPARATOR) {
+            Some((token, rest)) => {
+                self.rest = Some(rest);
+                token
+            }
+            None => {
+                self.rest = None;
+                rest
+            }
+        })
+    }
+
+    fn next(&mut self) -> Result<&'de str> {
+        let v = self.token();
+        self.read += 1;
+
+        if let (Some(s), Some((_, prefix @ None))) = (v, self.commands.last_mut()) {
+            *prefix = s.chars().next();
+        }
+
         match v {
             Some("") => Err(Error::Empty),
             Some(s) => Ok(s),
             None => Err(Error::MissingArgument),
         }
     }
+
+    /// A `"quoted string"` if the next token starts with a quote,
+    /// `\"`, `\\`, `\n`, `\r` and `\t` are escaped
+    fn quoted(&mut self) -> Result<Option<String>> {
+        let Some(rest) = self.rest.filter(|r| r.starts_with('"')) else {
+            return Ok(None);
+        };
+        self.read += 1;
+
+        let mut s = String::new();
+        let mut chars = rest.char_indices().skip(1);
+        while let Some((i, c)) = chars.next() {
+            match c {
+                '"' => {
+                    let after = &rest[i + 1..];
+                    self.rest = match after.strip_prefix(SEPARATOR) {
+                        Some(after) => Some(after),
+                        None if after.is_empty() => None,
+                        None => return Err(Error::Trailing),
+                    };
+                    return Ok(Some(s));
+                }
+                '\\' => s.push(match chars.next() {
+                    Some((_, 'n')) => '\n',
+                    Some((_, 'r')) => '\r',
+                    Some((_, 't')) => '\t',
+                    Some((_, c @ ('"' | '\\'))) => c,
+                    Some((_, c)) => return Err(Error::Escape(c)),
+                    None => break,
+                }),
+                c => s.push(c),
+            }
+        }
+
+        Err(Error::Unterminated)
+    }
+
+    /// The index of the field if the next token is `field=value`, consuming `field=`
+    fn keyword(&mut self, fields: &[&str], consume: bool) -> Option<usize> {
+        let (key, value) = self.rest?.split_once('=')?;
+        let i = fields.iter().position(|f| *f == key)?;
+        if consume {
+            self.rest = Some(value);
+        }
+        Some(i)
+    }
+
+    /// The length of a sequence or map, `None` if it's terminated instead, starting with `open`
+    fn length(&mut self, open: &str) -> Result<Option<usize>> {
+        match self.next()? {
+            t if t == open => Ok(None),
+            t => t.parse().map(Some).map_err(|_| Error::Parse {
+                expected: "length",
+                got: t.into(),
+            }),
+        }
+    }
+
+    /// Consumes the next token if it's `close`
+    fn close(&mut self, close: &str) -> bool {
+        let next = self
+            .rest
+            .map(|r| r.split_once(SEPARATOR).map_or(r, |(t, _)| t));
+        if next == Some(close) {
+            let _ = self.next();
+        }
+        next == Some(close)
+    }
+
+    fn parse<T: FromStr>(&mut self, expected: &'static str) -> Result<T> {
+        let s = self.next()?;
+        s.parse().map_err(|_| Error::Parse {
+            expected,
+            got: s.into(),
+        })
+    }
+
+    /// Adds the last token read, and the innermost command it's in to `e`
+    fn locate(&self, e: Error) -> Error {
+        let token = self.read.saturating_sub(1);
+        let command = match self.commands.last() {
+            Some(&(start, Some(prefix))) => Some(CommandPosition {
+                prefix,
+                name: Concrete::name_of(prefix),
+                arg: token - start,
+            }),
+            _ => None,
+        };
+
+        Error::At(Position { token, command }, Box::new(e))
+    }
 }
 
-impl<'de, 'a, I: Iterator<Item = &'de str>> de::Deserializer<'de> for &'a mut Deserializer<I> {
+impl<'de, 'a> de::Deserializer<'de> for &'a mut Deserializer<'de> {
     type Error = Error;
 
-    fn deserialize_any<V>(self, _: V) -> Result<V::Value>
+    /// positional, without any names or tags, unlike json
+    fn is_human_readable(&self) -> bool {
+        false
+    }
+
+    /// Guesses the type from the tokens, for types like `serde_json::Value`.
+    /// Sequences and maps have to be written with brackets, and strings quoted to contain spaces,
+    /// which is how the serializer writes them inside a [`SelfDescribing`](super::SelfDescribing).
+    fn deserialize_any<V>(self, visitor: V) -> Result<V::Value>
     where
         V: de::Visitor<'de>,
     {
-        Err(Error::DeserializeAny)
+        if let Some(s) = self.quoted()? {
+            return visitor.visit_string(s);
+        }
+
+        match self.next()? {
+            "[" => visitor.visit_seq(Terminated {
+                de: self,
+                close: "]",
+            }),
+            "{" => visitor.visit_map(Terminated {
+                de: self,
+                close: "}",
+            }),
+            "null" => visitor.visit_unit(),
+            "true" => visitor.visit_bool(true),
+            "false" => visitor.visit_bool(false),
+            t => {
+                if let Ok(v) = t.parse() {
+                    visitor.visit_u64(v)
+                } else if let Ok(v) = t.parse() {
+                    visitor.visit_i64(v)
+                } else if let Ok(v) = t.parse() {
+                    visitor.visit_f64(v)
+                } else {
+                    visitor.visit_borrowed_str(t)
+                }
+            }
+        }
     }
 
     fn deserialize_bool<V>(self, visitor: V) -> Result<V::Value>
@@ -57,9 +214,14 @@ impl<'de, 'a, I: Iterator<Item = &'de str>> de::Deserializer<'de> for &'a mut De
     {
         let next = self.next()?;
         visitor.visit_bool(match next {
-            "0" => false,
-            "1" => true,
-            _ => return Err(Error::Parse("bool")),
+            "0" | "false" => false,
+            "1" | "true" => true,
+            got => {
+                return Err(Error::Parse {
+                    expected: "bool",
+                    got: got.into(),
+                })
+            }
         })
     }
 
@@ -67,82 +229,97 @@ impl<'de, 'a, I: Iterator<Item = &'de str>> de::Deserializer<'de> for &'a mut De
     where
         V: de::Visitor<'de>,
     {
-        visitor.visit_i8(self.next()?.parse().map_err(|_| Error::Parse("i8"))?)
+        visitor.visit_i8(self.parse("i8")?)
     }
 
     fn deserialize_i16<V>(self, visitor: V) -> Result<V::Value>
     where
         V: de::Visitor<'de>,
     {
-        visitor.visit_i16(self.next()?.parse().map_err(|_| Error::Parse("i16"))?)
+        visitor.visit_i16(self.parse("i16")?)
     }
 
     fn deserialize_i32<V>(self, visitor: V) -> Result<V::Value>
     where
         V: de::Visitor<'de>,
     {
-        visitor.visit_i32(self.next()?.parse().map_err(|_| Error::Parse("i32"))?)
+        visitor.visit_i32(self.parse("i32")?)
     }
 
     fn deserialize_i64<V>(self, visitor: V) -> Result<V::Value>
     where
         V: de::Visitor<'de>,
     {
-        visitor.visit_i64(self.next()?.parse().map_err(|_| Error::Parse("i64"))?)
+        visitor.visit_i64(self.parse("i64")?)
     }
 
     fn deserialize_u8<V>(self, visitor: V) -> Result<V::Value>
     where
         V: de::Visitor<'de>,
     {
-        visitor.visit_u8(self.next()?.parse().map_err(|_| Error::Parse("u8"))?)
+        visitor.visit_u8(self.parse("u8")?)
     }
 
     fn deserialize_u16<V>(self, visitor: V) -> Result<V::Value>
     where
         V: de::Visitor<'de>,
     {
-        visitor.visit_u16(self.next()?.parse().map_err(|_| Error::Parse("u16"))?)
+        visitor.visit_u16(self.parse("u16")?)
     }
 
     fn deserialize_u32<V>(self, visitor: V) -> Result<V::Value>
     where
         V: de::Visitor<'de>,
     {
-        visitor.visit_u32(self.next()?.parse().map_err(|_| Error::Parse("u32"))?)
+        visitor.visit_u32(self.parse("u32")?)
     }
 
     fn deserialize_u64<V>(self, visitor: V) -> Result<V::Value>
     where
         V: de::Visitor<'de>,
     {
-        visitor.visit_u64(self.next()?.parse().map_err(|_| Error::Parse("u64"))?)
+        visitor.visit_u64(self.parse("u64")?)
     }
 
     fn deserialize_f32<V>(self, visitor: V) -> Result<V::Value>
     where
         V: de::Visitor<'de>,
     {
-        visitor.visit_f32(self.next()?.parse().map_err(|_| Error::Parse("f32"))?)
+        visitor.visit_f32(self.parse("f32")?)
     }
 
     fn deserialize_f64<V>(self, visitor: V) -> Result<V::Value>
     where
         V: de::Visitor<'de>,
     {
-        visitor.visit_f64(self.next()?.parse().map_err(|_| Error::Parse("f64"))?)
+        visitor.visit_f64(self.parse("f64")?)
     }
 
     fn deserialize_char<V>(self, visitor: V) -> Result<V::Value>
     where
         V: de::Visitor<'de>,
     {
-        let mut cs = self.next()?.chars();
-        let c = cs.next().ok_or(Error::Empty)?;
-        if cs.next().is_some() {
-            return Err(Error::Trailing)?;
+        // a bare `"` is the compact form of the quote itself, a quoted char has to fit in its own token,
+        // like `"a"` or `"\""`, otherwise it'd swallow the arguments after a bare one
+        let (rest, read) = (self.rest, self.read);
+        let after = rest
+            .and_then(|r| r.split_once(SEPARATOR))
+            .map(|(_, after)| after);
+        match self.quoted() {
+            Ok(Some(s)) if self.rest.map(str::len) == after.map(str::len) => {
+                let mut cs = s.chars();
+                return match (cs.next(), cs.next()) {
+                    (Some(c), None) => visitor.visit_char(c),
+                    _ => Err(Error::Parse {
+                        expected: "char",
+                        got: s,
+                    }),
+                };
+            }
+            Ok(None) => (),
+            _ => (self.rest, self.read) = (rest, read),
         }
-        visitor.visit_char(c)
+        visitor.visit_char(self.parse("char")?)
     }
 
     fn deserialize_str<V>(self, visitor: V) -> Result<V::Value>
@@ -156,7 +333,11 @@ impl<'de, 'a, I: Iterator<Item = &'de str>> de::Deserializer<'de> for &'a mut De
     where
         V: de::Visitor<'de>,
     {
-        let spaces: usize = self.next()?.parse().map_err(|_| Error::Parse("usize"))?;
+        if let Some(s) = self.quoted()? {
+            return visitor.visit_string(s);
+        }
+
+        let spaces: usize = self.parse("string length")?;
 
         let mut next_str = || match self.next() {
             Ok(v) => Ok(v),
@@ -203,7 +384,10 @@ impl<'de, 'a, I: Iterator<Item = &'de str>> de::Deserializer<'de> for &'a mut De
         match self.next()? {
             "0" => visitor.visit_none(),
             "1" => visitor.visit_some(self),
-            _ => Err(Error::Parse("Option")),
+            got => Err(Error::Parse {
+                expected: "option",
+                got: got.into(),
+            }),
         }
     }
 
@@ -232,10 +416,13 @@ impl<'de, 'a, I: Iterator<Item = &'de str>> de::Deserializer<'de> for &'a mut De
     where
         V: de::Visitor<'de>,
     {
-        visitor.visit_seq(Seq {
-            left: self.next()?.parse().map_err(|_| Error::Parse("usize"))?,
-            de: self,
-        })
+        match self.length("[")? {
+            Some(left) => visitor.visit_seq(Seq { de: self, left }),
+            None => visitor.visit_seq(Terminated {
+                de: self,
+                close: "]",
+            }),
+        }
     }
 
     fn deserialize_tuple<V>(self, len: usize, visitor: V) -> Result<V::Value>
@@ -267,37 +454,59 @@ impl<'de, 'a, I: Iterator<Item = &'de str>> de::Deserializer<'de> for &'a mut De
     where
         V: de::Visitor<'de>,
     {
-        visitor.visit_map(Seq {
-            left: self.next()?.parse().map_err(|_| Error::Parse("usize"))?,
-            de: self,
-        })
+        match self.length("{")? {
+            Some(left) => visitor.visit_map(Seq { de: self, left }),
+            None => visitor.visit_map(Terminated {
+                de: self,
+                close: "}",
+            }),
+        }
     }
 
     fn deserialize_struct<V>(
         self,
-        _: &'static str,
+        name: &'static str,
         fields: &'static [&'static str],
         visitor: V,
     ) -> Result<V::Value>
     where
         V: de::Visitor<'de>,
     {
-        visitor.visit_seq(Seq {
-            de: self,
-            left: fields.len(),
-        })
+        // keep track of the command for errors, only popped if it succeeds
+        let command = name == "Concrete";
+        if command {
+            self.commands.push((self.read, None));
+        }
+
+        let v = if !command && self.keyword(fields, false).is_some() {
+            visitor.visit_map(Keywords {
+                de: &mut *self,
+                fields,
+                seen: vec![false; fields.len()],
+            })?
+        } else {
+            visitor.visit_seq(Seq {
+                de: &mut *self,
+                left: fields.len(),
+            })?
+        };
+
+        if command {
+            self.commands.pop();
+        }
+        Ok(v)
     }
 
     fn deserialize_enum<V>(
         self,
         _: &'static str,
-        _: &'static [&'static str],
+        variants: &'static [&'static str],
         visitor: V,
     ) -> Result<V::Value>
     where
         V: de::Visitor<'de>,
     {
-        visitor.visit_enum(Enum { de: self })
+        visitor.visit_enum(Enum { de: self, variants })
     }
 
     fn deserialize_identifier<V>(self, _: V) -> Result<V::Value>
@@ -307,20 +516,20 @@ impl<'de, 'a, I: Iterator<Item = &'de str>> de::Deserializer<'de> for &'a mut De
         Err(Error::DeserializeAny)
     }
 
-    fn deserialize_ignored_any<V>(self, _: V) -> Result<V::Value>
+    fn deserialize_ignored_any<V>(self, visitor: V) -> Result<V::Value>
     where
         V: de::Visitor<'de>,
     {
-        Err(Error::DeserializeAny)
+        self.deserialize_any(visitor)
     }
 }
 
-struct Seq<'de: 'a, 'a, I: Iterator<Item = &'de str>> {
-    de: &'a mut Deserializer<I>,
+struct Seq<'de: 'a, 'a> {
+    de: &'a mut Deserializer<'de>,
     left: usize,
 }
 
-impl<'de, 'a, I: Iterator<Item = &'de str>> SeqAccess<'de> for Seq<'de, 'a, I> {
+impl<'de, 'a> SeqAccess<'de> for Seq<'de, 'a> {
     type Error = Error;
 
     fn next_element_seed<T>(&mut self, seed: T) -> Result<Option<T::Value>>
@@ -342,7 +551,7 @@ impl<'de, 'a, I: Iterator<Item = &'de str>> SeqAccess<'de> for Seq<'de, 'a, I> {
     }
 }
 
-impl<'de, 'a, I: Iterator<Item = &'de str>> MapAccess<'de> for Seq<'de, 'a, I> {
+impl<'de, 'a> MapAccess<'de> for Seq<'de, 'a> {
     type Error = Error;
 
     fn next_key_seed<K>(&mut self, seed: K) -> Result<Option<K::Value>>
@@ -369,11 +578,96 @@ impl<'de, 'a, I: Iterator<Item = &'de str>> MapAccess<'de> for Seq<'de, 'a, I> {
     }
 }
 
-struct Enum<'de: 'a, 'a, I: Iterator<Item = &'de str>> {
-    de: &'a mut Deserializer<I>,
+/// A sequence or map of unknown length, ended by `close`
+struct Terminated<'de: 'a, 'a> {
+    de: &'a mut Deserializer<'de>,
+    close: &'static str,
+}
+
+impl<'de, 'a> SeqAccess<'de> for Terminated<'de, 'a> {
+    type Error = Error;
+
+    fn next_element_seed<T>(&mut self, seed: T) -> Result<Option<T::Value>>
+    where
+        T: DeserializeSeed<'de>,
+    {
+        if self.de.close(self.close) {
+            return Ok(None);
+        }
+        seed.deserialize(&mut *self.de).map(Some)
+    }
+}
+
+impl<'de, 'a> MapAccess<'de> for Terminated<'de, 'a> {
+    type Error = Error;
+
+    fn next_key_seed<K>(&mut self, seed: K) -> Result<Option<K::Value>>
+    where
+        K: DeserializeSeed<'de>,
+    {
+        if self.de.close(self.close) {
+            return Ok(None);
+        }
+        seed.deserialize(&mut *self.de).map(Some)
+    }
+
+    fn next_value_seed<V>(&mut self, seed: V) -> Result<V::Value>
+    where
+        V: DeserializeSeed<'de>,
+    {
+        seed.deserialize(&mut *self.de)
+    }
+}
+
+/// A struct starting with a `name=value` field, the fields without names are read in order
+struct Keywords<'de: 'a, 'a> {
+    de: &'a mut Deserializer<'de>,
+    fields: &'static [&'static str],
+    seen: Vec<bool>,
+}
+
+impl<'de, 'a> MapAccess<'de> for Keywords<'de, 'a> {
+    type Error = Error;
+
+    fn next_key_seed<K>(&mut self, seed: K) -> Result<Option<K::Value>>
+    where
+        K: DeserializeSeed<'de>,
+    {
+        let Some(next) = self.seen.iter().position(|s| !s) else {
+            return Ok(None);
+        };
+
+        let i = self.de.keyword(self.fields, true).unwrap_or(next);
+        if std::mem::replace(&mut self.seen[i], true) {
+            // point at the duplicate, its value isn't read
+            self.de.read += 1;
+            return Err(de::Error::duplicate_field(self.fields[i]));
+        }
+
+        seed.deserialize(de::value::BorrowedStrDeserializer::<Error>::new(
+            self.fields[i],
+        ))
+        .map(Some)
+    }
+
+    fn next_value_seed<V>(&mut self, seed: V) -> Result<V::Value>
+    where
+        V: DeserializeSeed<'de>,
+    {
+        seed.deserialize(&mut *self.de)
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.seen.iter().filter(|s| !**s).count())
+    }
+}
+
+struct Enum<'de: 'a, 'a> {
+    de: &'a mut Deserializer<'de>,
+    variants: &'static [&'static str],
 }
 
-impl<'de, 'a, I: Iterator<Item = &'de str>> EnumAccess<'de> for Enum<'de, 'a, I> {
+impl<'de, 'a> EnumAccess<'de> for Enum<'de, 'a> {
     type Error = Error;
 
     type Variant = Self;
@@ -382,14 +676,24 @@ impl<'de, 'a, I: Iterator<Item = &'de str>> EnumAccess<'de> for Enum<'de, 'a, I>
     where
         V: DeserializeSeed<'de>,
     {
-        let var = self.de.next()?.parse().map_err(|_| Error::Parse("usize"))?;
-        let des = de::value::UsizeDeserializer::<Error>::new(var);
-        let v = seed.deserialize(des)?;
+        // the index, or the name in any case
+        let token = self.de.next()?;
+        let v = match token.parse() {
+            Ok(i) => seed.deserialize(de::value::UsizeDeserializer::<Error>::new(i))?,
+            Err(_) => {
+                let name = self
+                    .variants
+                    .iter()
+                    .find(|v| v.eq_ignore_ascii_case(token))
+                    .ok_or_else(|| <Error as de::Error>::unknown_variant(token, self.variants))?;
+                seed.deserialize(de::value::BorrowedStrDeserializer::<Error>::new(name))?
+            }
+        };
         Ok((v, self))
     }
 }
 
-impl<'de, 'a, I: Iterator<Item = &'de str>> VariantAccess<'de> for Enum<'de, 'a, I> {
+impl<'de, 'a> VariantAccess<'de> for Enum<'de, 'a> {
     type Error = Error;
 
     fn unit_variant(self) -> Result<()> {
@@ -13,12 +13,52 @@ pub enum Error {
     Message(String),
     MissingArgument,
     FormatterError(fmt::Error),
-    UnsizedSeq,
-    UnsizedMap,
     DeserializeAny,
-    Parse(&'static str),
+    Parse {
+        expected: &'static str,
+        got: String,
+    },
     Trailing,
     Empty,
+    /// A quoted string without the closing quote
+    Unterminated,
+    Escape(char),
+    /// Where in the input the error happened, added by [`from_str`](super::de::from_str)
+    At(Position, Box<Error>),
+}
+
+/// The token an error happened at, and the command it belongs to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Position {
+    /// Counted from 0, the id of a websocket message included
+    pub token: usize,
+    pub command: Option<CommandPosition>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CommandPosition {
+    pub prefix: char,
+    /// The name of the command, `None` if the prefix is unknown
+    pub name: Option<&'static str>,
+    /// The token's place in the command, 0 being the prefix
+    pub arg: usize,
+}
+
+impl Error {
+    /// The error without its position
+    pub fn kind(&self) -> &Error {
+        match self {
+            Error::At(_, e) => e.kind(),
+            e => e,
+        }
+    }
+
+    pub fn position(&self) -> Option<&Position> {
+        match self {
+            Error::At(p, _) => Some(p),
+            _ => None,
+        }
+    }
 }
 
 impl ser::Error for Error {
@@ -39,12 +79,29 @@ impl Display for Error {
             Error::Message(msg) => formatter.write_str(msg),
             Error::MissingArgument => formatter.write_str("missing argument"),
             Error::FormatterError(e) => formatter.write_str(&e.to_string()),
-            Error::UnsizedSeq => formatter.write_str("sequence length not specified"),
-            Error::UnsizedMap => formatter.write_str("map length not specified"),
             Error::DeserializeAny => formatter.write_str("can't deserialize arbitrary data"),
-            Error::Parse(ty) => formatter.write_fmt(format_args!("failed to parse {ty}")),
+            Error::Parse { expected, got } => write!(formatter, "expected {expected}, got `{got}`"),
             Error::Trailing => formatter.write_str("found trailing characters"),
             Error::Empty => formatter.write_str("empty argument"),
+            Error::Unterminated => formatter.write_str("unterminated string"),
+            Error::Escape(c) => write!(formatter, "unknown escape sequence `\\{c}`"),
+            Error::At(pos, e) => write!(formatter, "{pos}: {e}"),
+        }
+    }
+}
+
+impl Display for Position {
+    /// ``arg 2 of `m` (MoveRobot)``, or `token 0` outside of a command
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.command {
+            Some(CommandPosition { prefix, arg: 0, .. }) => write!(f, "prefix `{prefix}`"),
+            Some(CommandPosition {
+                prefix,
+                name: Some(name),
+                arg,
+            }) => write!(f, "arg {arg} of `{prefix}` ({name})"),
+            Some(CommandPosition { prefix, arg, .. }) => write!(f, "arg {arg} of `{prefix}`"),
+            None => write!(f, "token {}", self.token),
         }
     }
 }
@@ -2,6 +2,32 @@ pub mod de;
 pub mod error;
 pub mod ser;
 
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+/// The name [`SelfDescribing`] serializes as, the serializer switches forms when it sees it
+const SELF_DESCRIBING: &str = "$roblib::text_format::SelfDescribing";
+
+/// Writes its value in the bracketed and quoted form `deserialize_any` can read back,
+/// instead of the compact one, where `null` is nothing and a length looks like any other number.
+/// For values that only know how to deserialize with `deserialize_any`, like `serde_json::Value`:
+/// `[1, "a b"]` is written as `[ 1 "a b" ]` instead of `2 1 1 a b`.
+///
+/// Other formats see it as the value itself.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SelfDescribing<T>(pub T);
+
+impl<T: Serialize> Serialize for SelfDescribing<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_newtype_struct(SELF_DESCRIBING, &self.0)
+    }
+}
+
+impl<'de, T: Deserialize<'de>> Deserialize<'de> for SelfDescribing<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        T::deserialize(deserializer).map(Self)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use std::time::Duration;
@@ -55,6 +81,21 @@ mod tests {
             Err(Some(Ads(random(), random()))),
         ])?;
 
+        m::<(u32, Result<f64, cmd::Error>)>(&(1, Ok(random())))?;
+        m::<(u32, Result<f64, cmd::Error>)>(&(
+            2,
+            Err(cmd::Error::new(cmd::ErrorCode::Execute, "invalid pin 42")),
+        ))?;
+
+        m(&cmd::Capabilities {
+            version: cmd::PROTOCOL_VERSION,
+            features: vec!["gpio".into(), "backend".into()],
+            backends: vec![],
+            transports: vec!["tcp".into(), "ws".into()],
+            commands: Concrete::PREFIXES.to_vec(),
+            events: ConcreteType::NAMES.iter().map(|e| e.to_string()).collect(),
+        })?;
+
         Ok(())
     }
 
@@ -69,6 +110,7 @@ mod tests {
                 ConcreteType::CamlocDisconnect(event::CamlocDisconnect),
                 ConcreteType::CamlocPosition(event::CamlocPosition),
                 ConcreteType::CamlocInfoUpdate(event::CamlocInfoUpdate),
+                ConcreteType::ControlChanged(event::ControlChanged),
             ];
 
             for c in cs {
@@ -113,7 +155,25 @@ mod tests {
                 Concrete::Unsubscribe(cmd::Unsubscribe(event::GpioPin(random()).into())),
                 Concrete::Nop(cmd::Nop),
                 Concrete::GetUptime(cmd::GetUptime),
+                Concrete::GetCapabilities(cmd::GetCapabilities),
+                Concrete::GetSchema(cmd::GetSchema),
                 Concrete::Abort(cmd::Abort),
+                Concrete::Batch(cmd::Batch::new(vec![
+                    Concrete::Led(cmd::Led(random(), random(), random())),
+                    Concrete::MoveRobot(cmd::MoveRobot(random(), random())),
+                    Concrete::ReadPin(cmd::ReadPin(random())),
+                ])),
+                Concrete::Schedule(cmd::Schedule::new(
+                    cmd::When::After(Duration::from_secs_f64(random())),
+                    cmd::Led(random(), random(), random()),
+                )),
+                Concrete::Ack(cmd::Ack::new(cmd::Led(random(), random(), random()))),
+                Concrete::ListScheduled(cmd::ListScheduled),
+                Concrete::CancelScheduled(cmd::CancelScheduled(random())),
+                Concrete::AcquireControl(cmd::AcquireControl),
+                Concrete::TakeControl(cmd::TakeControl("admin token".into())),
+                Concrete::ReleaseControl(cmd::ReleaseControl),
+                Concrete::Heartbeat(cmd::Heartbeat),
             ];
 
             for c in cs {
@@ -128,4 +188,197 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn errors_point_at_the_argument() {
+        fn err<T: serde::de::DeserializeOwned + std::fmt::Debug>(s: &str) -> String {
+            super::de::from_str::<T>(s).unwrap_err().to_string()
+        }
+
+        assert_eq!(
+            err::<Concrete>("m 0.5 fast"),
+            "arg 2 of `m` (MoveRobot): expected f64, got `fast`"
+        );
+        assert_eq!(
+            err::<Concrete>("m 0.5"),
+            "arg 2 of `m` (MoveRobot): missing argument"
+        );
+        assert_eq!(
+            err::<Concrete>("B 2 m 1 1 r x"),
+            "arg 1 of `r` (ReadPin): expected u8, got `x`"
+        );
+        assert_eq!(
+            err::<(u32, Concrete)>("1x m 1 1"),
+            "token 0: expected u32, got `1x`"
+        );
+        assert_eq!(
+            err::<(u32, Concrete)>("3 p 1 5"),
+            "arg 2 of `p` (PinMode): invalid value: integer `5`, expected variant index 0 <= i < 2"
+        );
+        assert!(err::<Concrete>("~ 1").starts_with("prefix `~`: "));
+        assert_eq!(err::<Concrete>("s 1"), "token 1: found trailing characters");
+    }
+
+    #[test]
+    fn friendly_syntax() {
+        fn same(friendly: &str, compact: &str) {
+            let a: Concrete = super::de::from_str(friendly).unwrap();
+            let b: Concrete = super::de::from_str(compact).unwrap();
+            assert_eq!(format!("{a:?}"), format!("{b:?}"), "{friendly}");
+        }
+
+        same("p 4 input", "p 4 0");
+        same("p 4 OUTPUT", "p 4 1");
+        same("w 3 true", "w 3 1");
+        same("l false true false", "l 0 1 0");
+        same(r#"$ "hello world" 2 1 2"#, "$ 1 hello world 2 1 2");
+        same(r#"$ "say \"hi\"\n" 0"#, "$ 1 say \"hi\"\n 0");
+        same(r#"$ "" 0"#, "$ 0  0");
+        same("T after secs=1 nanos=500 m 1 1", "T 0 1 500 m 1 1");
+        same("T at nanos=500 secs=1 s", "T 1 1 500 s");
+        same("T at secs=1 500 s", "T 1 1 500 s");
+
+        let err = |s| super::de::from_str::<Concrete>(s).unwrap_err().to_string();
+        assert_eq!(
+            err("p 4 sideways"),
+            "arg 2 of `p` (PinMode): unknown variant `sideways`, expected `Input` or `Output`"
+        );
+        assert_eq!(
+            err(r#"$ "open 0"#),
+            "arg 1 of `$` (Custom): unterminated string"
+        );
+        assert_eq!(
+            err("T at secs=1 secs=2 s"),
+            "arg 3 of `T` (Schedule): duplicate field `secs`"
+        );
+    }
+
+    #[test]
+    fn quote_char_roundtrip() -> anyhow::Result<()> {
+        for v in [('"', 1u8), ('a', 2), ('\\', 3)] {
+            let s = super::ser::to_string(&v)?;
+            assert_eq!(super::de::from_str::<(char, u8)>(&s)?, v, "{s}");
+        }
+        assert_eq!(super::de::from_str::<char>("\"")?, '"');
+        assert_eq!(
+            super::de::from_str::<(char, char)>(r#""\"" x"#)?,
+            ('"', 'x')
+        );
+        assert_eq!(super::de::from_str::<(char, u8)>(r#""a" 1"#)?, ('a', 1));
+
+        // a later quote doesn't make the bare one the start of a quoted string
+        let v = ('"', String::from("a\""));
+        let s = super::ser::to_string(&v)?;
+        assert_eq!(super::de::from_str::<(char, String)>(&s)?, v, "{s}");
+        let v = ('"', 'x', '"');
+        let s = super::ser::to_string(&v)?;
+        assert_eq!(super::de::from_str::<(char, char, char)>(&s)?, v, "{s}");
+        Ok(())
+    }
+
+    #[test]
+    fn unsized_and_maps() -> anyhow::Result<()> {
+        use std::collections::{BTreeMap, HashMap};
+
+        /// a length the serializer doesn't know up front
+        struct Evens(Vec<u32>);
+        impl serde::Serialize for Evens {
+            fn serialize<S: serde::Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+                s.collect_seq(self.0.iter().filter(|n| *n % 2 == 0))
+            }
+        }
+
+        struct Named(Vec<(u8, &'static str)>);
+        impl serde::Serialize for Named {
+            fn serialize<S: serde::Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+                s.collect_map(self.0.iter().filter(|(n, _)| *n > 0).map(|(n, s)| (n, s)))
+            }
+        }
+
+        let txt = super::ser::to_string(&(Evens(vec![1, 2, 3, 4, 6]), 9u8))?;
+        assert_eq!(txt, "3 2 4 6 9");
+        assert_eq!(
+            super::de::from_str::<(Vec<u32>, u8)>(&txt)?,
+            (vec![2, 4, 6], 9)
+        );
+        assert_eq!(super::ser::to_string(&Evens(vec![1]))?, "0");
+
+        let txt = super::ser::to_string(&Named(vec![(0, "x"), (1, "a b"), (2, "c")]))?;
+        assert_eq!(txt, "2 1 1 a b 2 0 c");
+        let map: BTreeMap<u8, String> = super::de::from_str(&txt)?;
+        assert_eq!(map, BTreeMap::from([(1, "a b".into()), (2, "c".into())]));
+
+        let map = HashMap::from([(4u8, true), (7, false)]);
+        let txt = super::ser::to_string(&map)?;
+        assert_eq!(super::de::from_str::<HashMap<u8, bool>>(&txt)?, map);
+
+        // terminated instead of length prefixed
+        assert_eq!(
+            super::de::from_str::<(Vec<u8>, u8)>("[ 1 2 3 ] 4")?,
+            (vec![1, 2, 3], 4)
+        );
+        assert_eq!(
+            super::de::from_str::<BTreeMap<u8, Vec<u8>>>("{ 1 [ ] 2 0 }")?,
+            BTreeMap::from([(1, vec![]), (2, vec![])])
+        );
+
+        Ok(())
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn self_describing() -> anyhow::Result<()> {
+        let v: serde_json::Value =
+            super::de::from_str(r#"{ "speed" -1.5 "tags" [ "a b" x 3 ] "on" true "none" null }"#)?;
+        assert_eq!(
+            v,
+            serde_json::json!({"speed": -1.5, "tags": ["a b", "x", 3], "on": true, "none": null})
+        );
+        Ok(())
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn self_describing_roundtrip() -> anyhow::Result<()> {
+        use super::SelfDescribing;
+        use serde_json::{json, Value};
+
+        let values = [
+            json!([1, 2]),
+            json!([]),
+            json!([[1, -2], [], [3.5, 1.0]]),
+            json!({}),
+            json!({ "a": 1, "b c": [true, false, null], "d": { "e": "f", "g": {} } }),
+            json!(""),
+            json!("a string with  spaces"),
+            json!("\"quoted\" \\ and\nlines\t"),
+            json!("12"),
+            json!("null"),
+            json!(["[", "]", "{", "}"]),
+            json!(null),
+            json!(-3),
+            json!(0.25),
+        ];
+
+        for v in values {
+            let txt = super::ser::to_string(&SelfDescribing(&v))?;
+            let back: Value = super::de::from_str(&txt)?;
+            assert_eq!(back, v, "{txt}");
+            let back: SelfDescribing<Value> = super::de::from_str(&txt)?;
+            assert_eq!(back.0, v, "{txt}");
+        }
+
+        assert_eq!(
+            super::ser::to_string(&SelfDescribing(json!([1, 2])))?,
+            "[ 1 2 ]"
+        );
+        assert_eq!(super::ser::to_string(&json!([1, 2]))?, "2 1 2");
+        // a tuple keeps its compact form around one
+        assert_eq!(
+            super::ser::to_string(&(vec![1, 2], SelfDescribing(json!({ "a": "b c" })), true))?,
+            "2 1 2 { \"a\" \"b c\" } 1"
+        );
+
+        Ok(())
+    }
 }
@@ -1,6 +1,9 @@
 use crate::cmd::SEPARATOR;
 
-use super::error::{Error, Result};
+use super::{
+    error::{Error, Result},
+    SELF_DESCRIBING,
+};
 use serde::{
     ser::{self, SerializeSeq},
     Serialize,
@@ -16,6 +19,8 @@ pub fn to_string(s: &impl Serialize) -> Result<String> {
 pub struct Serializer<W: fmt::Write> {
     formatting: bool,
     put_separator: bool,
+    /// Inside a [`SelfDescribing`](super::SelfDescribing), writing the forms `deserialize_any` reads
+    describing: bool,
     writer: W,
 }
 
@@ -24,9 +29,52 @@ impl<W: fmt::Write> Serializer<W> {
         Self {
             put_separator: false,
             formatting: false,
+            describing: false,
             writer,
         }
     }
+
+    /// A `"quoted string"`, escaped the way the deserializer reads it
+    fn quote(&mut self, v: &str) -> Result<()> {
+        let mut s = String::with_capacity(v.len() + 2);
+        s.push('"');
+        for c in v.chars() {
+            match c {
+                '"' => s.push_str("\\\""),
+                '\\' => s.push_str("\\\\"),
+                '\n' => s.push_str("\\n"),
+                '\r' => s.push_str("\\r"),
+                '\t' => s.push_str("\\t"),
+                c => s.push(c),
+            }
+        }
+        s.push('"');
+        Ok(self.write_str(&s)?)
+    }
+
+    /// Opens a variant as a map from its name to its content, like `{ "Name" [ 1 2 ] }`,
+    /// or writes its index in the compact form
+    fn variant(&mut self, index: u32, name: &str) -> Result<()> {
+        if self.describing {
+            self.write_str("{")?;
+            self.quote(name)
+        } else {
+            Ok(write!(self, "{index}")?)
+        }
+    }
+
+    /// The bracket a self-describing compound starts with, nothing in the compact form
+    fn open(&mut self, bracket: &str) -> Result<()> {
+        if self.describing {
+            self.write_str(bracket)?;
+        }
+        Ok(())
+    }
+
+    /// Same as [`Serializer::open`], at the end
+    fn close(&mut self, bracket: &str) -> Result<()> {
+        self.open(bracket)
+    }
 }
 
 impl<W: fmt::Write> fmt::Write for Serializer<W> {
@@ -53,21 +101,30 @@ impl<W: fmt::Write> fmt::Write for Serializer<W> {
     }
 }
 
-impl<W: fmt::Write> ser::Serializer for &mut Serializer<W> {
+impl<'a, W: fmt::Write> ser::Serializer for &'a mut Serializer<W> {
     type Ok = ();
 
     type Error = Error;
 
-    type SerializeSeq = Self;
+    type SerializeSeq = Compound<'a, W>;
     type SerializeTuple = Self;
     type SerializeTupleStruct = Self;
     type SerializeTupleVariant = Self;
-    type SerializeMap = Self;
+    type SerializeMap = Compound<'a, W>;
     type SerializeStruct = Self;
     type SerializeStructVariant = Self;
 
+    /// positional, without any names or tags, unlike json
+    fn is_human_readable(&self) -> bool {
+        false
+    }
+
     fn serialize_bool(self, v: bool) -> Result<()> {
-        self.write_char(if v { '1' } else { '0' })?;
+        if self.describing {
+            self.write_str(if v { "true" } else { "false" })?;
+        } else {
+            self.write_char(if v { '1' } else { '0' })?;
+        }
         Ok(())
     }
 
@@ -103,19 +160,33 @@ impl<W: fmt::Write> ser::Serializer for &mut Serializer<W> {
         Ok(write!(self, "{v}")?)
     }
 
+    /// With a decimal point when it's self-describing, or it'd be read back as an integer
     fn serialize_f32(self, v: f32) -> Result<()> {
+        if self.describing {
+            return Ok(write!(self, "{v:?}")?);
+        }
         Ok(write!(self, "{v}")?)
     }
 
     fn serialize_f64(self, v: f64) -> Result<()> {
+        if self.describing {
+            return Ok(write!(self, "{v:?}")?);
+        }
         Ok(write!(self, "{v}")?)
     }
 
     fn serialize_char(self, v: char) -> Result<()> {
+        if self.describing {
+            return self.quote(v.encode_utf8(&mut [0; 4]));
+        }
         Ok(self.write_char(v)?)
     }
 
     fn serialize_str(self, v: &str) -> Result<()> {
+        if self.describing {
+            return self.quote(v);
+        }
+
         let spaces = v.chars().filter(|c| *c == SEPARATOR).count();
         write!(self, "{spaces}")?;
 
@@ -135,6 +206,9 @@ impl<W: fmt::Write> ser::Serializer for &mut Serializer<W> {
     }
 
     fn serialize_none(self) -> Result<()> {
+        if self.describing {
+            return Ok(self.write_str("null")?);
+        }
         Ok(self.write_char('0')?)
     }
 
@@ -142,58 +216,70 @@ impl<W: fmt::Write> ser::Serializer for &mut Serializer<W> {
     where
         T: Serialize,
     {
-        self.write_char('1')?;
+        if !self.describing {
+            self.write_char('1')?;
+        }
         value.serialize(self)
     }
 
     fn serialize_unit(self) -> Result<()> {
+        if self.describing {
+            self.write_str("null")?;
+        }
         Ok(())
     }
 
     fn serialize_unit_struct(self, _: &'static str) -> Result<()> {
-        Ok(())
+        self.serialize_unit()
     }
 
     fn serialize_unit_variant(
         self,
         _: &'static str,
         variant_index: u32,
-        _: &'static str,
+        variant: &'static str,
     ) -> Result<()> {
+        if self.describing {
+            return self.quote(variant);
+        }
         self.serialize_u32(variant_index)
     }
 
-    fn serialize_newtype_struct<T: ?Sized>(self, _: &'static str, value: &T) -> Result<()>
+    fn serialize_newtype_struct<T: ?Sized>(self, name: &'static str, value: &T) -> Result<()>
     where
         T: Serialize,
     {
-        value.serialize(self)
+        if name != SELF_DESCRIBING || self.describing {
+            return value.serialize(self);
+        }
+
+        self.describing = true;
+        let res = value.serialize(&mut *self);
+        self.describing = false;
+        res
     }
 
     fn serialize_newtype_variant<T: ?Sized>(
         self,
         _: &'static str,
         variant_index: u32,
-        _: &'static str,
+        variant: &'static str,
         value: &T,
     ) -> Result<()>
     where
         T: Serialize,
     {
-        write!(self, "{variant_index}")?;
-        value.serialize(self)
+        self.variant(variant_index, variant)?;
+        value.serialize(&mut *self)?;
+        self.close("}")
     }
 
     fn serialize_seq(self, len: Option<usize>) -> Result<Self::SerializeSeq> {
-        if let Some(len) = len {
-            write!(self, "{}", len)?;
-            Ok(self)
-        } else {
-            Err(Error::UnsizedSeq)
-        }
+        Compound::new(self, len, ("[", "]"))
     }
 
     fn serialize_tuple(self, _: usize) -> Result<Self::SerializeTuple> {
+        self.open("[")?;
         Ok(self)
     }
 
@@ -202,6 +288,7 @@ impl<W: fmt::Write> ser::Serializer for &mut Serializer<W> {
         _: &'static str,
         _: usize,
     ) -> Result<Self::SerializeTupleStruct> {
+        self.open("[")?;
         Ok(self)
     }
 
@@ -209,23 +296,20 @@ impl<W: fmt::Write> ser::Serializer for &mut Serializer<W> {
         self,
         _: &'static str,
         variant_index: u32,
-        _: &'static str,
+        variant: &'static str,
         _: usize,
     ) -> Result<Self::SerializeTupleVariant> {
-        write!(self, "{variant_index}")?;
+        self.variant(variant_index, variant)?;
+        self.open("[")?;
         Ok(self)
     }
 
     fn serialize_map(self, len: Option<usize>) -> Result<Self::SerializeMap> {
-        if let Some(len) = len {
-            write!(self, "{len}")?;
-            Ok(self)
-        } else {
-            Err(Error::UnsizedMap)
-        }
+        Compound::new(self, len, ("{", "}"))
     }
 
     fn serialize_struct(self, _: &'static str, _: usize) -> Result<Self::SerializeStruct> {
+        self.open("{")?;
         Ok(self)
     }
 
@@ -233,15 +317,73 @@ impl<W: fmt::Write> ser::Serializer for &mut Serializer<W> {
         self,
         _: &'static str,
         variant_index: u32,
-        _: &'static str,
+        variant: &'static str,
         _: usize,
     ) -> Result<Self::SerializeStructVariant> {
-        write!(self, "{variant_index}")?;
+        self.variant(variant_index, variant)?;
+        self.open("{")?;
         Ok(self)
     }
 }
 
-impl<'a, W: fmt::Write> ser::SerializeSeq for &'a mut Serializer<W> {
+/// A sequence or a map, prefixed with its length.
+/// If the length isn't known up front, the elements are buffered until they're counted.
+/// Self-describing ones are in brackets instead, like `[ 1 2 ]` or `{ "a" 1 }`.
+pub struct Compound<'a, W: fmt::Write> {
+    ser: &'a mut Serializer<W>,
+    buffer: Option<(Serializer<String>, usize)>,
+    close: &'static str,
+}
+
+impl<'a, W: fmt::Write> Compound<'a, W> {
+    fn new(
+        ser: &'a mut Serializer<W>,
+        len: Option<usize>,
+        (open, close): (&'static str, &'static str),
+    ) -> Result<Self> {
+        let buffer = match len {
+            _ if ser.describing => {
+                ser.write_str(open)?;
+                None
+            }
+            Some(len) => {
+                write!(ser, "{len}")?;
+                None
+            }
+            None => Some((Serializer::new(String::new()), 0)),
+        };
+        Ok(Self { ser, buffer, close })
+    }
+
+    /// `count` is false for the values of a map, its length is the number of keys
+    fn element<T>(&mut self, value: &T, count: bool) -> Result<()>
+    where
+        T: ?Sized + Serialize,
+    {
+        match &mut self.buffer {
+            Some((buf, len)) => {
+                *len += count as usize;
+                value.serialize(buf)
+            }
+            None => value.serialize(&mut *self.ser),
+        }
+    }
+
+    fn finish(self) -> Result<()> {
+        match self.buffer {
+            Some((buf, len)) => {
+                write!(self.ser, "{len}")?;
+                if !buf.writer.is_empty() {
+                    self.ser.write_str(&buf.writer)?;
+                }
+                Ok(())
+            }
+            None => self.ser.close(self.close),
+        }
+    }
+}
+
+impl<'a, W: fmt::Write> ser::SerializeSeq for Compound<'a, W> {
     type Ok = ();
     type Error = Error;
 
@@ -249,11 +391,11 @@ impl<'a, W: fmt::Write> ser::SerializeSeq for &'a mut Serializer<W> {
     where
         T: ?Sized + Serialize,
     {
-        value.serialize(&mut **self)
+        self.element(value, true)
     }
 
     fn end(self) -> Result<()> {
-        Ok(())
+        self.finish()
     }
 }
 
@@ -269,7 +411,7 @@ impl<'a, W: fmt::Write> ser::SerializeTuple for &'a mut Serializer<W> {
     }
 
     fn end(self) -> Result<()> {
-        Ok(())
+        self.close("]")
     }
 }
 
@@ -285,7 +427,7 @@ impl<'a, W: fmt::Write> ser::SerializeTupleStruct for &'a mut Serializer<W> {
     }
 
     fn end(self) -> Result<()> {
-        Ok(())
+        self.close("]")
     }
 }
 
@@ -301,11 +443,12 @@ impl<'a, W: fmt::Write> ser::SerializeTupleVariant for &'a mut Serializer<W> {
     }
 
     fn end(self) -> Result<()> {
-        Ok(())
+        self.close("]")?;
+        self.close("}")
     }
 }
 
-impl<'a, W: fmt::Write> ser::SerializeMap for &'a mut Serializer<W> {
+impl<'a, W: fmt::Write> ser::SerializeMap for Compound<'a, W> {
     type Ok = ();
     type Error = Error;
 
@@ -313,18 +456,18 @@ impl<'a, W: fmt::Write> ser::SerializeMap for &'a mut Serializer<W> {
     where
         T: ?Sized + Serialize,
     {
-        key.serialize(&mut **self)
+        self.element(key, true)
     }
 
     fn serialize_value<T>(&mut self, value: &T) -> Result<()>
     where
         T: ?Sized + Serialize,
     {
-        value.serialize(&mut **self)
+        self.element(value, false)
     }
 
     fn end(self) -> Result<()> {
-        Ok(())
+        self.finish()
     }
 }
 
@@ -332,15 +475,18 @@ impl<'a, W: fmt::Write> ser::SerializeStruct for &'a mut Serializer<W> {
     type Ok = ();
     type Error = Error;
 
-    fn serialize_field<T>(&mut self, _: &'static str, value: &T) -> Result<()>
+    fn serialize_field<T>(&mut self, key: &'static str, value: &T) -> Result<()>
     where
         T: ?Sized + Serialize,
     {
+        if self.describing {
+            self.quote(key)?;
+        }
         value.serialize(&mut **self)
     }
 
     fn end(self) -> Result<()> {
-        Ok(())
+        self.close("}")
     }
 }
 
@@ -348,14 +494,18 @@ impl<'a, W: fmt::Write> ser::SerializeStructVariant for &'a mut Serializer<W> {
     type Ok = ();
     type Error = Error;
 
-    fn serialize_field<T>(&mut self, _: &'static str, value: &T) -> Result<()>
+    fn serialize_field<T>(&mut self, key: &'static str, value: &T) -> Result<()>
     where
         T: ?Sized + Serialize,
     {
+        if self.describing {
+            self.quote(key)?;
+        }
         value.serialize(&mut **self)
     }
 
     fn end(self) -> Result<()> {
-        Ok(())
+        self.close("}")?;
+        self.close("}")
     }
 }
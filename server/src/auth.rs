@@ -0,0 +1,148 @@
+use crate::config;
+use roblib::cmd::{self, Concrete};
+use std::{
+    collections::HashMap,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+/// How long a challenge can be answered for
+const CHALLENGE_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// How many challenges can wait for an answer at once, from all the clients together
+const MAX_CHALLENGES: usize = 1024;
+
+/// Challenge-response authentication with the shared secret, see [`cmd::Authenticate`].
+/// The transports keep track of which clients authenticated, this only hands out and checks the challenges and tokens.
+pub(crate) struct Auth {
+    secret: Option<String>,
+    token_expiry: Duration,
+    /// The challenges that haven't been answered yet, with who asked for them and when
+    challenges: Mutex<HashMap<String, (String, Instant)>>,
+    /// Session tokens, with when they were last used
+    tokens: Mutex<HashMap<String, Instant>>,
+}
+
+impl Auth {
+    pub fn new(config: &config::Auth) -> Self {
+        Self {
+            secret: (!config.secret.is_empty()).then(|| config.secret.clone()),
+            token_expiry: Duration::from_secs(config.token_expiry_s),
+            challenges: Default::default(),
+            tokens: Default::default(),
+        }
+    }
+
+    pub fn required(&self) -> bool {
+        self.secret.is_some()
+    }
+
+    /// Whether a client can run `cmd`, the unauthenticated ones can only authenticate
+    pub fn check(&self, authenticated: bool, cmd: &Concrete) -> Result<(), cmd::Error> {
+        if authenticated || !self.required() {
+            return Ok(());
+        }
+        match cmd {
+            Concrete::GetChallenge(_)
+            | Concrete::Authenticate(_)
+            | Concrete::GetCapabilities(_) => Ok(()),
+            c => Err(cmd::Error::new(
+                cmd::ErrorCode::Unauthorized,
+                format!("authenticate before running {}", c.get_prefix()),
+            )),
+        }
+    }
+
+    /// A new challenge for `client`, replacing the one it got before
+    pub fn challenge(&self, client: &str) -> anyhow::Result<String> {
+        let mut challenges = self.challenges.lock().unwrap();
+        challenges.retain(|_, (c, at)| c != client && at.elapsed() < CHALLENGE_TIMEOUT);
+        if challenges.len() >= MAX_CHALLENGES {
+            anyhow::bail!("Too many challenges waiting for an answer, try again later");
+        }
+
+        let challenge = random_hex();
+        challenges.insert(challenge.clone(), (client.into(), Instant::now()));
+        Ok(challenge)
+    }
+
+    /// Checks the response to a challenge, which can only be tried once, and returns a new session token
+    pub fn authenticate(&self, challenge: &str, response: &str) -> anyhow::Result<String> {
+        let Some(secret) = &self.secret else {
+            return Ok(random_hex());
+        };
+
+        match self.challenges.lock().unwrap().remove(challenge) {
+            Some((_, at)) if at.elapsed() < CHALLENGE_TIMEOUT => (),
+            _ => anyhow::bail!("Unknown or expired challenge"),
+        }
+        if !cmd::auth::verify(secret, challenge, response) {
+            anyhow::bail!("Wrong response to the challenge");
+        }
+
+        let token = random_hex();
+        let mut tokens = self.tokens.lock().unwrap();
+        tokens.retain(|_, used| used.elapsed() < self.token_expiry);
+        tokens.insert(token.clone(), Instant::now());
+        Ok(token)
+    }
+
+    /// Whether `token` belongs to an authenticated session, and keeps it alive if it does
+    pub fn token(&self, token: &str) -> bool {
+        let mut tokens = self.tokens.lock().unwrap();
+        match tokens.get_mut(token) {
+            Some(used) if used.elapsed() < self.token_expiry => {
+                *used = Instant::now();
+                true
+            }
+            Some(_) => {
+                tokens.remove(token);
+                false
+            }
+            None => false,
+        }
+    }
+}
+
+/// 32 random bytes, hex encoded
+fn random_hex() -> String {
+    rand::random::<[u8; 32]>()
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn auth() -> Auth {
+        let mut config = crate::Config::default();
+        config.auth.secret = "hunter2".into();
+        Auth::new(&config.auth)
+    }
+
+    #[test]
+    fn challenge_replaces_previous() {
+        let auth = auth();
+        let first = auth.challenge("tcp a").unwrap();
+        let other = auth.challenge("tcp b").unwrap();
+        let second = auth.challenge("tcp a").unwrap();
+
+        let respond = |c: &str| cmd::auth::respond("hunter2", c);
+        assert!(auth.authenticate(&first, &respond(&first)).is_err());
+        assert!(auth.authenticate(&second, &respond(&second)).is_ok());
+        assert!(auth.authenticate(&other, &respond(&other)).is_ok());
+    }
+
+    #[test]
+    fn challenges_are_limited() {
+        let auth = auth();
+        for i in 0..MAX_CHALLENGES {
+            auth.challenge(&format!("udp {i}")).unwrap();
+        }
+        assert!(auth.challenge("tcp a").is_err());
+        // asking again only replaces the old one
+        assert!(auth.challenge("udp 0").is_ok());
+    }
+}
@@ -0,0 +1,238 @@
+use crate::{config, control, event_bus::sub::SubStatus, transports::SubscriptionId, Backends};
+use roblib::{cmd::Concrete, event::ConcreteType};
+use std::{collections::HashMap, sync::Mutex, time::Duration};
+
+/// Undoes what a client left behind when it disconnects, see [`config::Cleanup`].
+/// Clients are told apart by the same names as in [`control`].
+pub(crate) struct Cleanup {
+    policy: config::Cleanup,
+    /// The client that last drove the robot
+    driver: Mutex<Option<String>>,
+    /// The client that last set a PWM or servo signal on each pin
+    pins: Mutex<HashMap<u8, String>>,
+}
+
+impl Cleanup {
+    pub fn new(policy: &config::Cleanup) -> Self {
+        Self {
+            policy: policy.clone(),
+            driver: Default::default(),
+            pins: Default::default(),
+        }
+    }
+
+    /// Called for every command, remembers the client that ran it if it's going to need undoing.
+    /// Commands that don't come from a client, like the scheduled ones, aren't tracked.
+    #[allow(unused_variables)]
+    pub fn track(&self, cmd: &Concrete) {
+        let Ok(client) = control::client() else {
+            return;
+        };
+
+        match cmd {
+            #[cfg(feature = "roland")]
+            Concrete::MoveRobot(_) | Concrete::MoveRobotByAngle(_) => {
+                *self.driver.lock().unwrap() = Some(client);
+            }
+            #[cfg(feature = "roland")]
+            Concrete::StopRobot(_) => *self.driver.lock().unwrap() = None,
+
+            #[cfg(feature = "gpio")]
+            Concrete::Pwm(roblib::cmd::Pwm(pin, _, cycle)) if *cycle == 0. => {
+                self.pins.lock().unwrap().remove(pin);
+            }
+            #[cfg(feature = "gpio")]
+            Concrete::Pwm(roblib::cmd::Pwm(pin, ..))
+            | Concrete::Servo(roblib::cmd::Servo(pin, _)) => {
+                self.pins.lock().unwrap().insert(*pin, client);
+            }
+
+            _ => (),
+        }
+    }
+
+    /// How long a UDP client can stay quiet before it's considered gone, zero if it never is
+    pub fn udp_session(&self) -> Duration {
+        Duration::from_secs(self.policy.udp_session_s)
+    }
+
+    /// Cleans up after `client`, `sub` is any of its subscriptions, the id doesn't matter
+    pub fn disconnected(&self, robot: &Backends, client: &str, sub: SubscriptionId) {
+        if self.policy.subscriptions {
+            if let Err(e) = robot
+                .sub
+                .send((ConcreteType::None, sub, SubStatus::Disconnect))
+            {
+                log::error!("event bus sub error: {e}");
+            }
+        }
+
+        if self.policy.control {
+            robot.control.disconnected(client);
+        }
+
+        let mut driver = self.driver.lock().unwrap();
+        if self.policy.motors && driver.as_deref() == Some(client) {
+            *driver = None;
+            log::debug!("{client} disconnected while driving, stopping the robot");
+
+            #[cfg(all(feature = "roland", any(feature = "backend", feature = "sim")))]
+            if let Some(r) = &robot.roland {
+                use roblib::roland::Roland;
+                if let Err(e) = r.stop() {
+                    log::error!("Failed to stop the robot: {e}");
+                }
+            }
+        }
+        drop(driver);
+
+        if self.policy.pins {
+            let mut pins = self.pins.lock().unwrap();
+            let theirs: Vec<u8> = pins
+                .iter()
+                .filter(|(_, owner)| *owner == client)
+                .map(|(pin, _)| *pin)
+                .collect();
+
+            for pin in theirs {
+                pins.remove(&pin);
+                log::debug!("{client} disconnected, clearing pin {pin}");
+
+                #[cfg(all(feature = "gpio", any(feature = "backend", feature = "sim")))]
+                if let Some(r) = &robot.raw_gpio {
+                    use roblib::gpio::Gpio;
+                    if let Err(e) = r.pwm(pin, 0., 0.) {
+                        log::error!("Failed to clear pin {pin}: {e}");
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Cleans up after a connected client when dropped, so it's done however the connection ends.
+/// Not when the server is shutting down, that cleans up everything anyway.
+pub(crate) struct Guard<'a> {
+    pub robot: &'a Backends,
+    pub client: &'a str,
+    pub sub: SubscriptionId,
+}
+
+impl Drop for Guard<'_> {
+    fn drop(&mut self) {
+        if !self.robot.abort_token.is_cancelled() {
+            let sub = self.sub.clone();
+            self.robot
+                .cleanup
+                .disconnected(self.robot, self.client, sub);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{event_bus::sub::SubStatus, Config};
+    use roblib::cmd::Custom;
+
+    fn drive() -> Concrete {
+        Custom("drive".into(), vec![]).into()
+    }
+
+    #[test]
+    fn guard_releases_control_and_unsubscribes() {
+        let robot = Backends::test(&Config::default());
+        let mut subs = robot.sub.subscribe();
+        let addr = ([127, 0, 0, 1], 1).into();
+
+        robot.control.acquire("tcp 127.0.0.1:1".into()).unwrap();
+        assert!(robot.control.check("tcp other", &drive()).is_err());
+
+        drop(Guard {
+            robot: &robot,
+            client: "tcp 127.0.0.1:1",
+            sub: SubscriptionId::Tcp(addr, 0),
+        });
+
+        assert!(robot.control.check("tcp other", &drive()).is_ok());
+        let (ty, sub, status) = subs.try_recv().unwrap();
+        assert_eq!(ty, ConcreteType::None);
+        assert_eq!(sub, SubscriptionId::Tcp(addr, 0));
+        assert!(matches!(status, SubStatus::Disconnect));
+    }
+
+    #[test]
+    fn guard_does_nothing_on_shutdown() {
+        let robot = Backends::test(&Config::default());
+        let mut subs = robot.sub.subscribe();
+        robot.control.acquire("tcp a".into()).unwrap();
+
+        robot.abort_token.cancel();
+        drop(Guard {
+            robot: &robot,
+            client: "tcp a",
+            sub: SubscriptionId::Tcp(([127, 0, 0, 1], 1).into(), 0),
+        });
+
+        assert!(robot.control.check("tcp other", &drive()).is_err());
+        assert!(subs.try_recv().is_err());
+    }
+
+    #[test]
+    fn policy_turns_cleanup_off() {
+        let mut config = Config::default();
+        config.cleanup.control = false;
+        config.cleanup.subscriptions = false;
+        let robot = Backends::test(&config);
+        let mut subs = robot.sub.subscribe();
+        robot.control.acquire("tcp a".into()).unwrap();
+
+        drop(Guard {
+            robot: &robot,
+            client: "tcp a",
+            sub: SubscriptionId::Tcp(([127, 0, 0, 1], 1).into(), 0),
+        });
+
+        assert!(robot.control.check("tcp other", &drive()).is_err());
+        assert!(subs.try_recv().is_err());
+    }
+
+    #[cfg(all(feature = "roland", feature = "sim"))]
+    #[test]
+    fn guard_stops_the_motors() {
+        use roblib::{cmd::MoveRobot, roland::Roland};
+
+        let config = Config::default();
+        let robot = Backends {
+            roland: Some(crate::RolandBackend::new(config.sim.arena.clone())),
+            ..Backends::test(&config)
+        };
+        let roland = robot.roland.as_ref().unwrap();
+        let moved = |wait| {
+            let before = roland.pose().x;
+            std::thread::sleep(wait);
+            roland.pose().x != before
+        };
+        let tick = std::time::Duration::from_millis(20);
+
+        let guard = |client| Guard {
+            robot: &robot,
+            client,
+            sub: SubscriptionId::Tcp(([127, 0, 0, 1], 1).into(), 0),
+        };
+        let drive = |client: &str| {
+            control::CLIENT.sync_scope(client.into(), || {
+                robot.cleanup.track(&MoveRobot(0.5, 0.5).into())
+            });
+            roland.drive(0.5, 0.5).unwrap();
+        };
+
+        drive("tcp a");
+        // someone else leaving doesn't stop it
+        drop(guard("tcp b"));
+        assert!(moved(tick));
+
+        drop(guard("tcp a"));
+        assert!(!moved(tick));
+    }
+}
@@ -13,7 +13,7 @@ impl Execute for PinMode {
 
         debug!("Pinmode: {pin} {mode:?}");
 
-        #[cfg(feature = "backend")]
+        #[cfg(any(feature = "backend", feature = "sim"))]
         if let Some(r) = &robot.raw_gpio {
             r.pin_mode(pin, mode)?
         };
@@ -29,14 +29,14 @@ impl Execute for ReadPin {
 
         debug!("Read pin: {pin}");
 
-        #[cfg(feature = "backend")]
+        #[cfg(any(feature = "backend", feature = "sim"))]
         let res = if let Some(r) = &robot.raw_gpio {
             r.read_pin(pin)?
         } else {
             false
         };
 
-        #[cfg(not(feature = "backend"))]
+        #[cfg(not(any(feature = "backend", feature = "sim")))]
         let res = false;
 
         Ok(res)
@@ -50,7 +50,7 @@ impl Execute for WritePin {
 
         debug!("Set pin: {pin}:{value}");
 
-        #[cfg(feature = "backend")]
+        #[cfg(any(feature = "backend", feature = "sim"))]
         if let Some(r) = &robot.raw_gpio {
             r.write_pin(pin, value)?;
         }
@@ -66,7 +66,7 @@ impl Execute for Pwm {
 
         debug!("Set pwm: {pin}:{hz}:{cycle}");
 
-        #[cfg(feature = "backend")]
+        #[cfg(any(feature = "backend", feature = "sim"))]
         if let Some(r) = &robot.raw_gpio {
             r.pwm(pin, hz, cycle)?;
         }
@@ -82,7 +82,7 @@ impl Execute for Servo {
 
         debug!("Servo basic: {deg}");
 
-        #[cfg(feature = "backend")]
+        #[cfg(any(feature = "backend", feature = "sim"))]
         if let Some(r) = &robot.raw_gpio {
             r.servo(pin, deg)?;
         }
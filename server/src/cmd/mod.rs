@@ -2,8 +2,16 @@ use std::sync::Arc;
 
 use crate::Backends;
 
-use roblib::cmd::{Abort, Command, Concrete, GetUptime, Nop};
-use serde::{Serialize, Serializer};
+use roblib::{
+    cmd::{
+        has_return, Abort, Ack, AcquireControl, Authenticate, Batch, CancelScheduled, Capabilities,
+        Command, Concrete, Custom, GetCapabilities, GetChallenge, GetSchema, GetUptime, Heartbeat,
+        ListScheduled, Nop, ReleaseControl, Schedule, Subscribe, TakeControl, Unsubscribe, When,
+        PROTOCOL_VERSION,
+    },
+    event::ConcreteType,
+};
+use serde::{ser::SerializeTuple, Serialize, Serializer};
 
 #[cfg(feature = "roland")]
 mod roland;
@@ -19,6 +27,178 @@ pub(crate) trait Execute: Command {
     async fn execute(&self, robot: Arc<Backends>) -> anyhow::Result<Self::Return>;
 }
 
+/// A command's return value, if it has one
+pub(crate) type Return = Option<Box<dyn erased_serde::Serialize + Send>>;
+
+#[async_trait::async_trait]
+trait Dispatch {
+    async fn dispatch(self, robot: Arc<Backends>) -> anyhow::Result<Return>;
+}
+
+#[async_trait::async_trait]
+impl<C: Execute> Dispatch for C {
+    async fn dispatch(self, robot: Arc<Backends>) -> anyhow::Result<Return> {
+        let ret = self.execute(robot).await?;
+
+        if !has_return::<C>() {
+            return Ok(None);
+        }
+
+        Ok(Some(Box::new(ret)))
+    }
+}
+
+/// Custom commands are looked up by name, their return type is only known by the handler
+#[async_trait::async_trait]
+impl Dispatch for Custom {
+    async fn dispatch(self, robot: Arc<Backends>) -> anyhow::Result<Return> {
+        let Custom(name, payload) = self;
+        debug!("Custom command: {name}");
+
+        let Some(handler) = robot.custom.get(&name[..]) else {
+            anyhow::bail!("Unknown custom command: {name}");
+        };
+
+        handler(&payload).await
+    }
+}
+
+#[async_trait::async_trait]
+impl Dispatch for Batch {
+    async fn dispatch(self, robot: Arc<Backends>) -> anyhow::Result<Return> {
+        debug!("Batch of {}", self.0.len());
+
+        // checked up front, the batch isn't undone if it fails halfway through
+        let acked = |c: &Concrete| matches!(c, Concrete::Ack(a) if a.0.replies());
+        if let Some(i) = self.0.iter().position(acked) {
+            anyhow::bail!(
+                "Batch command {i} acks a command that replies anyway, send it without the ack"
+            );
+        }
+
+        let mut rets = Vec::with_capacity(self.0.len());
+        for (i, c) in self.0.into_iter().enumerate() {
+            let prefix = c.get_prefix();
+            match dispatch(c, robot.clone()).await {
+                Ok(ret) => rets.push(ret),
+                Err(e) => anyhow::bail!("Batch command {i} ({prefix}) failed: {e}"),
+            }
+        }
+
+        Ok(Some(Box::new(Flat(
+            rets.into_iter()
+                .map(|r| r.unwrap_or_else(|| Box::new(())))
+                .collect(),
+        ))))
+    }
+}
+
+/// Replies with `()` if the command doesn't have a return value
+#[async_trait::async_trait]
+impl Dispatch for Ack {
+    async fn dispatch(self, robot: Arc<Backends>) -> anyhow::Result<Return> {
+        let ret = dispatch(*self.0, robot).await?;
+        Ok(Some(ret.unwrap_or_else(|| Box::new(()))))
+    }
+}
+
+/// Takes ownership of the command, so it's not an [`Execute`] either.
+/// The command runs as the client that scheduled it, if it's still allowed to when it fires.
+#[async_trait::async_trait]
+impl Dispatch for Schedule {
+    async fn dispatch(self, robot: Arc<Backends>) -> anyhow::Result<Return> {
+        let Schedule(when, cmd) = self;
+        let client = crate::control::client()?;
+
+        let at = match when {
+            When::After(delay) => robot.startup_time.elapsed().checked_add(delay),
+            When::At(at) => Some(at),
+        };
+        let Some(deadline) = at.and_then(|at| robot.startup_time.checked_add(at)) else {
+            anyhow::bail!("Scheduled time out of range: {when:?}");
+        };
+        let at = at.unwrap();
+
+        let prefix = cmd.get_prefix();
+        let cancel = robot.abort_token.child_token();
+        let id = robot.scheduler.insert(at, prefix, cancel.clone());
+        debug!("Schedule {prefix} at {at:?} as {id}");
+
+        tokio::spawn(async move {
+            tokio::select! {
+                _ = tokio::time::sleep_until(deadline.into()) => {},
+                _ = cancel.cancelled() => {
+                    debug!("abort: scheduled {id}");
+                    robot.scheduler.take(id);
+                    return;
+                }
+            }
+
+            if !robot.scheduler.take(id) {
+                return;
+            }
+
+            let fire = async {
+                robot.control.check(&client, &cmd)?;
+                execute(*cmd, robot.clone()).await
+            };
+            let fire = crate::control::CLIENT.scope(client.clone(), fire);
+            let fire = crate::scheduler::SCHEDULED.scope(id, fire);
+            if let Err(e) = fire.await {
+                error!("Scheduled command {id} ({prefix}) failed: {e}");
+            }
+        });
+
+        Ok(Some(Box::new(id)))
+    }
+}
+
+/// Serializes `[a, b, c]` as `(a, b, c)`, the shape of a batch's return value, and `[]` as `()`
+struct Flat(Vec<Box<dyn erased_serde::Serialize + Send>>);
+impl Serialize for Flat {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if self.0.is_empty() {
+            return serializer.serialize_unit();
+        }
+
+        let mut t = serializer.serialize_tuple(self.0.len())?;
+        for v in &self.0 {
+            t.serialize_element(&**v)?;
+        }
+        t.end()
+    }
+}
+
+macro_rules! dispatch {
+    ($($(#[$attr:meta])* $cmd:ident),*) => {
+        /// Executes `concrete`, returning its return value if it has one
+        pub(crate) async fn dispatch(concrete: Concrete, robot: Arc<Backends>) -> anyhow::Result<Return> {
+            robot.watchdog.feed(&concrete);
+            robot.cleanup.track(&concrete);
+            match concrete {
+                $(
+                    $(#[$attr])*
+                    Concrete::$cmd(c) => c.dispatch(robot).await,
+                )*
+            }
+        }
+    };
+}
+roblib::for_each_command!(dispatch);
+
+/// Executes a command received from a client, recording it and what it returned if recording is enabled.
+/// Counted in the metrics, see [`crate::metrics::Metrics::command`]
+pub(crate) async fn execute(concrete: Concrete, robot: Arc<Backends>) -> anyhow::Result<Return> {
+    let id = robot.recorder.command(&concrete);
+    let start = std::time::Instant::now();
+    let prefix = concrete.get_prefix();
+    let ret = dispatch(concrete, robot.clone()).await;
+    robot.metrics.command(prefix, start.elapsed(), ret.is_ok());
+    robot.recorder.returned(id, &ret);
+    ret
+}
+
+/// Same as [`execute`], serializing the return value if it has one
 pub(crate) async fn execute_concrete<S>(
     concrete: Concrete,
     robot: Arc<Backends>,
@@ -27,106 +207,27 @@ pub(crate) async fn execute_concrete<S>(
 where
     S: Serializer + Send,
 {
-    Ok(match concrete {
-        #[cfg(feature = "roland")]
-        Concrete::MoveRobot(c) => {
-            c.execute(robot).await?;
-            None
-        }
-        #[cfg(feature = "roland")]
-        Concrete::MoveRobotByAngle(c) => {
-            c.execute(robot).await?;
-            None
-        }
-        #[cfg(feature = "roland")]
-        Concrete::StopRobot(c) => {
-            c.execute(robot).await?;
-            None
-        }
-        #[cfg(feature = "roland")]
-        Concrete::Led(c) => {
-            c.execute(robot).await?;
-            None
-        }
-        #[cfg(feature = "roland")]
-        Concrete::RolandServo(c) => {
-            c.execute(robot).await?;
-            None
-        }
-        #[cfg(feature = "roland")]
-        Concrete::Buzzer(c) => {
-            c.execute(robot).await?;
-            None
-        }
-        #[cfg(feature = "roland")]
-        Concrete::TrackSensor(c) => Some(
-            c.execute(robot)
-                .await?
-                .serialize(ser)
-                .map_err(|e| anyhow::Error::msg(e.to_string()))?,
-        ),
-        #[cfg(feature = "roland")]
-        Concrete::UltraSensor(c) => Some(
-            c.execute(robot)
-                .await?
-                .serialize(ser)
-                .map_err(|e| anyhow::Error::msg(e.to_string()))?,
-        ),
-
-        #[cfg(feature = "gpio")]
-        Concrete::PinMode(c) => {
-            c.execute(robot).await?;
-            None
-        }
-        #[cfg(feature = "gpio")]
-        Concrete::ReadPin(c) => Some(
-            c.execute(robot)
-                .await?
-                .serialize(ser)
-                .map_err(|e| anyhow::Error::msg(e.to_string()))?,
-        ),
-        #[cfg(feature = "gpio")]
-        Concrete::WritePin(c) => {
-            c.execute(robot).await?;
-            None
-        }
-        #[cfg(feature = "gpio")]
-        Concrete::Pwm(c) => {
-            c.execute(robot).await?;
-            None
-        }
-        #[cfg(feature = "gpio")]
-        Concrete::Servo(c) => {
-            c.execute(robot).await?;
-            None
-        }
+    let Some(ret) = execute(concrete, robot).await? else {
+        return Ok(None);
+    };
 
-        #[cfg(feature = "camloc")]
-        Concrete::GetPosition(c) => Some(
-            c.execute(robot)
-                .await?
-                .serialize(ser)
-                .map_err(|e| anyhow::Error::msg(e.to_string()))?,
-        ),
+    Ok(Some(
+        erased_serde::serialize(&*ret, ser).map_err(|e| anyhow::Error::msg(e.to_string()))?,
+    ))
+}
 
-        Concrete::Subscribe(_) => unreachable!("Subscribe should be handled by the transport"),
-        Concrete::Unsubscribe(_) => unreachable!("Unsubscribe should be handled by the transport"),
+#[async_trait::async_trait]
+impl Execute for Subscribe {
+    async fn execute(&self, _: Arc<Backends>) -> anyhow::Result<Self::Return> {
+        anyhow::bail!("Subscribe should be handled by the transport")
+    }
+}
 
-        Concrete::Nop(c) => {
-            c.execute(robot).await?;
-            None
-        }
-        Concrete::GetUptime(c) => Some(
-            c.execute(robot)
-                .await?
-                .serialize(ser)
-                .map_err(|e| anyhow::Error::msg(e.to_string()))?,
-        ),
-        Concrete::Abort(c) => {
-            c.execute(robot).await?;
-            None
-        }
-    })
+#[async_trait::async_trait]
+impl Execute for Unsubscribe {
+    async fn execute(&self, _: Arc<Backends>) -> anyhow::Result<Self::Return> {
+        anyhow::bail!("Unsubscribe should be handled by the transport")
+    }
 }
 
 #[async_trait::async_trait]
@@ -137,6 +238,15 @@ impl Execute for Nop {
     }
 }
 
+/// The watchdog is fed before any command runs
+#[async_trait::async_trait]
+impl Execute for Heartbeat {
+    async fn execute(&self, _: Arc<Backends>) -> anyhow::Result<Self::Return> {
+        trace!("Heartbeat");
+        Ok(())
+    }
+}
+
 #[async_trait::async_trait]
 impl Execute for GetUptime {
     async fn execute(&self, robot: Arc<Backends>) -> anyhow::Result<Self::Return> {
@@ -145,11 +255,180 @@ impl Execute for GetUptime {
     }
 }
 
+#[async_trait::async_trait]
+impl Execute for GetCapabilities {
+    async fn execute(&self, robot: Arc<Backends>) -> anyhow::Result<Self::Return> {
+        debug!("Get capabilities");
+        Ok(Capabilities {
+            version: PROTOCOL_VERSION,
+            features: crate::FEATURES.iter().map(|f| f.to_string()).collect(),
+            backends: robot.initialized(),
+            transports: robot.transports.clone(),
+            commands: Concrete::PREFIXES.to_vec(),
+            events: ConcreteType::NAMES.iter().map(|e| e.to_string()).collect(),
+        })
+    }
+}
+
+#[async_trait::async_trait]
+impl Execute for GetSchema {
+    async fn execute(&self, _: Arc<Backends>) -> anyhow::Result<Self::Return> {
+        debug!("Get schema");
+        Ok(roblib::schema::export())
+    }
+}
+
+#[async_trait::async_trait]
+impl Execute for GetChallenge {
+    async fn execute(&self, robot: Arc<Backends>) -> anyhow::Result<Self::Return> {
+        debug!("Get challenge");
+        robot.auth.challenge(&crate::control::client()?)
+    }
+}
+
+/// The connections remember the clients that authenticated successfully, UDP clients send the token every time
+#[async_trait::async_trait]
+impl Execute for Authenticate {
+    async fn execute(&self, robot: Arc<Backends>) -> anyhow::Result<Self::Return> {
+        debug!("Authenticate");
+        let Authenticate(challenge, response) = self;
+        robot.auth.authenticate(challenge, response)
+    }
+}
+
+#[async_trait::async_trait]
+impl Execute for AcquireControl {
+    async fn execute(&self, robot: Arc<Backends>) -> anyhow::Result<Self::Return> {
+        debug!("Acquire control");
+        robot.control.acquire(crate::control::client()?)
+    }
+}
+
+#[async_trait::async_trait]
+impl Execute for TakeControl {
+    async fn execute(&self, robot: Arc<Backends>) -> anyhow::Result<Self::Return> {
+        debug!("Take control");
+        robot.control.take(crate::control::client()?, &self.0)
+    }
+}
+
+#[async_trait::async_trait]
+impl Execute for ReleaseControl {
+    async fn execute(&self, robot: Arc<Backends>) -> anyhow::Result<Self::Return> {
+        debug!("Release control");
+        robot.control.release(&crate::control::client()?)
+    }
+}
+
+#[async_trait::async_trait]
+impl Execute for ListScheduled {
+    async fn execute(&self, robot: Arc<Backends>) -> anyhow::Result<Self::Return> {
+        debug!("List scheduled");
+        Ok(robot.scheduler.list())
+    }
+}
+
+#[async_trait::async_trait]
+impl Execute for CancelScheduled {
+    async fn execute(&self, robot: Arc<Backends>) -> anyhow::Result<Self::Return> {
+        debug!("Cancel scheduled {}", self.0);
+        Ok(robot.scheduler.cancel(self.0))
+    }
+}
+
 #[async_trait::async_trait]
 impl Execute for Abort {
     async fn execute(&self, robot: Arc<Backends>) -> anyhow::Result<Self::Return> {
+        if !robot.safety.allow_abort {
+            anyhow::bail!("Abort is disabled on this server");
+        }
         error!("Abort");
         robot.abort_token.cancel();
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Config;
+    use std::time::Duration;
+
+    async fn run(cmds: Vec<Concrete>) -> anyhow::Result<Option<String>> {
+        let robot = Arc::new(Backends::test(&Config::default()));
+        let ret = dispatch(Concrete::Batch(Batch::new(cmds)), robot).await?;
+        Ok(ret.map(|r| serde_json::to_string(&r).unwrap()))
+    }
+
+    #[tokio::test]
+    async fn batch_returns_flat() {
+        let ret = run(vec![Nop.into(), GetUptime.into(), Nop.into()]).await;
+        let ret = ret.unwrap().unwrap();
+        let _: ((), Duration, ()) = serde_json::from_str(&ret).unwrap();
+    }
+
+    #[tokio::test]
+    async fn batch_always_replies() {
+        assert_eq!(run(vec![]).await.unwrap().as_deref(), Some("null"));
+        assert_eq!(
+            run(vec![Nop.into(), Nop.into()]).await.unwrap().as_deref(),
+            Some("[null,null]")
+        );
+
+        let missing = Custom("missing".into(), vec![]);
+        let err = run(vec![Nop.into(), missing.into()]).await.unwrap_err();
+        assert!(err.to_string().starts_with("Batch command 1"), "{err}");
+    }
+
+    /// Schedules `cmd` as `client` to run after `delay`, then waits for it to
+    async fn schedule(robot: &Arc<Backends>, client: &str, cmd: Concrete, delay: Duration) {
+        let schedule = Schedule(When::After(delay), Box::new(cmd));
+        let exec = dispatch(schedule.into(), robot.clone());
+        crate::control::CLIENT
+            .scope(client.into(), exec)
+            .await
+            .unwrap();
+        tokio::time::sleep(delay + Duration::from_millis(50)).await;
+    }
+
+    fn ran(robot: &Backends, line: &str) -> bool {
+        robot.metrics.encode().unwrap().lines().any(|l| l == line)
+    }
+
+    #[tokio::test]
+    async fn scheduled_runs_as_client() {
+        let robot = Arc::new(Backends::test(&Config::default()));
+        schedule(&robot, "tcp a", GetUptime.into(), Duration::ZERO).await;
+        assert!(ran(
+            &robot,
+            r#"roblib_commands_total{command="GetUptime",transport="tcp"} 1"#
+        ));
+    }
+
+    #[tokio::test]
+    async fn scheduled_needs_control_when_it_fires() {
+        let robot = Arc::new(Backends::test(&Config::default()));
+        let drive = || Concrete::from(Custom("drive".into(), vec![]));
+        let line = r#"roblib_commands_total{command="Custom",transport="tcp"} 1"#;
+
+        robot.control.acquire("tcp a".into()).unwrap();
+        let exec = schedule(&robot, "tcp a", drive(), Duration::from_millis(50));
+        let takeover = async {
+            robot.control.release("tcp a").unwrap();
+            robot.control.acquire("tcp b".into()).unwrap();
+        };
+        tokio::join!(exec, takeover);
+        assert!(!ran(&robot, line));
+
+        robot.control.release("tcp b").unwrap();
+        schedule(&robot, "tcp a", drive(), Duration::ZERO).await;
+        assert!(ran(&robot, line));
+    }
+
+    #[tokio::test]
+    async fn batch_rejects_acked_returns() {
+        let err = run(vec![Nop.into(), Ack::new(GetUptime).into()]).await;
+        assert!(err.unwrap_err().to_string().starts_with("Batch command 1"));
+        assert!(run(vec![Ack::new(Nop).into()]).await.is_ok());
+    }
+}
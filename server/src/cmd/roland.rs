@@ -15,14 +15,16 @@ use super::{Backends, Execute};
 impl Execute for MoveRobot {
     async fn execute(&self, robot: Arc<Backends>) -> anyhow::Result<Self::Return> {
         let MoveRobot(left, right) = *self;
+        let max = robot.safety.max_speed;
+        let (left, right) = (left.clamp(-max, max), right.clamp(-max, max));
 
         debug!("Moving robot: {left}:{right}");
 
-        #[cfg(feature = "backend")]
+        #[cfg(any(feature = "backend", feature = "sim"))]
         if let Some(r) = &robot.roland {
             r.drive(left, right)?;
 
-            #[cfg(feature = "camloc")]
+            #[cfg(all(feature = "camloc", feature = "backend"))]
             if let Some(c) = &robot.camloc {
                 let hint = roblib::camloc::get_motion_hint(left, right);
                 c.set_motion_hint(hint).await;
@@ -37,14 +39,16 @@ impl Execute for MoveRobot {
 impl Execute for MoveRobotByAngle {
     async fn execute(&self, robot: Arc<Backends>) -> anyhow::Result<Self::Return> {
         let MoveRobotByAngle(angle, speed) = *self;
+        let max = robot.safety.max_speed;
+        let speed = speed.clamp(-max, max);
 
         debug!("Moving robot by angle: {}:{}", angle, speed);
 
-        #[cfg(feature = "backend")]
+        #[cfg(any(feature = "backend", feature = "sim"))]
         if let Some(r) = &robot.roland {
             r.drive_by_angle(angle, speed)?;
 
-            #[cfg(feature = "camloc")]
+            #[cfg(all(feature = "camloc", feature = "backend"))]
             if let Some(c) = &robot.camloc {
                 let (left, right) = roblib::roland::convert_move(angle, speed);
                 let hint = roblib::camloc::get_motion_hint(left, right);
@@ -61,7 +65,7 @@ impl Execute for StopRobot {
     async fn execute(&self, robot: Arc<Backends>) -> anyhow::Result<Self::Return> {
         debug!("Stopping robot");
 
-        #[cfg(feature = "backend")]
+        #[cfg(any(feature = "backend", feature = "sim"))]
         if let Some(r) = &robot.roland {
             r.drive(0., 0.)?;
         }
@@ -77,7 +81,7 @@ impl Execute for Led {
 
         debug!("LED: {r}:{g}:{b}");
 
-        #[cfg(feature = "backend")]
+        #[cfg(any(feature = "backend", feature = "sim"))]
         if let Some(rr) = &robot.roland {
             rr.led(r, g, b)?;
         }
@@ -93,7 +97,7 @@ impl Execute for RolandServo {
 
         debug!("Servo absolute: {deg}");
 
-        #[cfg(feature = "backend")]
+        #[cfg(any(feature = "backend", feature = "sim"))]
         if robot.roland.is_some() {
             tokio::task::spawn_blocking(move || robot.roland.as_ref().unwrap().roland_servo(deg));
         }
@@ -109,7 +113,7 @@ impl Execute for Buzzer {
 
         debug!("Buzzer: {pw}");
 
-        #[cfg(feature = "backend")]
+        #[cfg(any(feature = "backend", feature = "sim"))]
         if let Some(r) = &robot.roland {
             r.buzzer(pw)?
         }
@@ -123,14 +127,14 @@ impl Execute for TrackSensor {
     async fn execute(&self, robot: Arc<Backends>) -> anyhow::Result<Self::Return> {
         debug!("Track sensor");
 
-        #[cfg(feature = "backend")]
+        #[cfg(any(feature = "backend", feature = "sim"))]
         let res = if let Some(r) = &robot.roland {
             r.track_sensor()?
         } else {
             [false, false, false, false]
         };
 
-        #[cfg(not(feature = "backend"))]
+        #[cfg(not(any(feature = "backend", feature = "sim")))]
         let res = [false, false, false, false];
 
         Ok(res)
@@ -142,7 +146,7 @@ impl Execute for UltraSensor {
     async fn execute(&self, robot: Arc<Backends>) -> anyhow::Result<Self::Return> {
         debug!("Ultra sensor");
 
-        #[cfg(feature = "backend")]
+        #[cfg(any(feature = "backend", feature = "sim"))]
         let res = if robot.roland.is_some() {
             // because it uses std::thread::sleep
             spawn_blocking(move || robot.roland.as_ref().unwrap().ultra_sensor()).await??
@@ -150,7 +154,7 @@ impl Execute for UltraSensor {
             f64::NAN
         };
 
-        #[cfg(not(feature = "backend"))]
+        #[cfg(not(any(feature = "backend", feature = "sim")))]
         let res = f64::NAN;
 
         Ok(res)
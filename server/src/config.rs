@@ -0,0 +1,630 @@
+//! The server's configuration.
+//!
+//! Read from a TOML file, `roblib.toml` in the working directory by default,
+//! or the one given with `--config` or the `ROBLIB_CONFIG` environment variable.
+//! Every key can be overridden by an environment variable named after its path,
+//! like `ROBLIB_TCP_PORT` for `port` in the `[tcp]` table.
+//! Anything left out keeps its default, see `--print-config`.
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use toml::{Table, Value};
+
+const DEFAULT_PATH: &str = "roblib.toml";
+const ENV_PREFIX: &str = "ROBLIB_";
+/// Printed instead of the secrets, see [`Config::to_toml`]
+const REDACTED: &str = "<redacted>";
+/// The environment variables from before the config file, still read without the prefix
+const LEGACY_ENV: &[&str] = &[
+    "TCP_HOST",
+    "UDP_HOST",
+    "WEB_HOST",
+    "TCP_PORT",
+    "UDP_PORT",
+    "WEB_PORT",
+    "MAX_FRAME",
+];
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Config {
+    /// The largest TCP frame or UDP datagram accepted, in bytes
+    pub max_frame: usize,
+
+    pub tcp: Transport,
+    pub udp: Transport,
+    pub web: Web,
+
+    pub log: Log,
+    pub safety: Safety,
+    pub auth: Auth,
+    pub control: Control,
+    pub cleanup: Cleanup,
+    pub tls: Tls,
+    pub record: Record,
+
+    #[cfg(feature = "camloc")]
+    pub camloc: Camloc,
+
+    #[cfg(all(feature = "roland", feature = "backend"))]
+    pub roland: roblib::roland::backend::Pins,
+
+    #[cfg(feature = "sim")]
+    pub sim: Sim,
+
+    /// The keys and environment variables that don't mean anything, warned about once logging is set up
+    #[serde(skip)]
+    pub(crate) unknown: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Transport {
+    pub enabled: bool,
+    pub host: String,
+    pub port: u16,
+}
+
+impl Transport {
+    fn new(port: u16) -> Self {
+        Self {
+            enabled: true,
+            host: "0.0.0.0".into(),
+            port,
+        }
+    }
+}
+
+/// The HTTP server, which also serves the WebSocket endpoint
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Web {
+    pub enabled: bool,
+    pub host: String,
+    pub port: u16,
+    /// Commands over HTTP POST at `/cmd`
+    pub http: bool,
+    /// WebSocket at `/ws`
+    pub ws: bool,
+    /// Prometheus metrics at `GET /metrics`
+    pub metrics: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Log {
+    /// In the format of `RUST_LOG`, which takes precedence if it's set
+    pub level: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Safety {
+    /// The fastest the motors are allowed to go, between 0 and 1. Faster drive commands are slowed down to it.
+    pub max_speed: f64,
+    /// Whether clients can shut down the server with `Abort`
+    pub allow_abort: bool,
+    /// How long the robot keeps going without hearing from the client that last moved it,
+    /// in milliseconds, off if it's 0. See `roblib::cmd::Heartbeat`
+    pub watchdog_ms: u64,
+}
+
+/// Clients have to prove they know the secret before running commands, see `roblib::cmd::Authenticate`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Auth {
+    /// The shared secret, authentication is off if it's empty
+    pub secret: String,
+    /// How long an HTTP or UDP session token stays valid without being used, in seconds
+    pub token_expiry_s: u64,
+}
+
+/// The exclusive control of the robot, see `roblib::cmd::AcquireControl`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Control {
+    /// Whether clients have to acquire the control before moving the robot, even if no one else is in control
+    pub required: bool,
+    /// How long the client in control keeps it without sending any commands, in seconds
+    pub lease_s: u64,
+    /// Lets a client take the control away from another one with `TakeControl`, disabled if it's empty
+    pub admin_token: String,
+}
+
+/// What's undone when a client disconnects
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Cleanup {
+    /// Drop the client's event subscriptions
+    pub subscriptions: bool,
+    /// Stop the robot if the client was the last one to drive it
+    pub motors: bool,
+    /// Clear the PWM and servo signals on the GPIO pins the client set them on
+    pub pins: bool,
+    /// Release the control of the robot if the client had it
+    pub control: bool,
+    /// UDP clients are considered gone after this many seconds without sending anything, never if it's 0
+    pub udp_session_s: u64,
+}
+
+/// TLS on the TCP listener and the webserver, UDP is always unencrypted
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Tls {
+    pub tcp: bool,
+    /// `https://` and `wss://`
+    pub web: bool,
+    /// The certificate chain, a PEM file.
+    /// If it's left empty along with `key`, a self-signed certificate is generated, only meant for development.
+    pub cert: String,
+    /// The certificate's private key, a PEM file
+    pub key: String,
+    /// The names the self-signed certificate is valid for, hostnames or ip addresses
+    pub self_signed_names: Vec<String>,
+    /// Where the CA of the self-signed certificate is saved, for clients to trust it
+    pub self_signed_cert: String,
+}
+
+/// Recording the commands, their return values and the events to a file, see `--dump` and `--replay`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Record {
+    /// The file to record to, overwritten on every start. Recording is off if it's empty
+    pub path: String,
+    /// Whether the events sent to the clients are recorded too
+    pub events: bool,
+}
+
+#[cfg(feature = "camloc")]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Camloc {
+    pub enabled: bool,
+    /// Where the service listens for the cameras
+    pub address: std::net::SocketAddr,
+    /// The smallest angle between two cameras for their data to be combined, in degrees
+    pub min_camera_angle_diff: f64,
+    /// How long a camera's data is used for, in milliseconds
+    pub data_validity_ms: u64,
+}
+
+/// The simulated backends, used instead of the real ones with the `sim` feature
+#[cfg(feature = "sim")]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Sim {
+    /// Where the simulated robot drives around
+    #[cfg(feature = "roland")]
+    pub arena: roblib::roland::sim::Arena,
+    /// Output pins connected to input pins, `[output, input]` pairs
+    #[cfg(feature = "gpio")]
+    pub wires: Vec<[u8; 2]>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            max_frame: roblib::cmd::MAX_FRAME,
+
+            tcp: Transport::new(1110),
+            udp: Transport::new(1110),
+            web: Web {
+                enabled: true,
+                host: "0.0.0.0".into(),
+                port: 1111,
+                http: true,
+                ws: true,
+                metrics: true,
+            },
+
+            log: Log {
+                level: "roblib_server=debug,roblib=debug".into(),
+            },
+            safety: Safety {
+                max_speed: 1.,
+                allow_abort: true,
+                watchdog_ms: 0,
+            },
+            auth: Auth {
+                secret: String::new(),
+                token_expiry_s: 60 * 60,
+            },
+            control: Control {
+                required: false,
+                lease_s: 30,
+                admin_token: String::new(),
+            },
+            cleanup: Cleanup {
+                subscriptions: true,
+                motors: true,
+                pins: true,
+                control: true,
+                udp_session_s: 60,
+            },
+            tls: Tls {
+                tcp: false,
+                web: false,
+                cert: String::new(),
+                key: String::new(),
+                self_signed_names: vec!["localhost".into(), "127.0.0.1".into()],
+                self_signed_cert: "roblib-self-signed.pem".into(),
+            },
+            record: Record {
+                path: String::new(),
+                events: true,
+            },
+
+            #[cfg(feature = "camloc")]
+            camloc: Camloc {
+                enabled: true,
+                address: (std::net::Ipv4Addr::LOCALHOST, roblib::camloc::MAIN_PORT).into(),
+                min_camera_angle_diff: 15.,
+                data_validity_ms: 500,
+            },
+
+            #[cfg(all(feature = "roland", feature = "backend"))]
+            roland: Default::default(),
+
+            #[cfg(feature = "sim")]
+            sim: Sim {
+                #[cfg(feature = "roland")]
+                arena: Default::default(),
+                #[cfg(feature = "gpio")]
+                wires: vec![],
+            },
+
+            unknown: vec![],
+        }
+    }
+}
+
+impl Config {
+    /// Reads the file at `path`, or the default one if it exists, then applies the environment variables
+    pub fn load(path: Option<&Path>) -> Result<Self> {
+        Self::load_with(path, std::env::vars())
+    }
+
+    /// Same as [`Config::load`], with the environment variables in `env`
+    fn load_with(
+        path: Option<&Path>,
+        env: impl IntoIterator<Item = (String, String)>,
+    ) -> Result<Self> {
+        let env: Vec<_> = env.into_iter().collect();
+        let defaults = Table::try_from(Self::default())?;
+        let mut table = defaults.clone();
+
+        let env_path = env
+            .iter()
+            .find(|(name, _)| name == "ROBLIB_CONFIG")
+            .map(|(_, path)| PathBuf::from(path));
+        let path = path.map(Path::to_path_buf).or(env_path);
+        let file = match &path {
+            Some(p) => Some(std::fs::read_to_string(p).with_context(|| p.display().to_string())?),
+            None => std::fs::read_to_string(DEFAULT_PATH).ok(),
+        };
+        if let Some(file) = file {
+            let name = path.as_deref().unwrap_or(DEFAULT_PATH.as_ref());
+            let file: Table = file
+                .parse()
+                .with_context(|| format!("invalid config {}", name.display()))?;
+            merge(&mut table, file);
+        }
+
+        let mut unknown = vec![];
+        for (name, value) in env {
+            let key = match name.strip_prefix(ENV_PREFIX) {
+                Some(key) => key,
+                None if LEGACY_ENV.contains(&&name[..]) => &name,
+                None => continue,
+            };
+            if key == "CONFIG" {
+                continue;
+            }
+            if !set(&mut table, &defaults, &key.to_lowercase(), &value) {
+                unknown.push(format!("${name}"));
+            }
+        }
+
+        let mut config: Self =
+            serde_ignored::deserialize(Value::Table(table), |path| unknown.push(path.to_string()))
+                .context("invalid config")?;
+        config.unknown = unknown;
+
+        if !(0. ..=1.).contains(&config.safety.max_speed) {
+            anyhow::bail!("safety.max_speed must be between 0 and 1");
+        }
+        if config.control.lease_s == 0 {
+            anyhow::bail!("control.lease_s must be positive");
+        }
+        if config.tls.cert.is_empty() != config.tls.key.is_empty() {
+            anyhow::bail!("tls.cert and tls.key have to be set together");
+        }
+
+        Ok(config)
+    }
+
+    /// The names of the transports clients can connect with
+    pub fn transports(&self) -> Vec<String> {
+        let web = |on| self.web.enabled && on;
+        let tls = |on, plain, tls| if on { tls } else { plain };
+        [
+            (tls(self.tls.tcp, "tcp", "tcp+tls"), self.tcp.enabled),
+            ("udp", self.udp.enabled),
+            (tls(self.tls.web, "http", "https"), web(self.web.http)),
+            (tls(self.tls.web, "ws", "wss"), web(self.web.ws)),
+        ]
+        .into_iter()
+        .filter(|(_, on)| *on)
+        .map(|(name, _)| name.to_string())
+        .collect()
+    }
+
+    /// The effective configuration, in the format of the file, with the secrets redacted
+    pub fn to_toml(&self) -> Result<String> {
+        let mut config = self.clone();
+        for secret in [&mut config.auth.secret, &mut config.control.admin_token] {
+            if !secret.is_empty() {
+                *secret = REDACTED.into();
+            }
+        }
+        Ok(toml::to_string_pretty(&config)?)
+    }
+}
+
+/// Overwrites the values in `table` with the ones in `other`, keeping the rest of the nested tables
+fn merge(table: &mut Table, other: Table) {
+    for (key, value) in other {
+        match (table.get_mut(&key), value) {
+            (Some(Value::Table(t)), Value::Table(o)) => merge(t, o),
+            (_, value) => {
+                table.insert(key, value);
+            }
+        }
+    }
+}
+
+/// Sets the key `name` refers to, the path to it joined with `_`.
+/// Keys can contain `_` themselves, so the existing ones in `defaults` decide where to split.
+fn set(table: &mut Table, defaults: &Table, name: &str, value: &str) -> bool {
+    for (key, default) in defaults {
+        match default {
+            Value::Table(defaults) => {
+                let Some(rest) = name
+                    .strip_prefix(&key[..])
+                    .and_then(|r| r.strip_prefix('_'))
+                else {
+                    continue;
+                };
+                let entry = table
+                    .entry(key)
+                    .or_insert_with(|| Value::Table(Table::new()));
+                if let Value::Table(t) = entry {
+                    if set(t, defaults, rest, value) {
+                        return true;
+                    }
+                }
+            }
+            // strings are taken as they are, even if they look like a number
+            Value::String(_) if key == name => {
+                table.insert(key.clone(), Value::String(value.into()));
+                return true;
+            }
+            _ if key == name => {
+                table.insert(key.clone(), parse_value(value));
+                return true;
+            }
+            _ => {}
+        }
+    }
+    false
+}
+
+/// Numbers, booleans and arrays are written like in TOML, anything else is a string
+fn parse_value(s: &str) -> Value {
+    format!("v = {s}")
+        .parse::<Table>()
+        .ok()
+        .and_then(|mut t| t.remove("v"))
+        .unwrap_or_else(|| Value::String(s.into()))
+}
+
+/// The command line options
+#[derive(Debug, Default)]
+pub struct Args {
+    /// `--config <path>`
+    pub config: Option<PathBuf>,
+    /// `--print-config`, prints the effective configuration and exits
+    pub print_config: bool,
+    /// `--replay <path>`, runs the commands in a recording, then exits
+    pub replay: Option<PathBuf>,
+    /// `--dump <path>`, prints a recording in the text format and exits
+    pub dump: Option<PathBuf>,
+}
+
+impl Args {
+    pub const USAGE: &'static str =
+        "usage: roblib-server [--config <path>] [--print-config] [--replay <recording>] [--dump <recording>]";
+
+    pub fn parse() -> Result<Self> {
+        Self::parse_from(std::env::args().skip(1))
+    }
+
+    /// Same as [`Args::parse`], without the name of the program
+    fn parse_from(args: impl IntoIterator<Item = String>) -> Result<Self> {
+        let mut it = args.into_iter();
+        let mut args = Self::default();
+        while let Some(arg) = it.next() {
+            match &arg[..] {
+                "--config" | "-c" => {
+                    let path = it.next().context("--config needs a path")?;
+                    args.config = Some(path.into());
+                }
+                "--print-config" => args.print_config = true,
+                "--replay" => {
+                    let path = it.next().context("--replay needs a path")?;
+                    args.replay = Some(path.into());
+                }
+                "--dump" => {
+                    let path = it.next().context("--dump needs a path")?;
+                    args.dump = Some(path.into());
+                }
+                _ => anyhow::bail!("unknown argument: {arg}"),
+            }
+        }
+        Ok(args)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn env(vars: &[(&str, &str)]) -> Vec<(String, String)> {
+        vars.iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    /// A config file in the temp dir, removed when dropped
+    struct File(PathBuf);
+
+    impl File {
+        fn new(name: &str, contents: &str) -> Self {
+            let name = format!("roblib-{}-{name}.toml", std::process::id());
+            let path = std::env::temp_dir().join(name);
+            std::fs::write(&path, contents).unwrap();
+            Self(path)
+        }
+    }
+
+    impl Drop for File {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_file(&self.0);
+        }
+    }
+
+    #[test]
+    fn defaults() {
+        let config = Config::load_with(None, env(&[("HOME", "/root")])).unwrap();
+        assert_eq!(
+            config.to_toml().unwrap(),
+            Config::default().to_toml().unwrap()
+        );
+        assert!(config.unknown.is_empty());
+    }
+
+    #[test]
+    fn file_then_env() {
+        let file = File::new(
+            "file_then_env",
+            "max_frame = 100\n[tcp]\nport = 2000\n[web]\nport = 2001\n[safety]\nmax_speed = 0.5\n",
+        );
+
+        let config = Config::load_with(Some(&file.0), []).unwrap();
+        assert_eq!(config.max_frame, 100);
+        assert_eq!(config.tcp.port, 2000);
+        // the rest of the table keeps its defaults
+        assert_eq!(config.tcp.host, "0.0.0.0");
+        assert!(config.tcp.enabled);
+        assert_eq!(config.safety.max_speed, 0.5);
+        assert!(config.safety.allow_abort);
+
+        let vars = env(&[
+            ("ROBLIB_TCP_PORT", "3000"),
+            ("ROBLIB_SAFETY_ALLOW_ABORT", "false"),
+            ("WEB_PORT", "3001"),
+            ("MAX_FRAME", "200"),
+        ]);
+        let config = Config::load_with(Some(&file.0), vars).unwrap();
+        assert_eq!(config.tcp.port, 3000);
+        assert!(!config.safety.allow_abort);
+        assert_eq!(config.safety.max_speed, 0.5);
+        assert_eq!(config.web.port, 3001);
+        assert_eq!(config.max_frame, 200);
+    }
+
+    #[test]
+    fn file_from_env() {
+        let file = File::new("file_from_env", "[udp]\nenabled = false\n");
+        let path = file.0.display().to_string();
+
+        let config = Config::load_with(None, env(&[("ROBLIB_CONFIG", &path)])).unwrap();
+        assert!(!config.udp.enabled);
+        assert!(config.unknown.is_empty());
+
+        // the argument wins
+        let other = File::new("file_from_env_arg", "[udp]\nport = 5\n");
+        let config = Config::load_with(Some(&other.0), env(&[("ROBLIB_CONFIG", &path)])).unwrap();
+        assert!(config.udp.enabled);
+        assert_eq!(config.udp.port, 5);
+
+        let missing = Path::new("/nonexistent/roblib.toml");
+        assert!(Config::load_with(Some(missing), []).is_err());
+    }
+
+    #[test]
+    fn env_values() {
+        let vars = env(&[
+            // strings stay strings, even if they look like a number
+            ("ROBLIB_AUTH_SECRET", "1234"),
+            ("ROBLIB_TLS_SELF_SIGNED_NAMES", r#"["a", "b"]"#),
+            ("ROBLIB_CONTROL_LEASE_S", "5"),
+        ]);
+        let config = Config::load_with(None, vars).unwrap();
+        assert_eq!(config.auth.secret, "1234");
+        assert_eq!(config.tls.self_signed_names, ["a", "b"]);
+        assert_eq!(config.control.lease_s, 5);
+    }
+
+    #[test]
+    fn unknown_keys() {
+        let file = File::new("unknown_keys", "bogus = 1\n[tcp]\nnope = 2\n");
+        let vars = env(&[("ROBLIB_NOPE", "1"), ("UNRELATED", "1")]);
+        let config = Config::load_with(Some(&file.0), vars).unwrap();
+        assert!(config.unknown.contains(&"bogus".to_string()));
+        assert!(config.unknown.contains(&"tcp.nope".to_string()));
+        assert!(config.unknown.contains(&"$ROBLIB_NOPE".to_string()));
+        assert_eq!(config.unknown.len(), 3);
+    }
+
+    #[test]
+    fn invalid() {
+        let load = |vars: &[(&str, &str)]| Config::load_with(None, env(vars));
+        assert!(load(&[("ROBLIB_SAFETY_MAX_SPEED", "2")]).is_err());
+        assert!(load(&[("ROBLIB_CONTROL_LEASE_S", "0")]).is_err());
+        assert!(load(&[("ROBLIB_TLS_CERT", "cert.pem")]).is_err());
+        assert!(load(&[("ROBLIB_TCP_PORT", "not a port")]).is_err());
+
+        let file = File::new("invalid", "[tcp\n");
+        assert!(Config::load_with(Some(&file.0), []).is_err());
+    }
+
+    #[test]
+    fn secrets_redacted() {
+        let mut config = Config::default();
+        assert!(!config.to_toml().unwrap().contains(REDACTED));
+
+        config.auth.secret = "hunter2".into();
+        config.control.admin_token = "letmein".into();
+        let toml = config.to_toml().unwrap();
+        assert!(!toml.contains("hunter2"));
+        assert!(!toml.contains("letmein"));
+        assert_eq!(toml.matches(REDACTED).count(), 2);
+        // only the printed one
+        assert_eq!(config.auth.secret, "hunter2");
+    }
+
+    #[test]
+    fn args() {
+        let parse = |args: &[&str]| Args::parse_from(args.iter().map(|a| a.to_string()));
+
+        let args = parse(&[]).unwrap();
+        assert!(args.config.is_none() && !args.print_config);
+        assert!(args.replay.is_none() && args.dump.is_none());
+
+        let args = parse(&["--config", "a.toml", "--print-config"]).unwrap();
+        assert_eq!(args.config.as_deref(), Some(Path::new("a.toml")));
+        assert!(args.print_config);
+
+        let args = parse(&["-c", "b.toml", "--replay", "r.rec"]).unwrap();
+        assert_eq!(args.config.as_deref(), Some(Path::new("b.toml")));
+        assert_eq!(args.replay.as_deref(), Some(Path::new("r.rec")));
+
+        let args = parse(&["--dump", "d.rec"]).unwrap();
+        assert_eq!(args.dump.as_deref(), Some(Path::new("d.rec")));
+
+        assert!(parse(&["--config"]).is_err());
+        assert!(parse(&["--replay"]).is_err());
+        assert!(parse(&["--dump"]).is_err());
+        assert!(parse(&["--verbose"]).is_err());
+    }
+}
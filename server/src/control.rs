@@ -0,0 +1,333 @@
+use crate::config;
+use roblib::cmd::{self, Concrete};
+use std::{
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+use subtle::ConstantTimeEq;
+use tokio::sync::{broadcast, Notify};
+use tokio_util::sync::CancellationToken;
+
+tokio::task_local! {
+    /// The client running the command, set by the transports.
+    /// Scheduled commands run as the client that scheduled them.
+    pub(crate) static CLIENT: String;
+}
+
+/// The client running the current command, see [`CLIENT`]
+pub(crate) fn client() -> anyhow::Result<String> {
+    CLIENT
+        .try_with(Clone::clone)
+        .map_err(|_| anyhow::anyhow!("Not run by a client"))
+}
+
+/// The exclusive control of the robot, see [`cmd::AcquireControl`].
+/// Clients are told apart by the name the transports give them, like `tcp 127.0.0.1:5000`.
+pub(crate) struct Control {
+    required: bool,
+    lease: Duration,
+    admin_token: Option<String>,
+    driver: Mutex<Option<Lease>>,
+    /// Wakes up [`Control::expire`] when a new lease starts
+    acquired: Notify,
+    /// The client now in control, forwarded to the subscribers by the event bus
+    changed: broadcast::Sender<Option<String>>,
+}
+
+struct Lease {
+    client: String,
+    until: Instant,
+}
+
+impl Control {
+    pub fn new(config: &config::Control) -> Self {
+        Self {
+            required: config.required,
+            lease: Duration::from_secs(config.lease_s),
+            admin_token: (!config.admin_token.is_empty()).then(|| config.admin_token.clone()),
+            driver: Default::default(),
+            acquired: Notify::new(),
+            changed: broadcast::channel(16).0,
+        }
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<Option<String>> {
+        self.changed.subscribe()
+    }
+
+    /// Whether `client` can run `cmd`, observers can't run the ones that move the robot.
+    /// Any command from the client in control renews its lease.
+    pub fn check(&self, client: &str, cmd: &Concrete) -> Result<(), cmd::Error> {
+        let mut driver = self.driver.lock().unwrap();
+        match &mut *driver {
+            Some(lease) if lease.client == client => {
+                lease.until = Instant::now() + self.lease;
+                Ok(())
+            }
+            _ if !controls(cmd) => Ok(()),
+            Some(lease) if lease.until > Instant::now() => Err(cmd::Error::new(
+                cmd::ErrorCode::NotInControl,
+                format!("{} is in control of the robot", lease.client),
+            )),
+            _ if self.required => Err(cmd::Error::new(
+                cmd::ErrorCode::NotInControl,
+                format!("acquire control before running {}", cmd.get_prefix()),
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    /// Gives the control to `client` if no one else has it, or renews its lease
+    pub fn acquire(&self, client: String) -> anyhow::Result<Duration> {
+        let mut driver = self.driver.lock().unwrap();
+        if let Some(lease) = &*driver {
+            if lease.client != client && lease.until > Instant::now() {
+                anyhow::bail!("{} is in control of the robot", lease.client);
+            }
+        }
+        self.set(&mut driver, Some(client));
+        Ok(self.lease)
+    }
+
+    /// Gives the control to `client`, even if another client has it
+    pub fn take(&self, client: String, admin_token: &str) -> anyhow::Result<Duration> {
+        match &self.admin_token {
+            Some(token) if bool::from(token.as_bytes().ct_eq(admin_token.as_bytes())) => (),
+            Some(_) => anyhow::bail!("Wrong admin token"),
+            None => anyhow::bail!("Taking control is disabled on this server"),
+        }
+        let mut driver = self.driver.lock().unwrap();
+        self.set(&mut driver, Some(client));
+        Ok(self.lease)
+    }
+
+    pub fn release(&self, client: &str) -> anyhow::Result<()> {
+        let mut driver = self.driver.lock().unwrap();
+        match &*driver {
+            Some(lease) if lease.client == client => {
+                self.set(&mut driver, None);
+                Ok(())
+            }
+            _ => anyhow::bail!("Not in control of the robot"),
+        }
+    }
+
+    /// Releases the control if `client` had it, for when it disconnects
+    pub fn disconnected(&self, client: &str) {
+        let mut driver = self.driver.lock().unwrap();
+        if driver.as_ref().is_some_and(|lease| lease.client == client) {
+            log::debug!("{client} disconnected while in control");
+            self.set(&mut driver, None);
+        }
+    }
+
+    /// Releases the control when the lease runs out, until the server is aborted
+    pub async fn expire(&self, abort: &CancellationToken) {
+        loop {
+            let until = self
+                .driver
+                .lock()
+                .unwrap()
+                .as_ref()
+                .map(|lease| lease.until);
+            let sleep = async {
+                match until {
+                    Some(until) => tokio::time::sleep_until(until.into()).await,
+                    None => std::future::pending().await,
+                }
+            };
+
+            tokio::select! {
+                _ = abort.cancelled() => return,
+                _ = self.acquired.notified() => continue,
+                _ = sleep => (),
+            }
+
+            // the lease might have been renewed since
+            let mut driver = self.driver.lock().unwrap();
+            if let Some(lease) = &*driver {
+                if lease.until <= Instant::now() {
+                    log::debug!("Control lease of {} ran out", lease.client);
+                    self.set(&mut driver, None);
+                }
+            }
+        }
+    }
+
+    /// Starts a new lease for `client`, announcing it if the control changed hands
+    fn set(&self, driver: &mut Option<Lease>, client: Option<String>) {
+        let previous = driver.take().map(|lease| lease.client);
+        *driver = client.clone().map(|client| Lease {
+            client,
+            until: Instant::now() + self.lease,
+        });
+
+        if previous != client {
+            log::info!("Control: {previous:?} -> {client:?}");
+            // no receivers if there's no event bus yet
+            let _ = self.changed.send(client);
+        }
+        self.acquired.notify_one();
+    }
+}
+
+/// Whether `cmd` moves the robot or changes what it does, which observers can't do
+pub(crate) fn controls(cmd: &Concrete) -> bool {
+    match cmd {
+        Concrete::Batch(b) => b.0.iter().any(controls),
+        Concrete::Ack(a) => controls(&a.0),
+        Concrete::Schedule(s) => controls(&s.1),
+
+        #[cfg(feature = "roland")]
+        Concrete::TrackSensor(_) | Concrete::UltraSensor(_) => false,
+        #[cfg(feature = "gpio")]
+        Concrete::ReadPin(_) => false,
+        #[cfg(feature = "camloc")]
+        Concrete::GetPosition(_) => false,
+
+        Concrete::Subscribe(_)
+        | Concrete::Unsubscribe(_)
+        | Concrete::Nop(_)
+        | Concrete::GetUptime(_)
+        | Concrete::GetCapabilities(_)
+        | Concrete::GetSchema(_)
+        | Concrete::GetChallenge(_)
+        | Concrete::Authenticate(_)
+        | Concrete::AcquireControl(_)
+        | Concrete::TakeControl(_)
+        | Concrete::ReleaseControl(_)
+        | Concrete::Heartbeat(_)
+        | Concrete::ListScheduled(_) => false,
+
+        // the custom commands might move the robot too
+        _ => true,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use roblib::cmd::{Custom, Heartbeat};
+
+    fn control(required: bool) -> Control {
+        Control::new(&config::Control {
+            required,
+            lease_s: 30,
+            admin_token: "admin".into(),
+        })
+    }
+
+    /// Custom commands might move the robot, so observers can't run them
+    fn drive() -> Concrete {
+        Custom("drive".into(), vec![]).into()
+    }
+
+    fn refused(res: Result<(), cmd::Error>) -> bool {
+        matches!(res, Err(cmd::Error(cmd::ErrorCode::NotInControl, _)))
+    }
+
+    #[test]
+    fn acquire_and_release() {
+        let control = control(false);
+        let mut changes = control.subscribe();
+
+        // no one is in control
+        assert!(control.check("tcp b", &drive()).is_ok());
+
+        assert_eq!(
+            control.acquire("tcp a".into()).unwrap(),
+            Duration::from_secs(30)
+        );
+        assert_eq!(changes.try_recv().unwrap().as_deref(), Some("tcp a"));
+        assert!(control.check("tcp a", &drive()).is_ok());
+        assert!(refused(control.check("tcp b", &drive())));
+        // observers can still do what doesn't move the robot
+        assert!(control.check("tcp b", &Heartbeat.into()).is_ok());
+
+        assert!(control.acquire("tcp b".into()).is_err());
+        // renewing doesn't announce anything
+        assert!(control.acquire("tcp a".into()).is_ok());
+        assert!(changes.try_recv().is_err());
+
+        assert!(control.release("tcp b").is_err());
+        control.release("tcp a").unwrap();
+        assert_eq!(changes.try_recv().unwrap(), None);
+        assert!(control.check("tcp b", &drive()).is_ok());
+        assert!(control.release("tcp a").is_err());
+    }
+
+    #[test]
+    fn required() {
+        let control = control(true);
+        assert!(refused(control.check("tcp a", &drive())));
+        assert!(control.check("tcp a", &Heartbeat.into()).is_ok());
+
+        control.acquire("tcp a".into()).unwrap();
+        assert!(control.check("tcp a", &drive()).is_ok());
+    }
+
+    #[test]
+    fn take() {
+        let control = control(false);
+        control.acquire("tcp a".into()).unwrap();
+
+        assert!(control.take("tcp b".into(), "wrong").is_err());
+        assert!(control.take("tcp b".into(), "").is_err());
+        assert!(refused(control.check("tcp b", &drive())));
+
+        control.take("tcp b".into(), "admin").unwrap();
+        assert!(control.check("tcp b", &drive()).is_ok());
+        assert!(refused(control.check("tcp a", &drive())));
+
+        let disabled = Control::new(&config::Control {
+            required: false,
+            lease_s: 30,
+            admin_token: String::new(),
+        });
+        assert!(disabled.take("tcp b".into(), "").is_err());
+    }
+
+    #[test]
+    fn any_command_renews() {
+        let control = control(false);
+        control.acquire("tcp a".into()).unwrap();
+
+        let soon = Instant::now() + Duration::from_secs(1);
+        control.driver.lock().unwrap().as_mut().unwrap().until = soon;
+        control.check("tcp a", &Heartbeat.into()).unwrap();
+        let until = control.driver.lock().unwrap().as_ref().unwrap().until;
+        assert!(until > soon);
+
+        // not by the others
+        control.check("tcp b", &Heartbeat.into()).unwrap();
+        let after = control.driver.lock().unwrap().as_ref().unwrap().until;
+        assert_eq!(until, after);
+    }
+
+    #[tokio::test]
+    async fn expires() {
+        let control = control(false);
+        control.acquire("tcp a".into()).unwrap();
+        let mut changes = control.subscribe();
+        control.driver.lock().unwrap().as_mut().unwrap().until = Instant::now();
+
+        let abort = CancellationToken::new();
+        let changed = tokio::select! {
+            _ = control.expire(&abort) => unreachable!(),
+            c = changes.recv() => c.unwrap(),
+            _ = tokio::time::sleep(Duration::from_secs(5)) => panic!("the lease didn't run out"),
+        };
+        assert_eq!(changed, None);
+        assert!(control.check("tcp b", &drive()).is_ok());
+    }
+
+    #[test]
+    fn disconnected() {
+        let control = control(false);
+        control.acquire("tcp a".into()).unwrap();
+        control.disconnected("tcp b");
+        assert!(refused(control.check("tcp b", &drive())));
+        control.disconnected("tcp a");
+        assert!(control.check("tcp b", &drive()).is_ok());
+    }
+}
@@ -0,0 +1,105 @@
+//! Commands and events defined outside of roblib
+//!
+//! ```ignore
+//! let mut custom = roblib_server::custom::Registry::new();
+//! custom.command(|MyCommand(x): MyCommand| async move { Ok(x * 2) });
+//!
+//! let emitter = custom.emitter();
+//! tokio::spawn(async move { emitter.emit(MyEvent, 42) });
+//!
+//! roblib_server::run(custom).await
+//! ```
+
+use crate::cmd::Return;
+use futures_util::{future::BoxFuture, FutureExt};
+use roblib::{
+    cmd::CustomCommand,
+    event::{ConcreteType, ConcreteValue, CustomEvent, CustomValue, Typed},
+};
+use std::{collections::HashMap, future::Future};
+use tokio::sync::mpsc;
+
+type Handler = Box<dyn Fn(&[u8]) -> BoxFuture<'static, anyhow::Result<Return>> + Send + Sync>;
+
+pub(crate) type Commands = HashMap<&'static str, Handler>;
+pub(crate) type Events = mpsc::UnboundedReceiver<(ConcreteType, ConcreteValue)>;
+
+/// Collects the handlers for custom commands and the sources of custom events before the server starts
+pub struct Registry {
+    commands: Commands,
+    events: (mpsc::UnboundedSender<(ConcreteType, ConcreteValue)>, Events),
+}
+
+impl Default for Registry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Registry {
+    pub fn new() -> Self {
+        Self {
+            commands: HashMap::new(),
+            events: mpsc::unbounded_channel(),
+        }
+    }
+
+    /// Registers `handler` for the custom command `C`
+    ///
+    /// # Panics
+    /// if a handler is already registered under `C::NAME`
+    pub fn command<C, F, Fut>(&mut self, handler: F) -> &mut Self
+    where
+        C: CustomCommand,
+        F: Fn(C) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = anyhow::Result<C::Return>> + Send + 'static,
+    {
+        let handler: Handler = Box::new(move |payload| {
+            let cmd: C = match bincode::Options::deserialize(bincode::options(), payload) {
+                Ok(c) => c,
+                Err(e) => return futures_util::future::ready(Err(e.into())).boxed(),
+            };
+
+            handler(cmd)
+                .map(|res| {
+                    let ret = res?;
+                    Ok(if std::mem::size_of::<C::Return>() == 0 {
+                        None
+                    } else {
+                        Some(Box::new(ret) as Box<dyn erased_serde::Serialize + Send>)
+                    })
+                })
+                .boxed()
+        });
+
+        if self.commands.insert(C::NAME, handler).is_some() {
+            panic!("Custom command {} registered twice", C::NAME);
+        }
+        self
+    }
+
+    /// Returns a handle for emitting custom events to the clients subscribed to them
+    pub fn emitter(&self) -> Emitter {
+        Emitter(self.events.0.clone())
+    }
+
+    pub(crate) fn split(self) -> (Commands, Events) {
+        (self.commands, self.events.1)
+    }
+}
+
+#[derive(Clone)]
+pub struct Emitter(mpsc::UnboundedSender<(ConcreteType, ConcreteValue)>);
+
+impl Emitter {
+    /// Sends `item` to every client subscribed to `ev`, does nothing if there are none
+    pub fn emit<E: CustomEvent>(&self, ev: E, item: E::Item) {
+        let msg = (
+            Typed(ev).into(),
+            ConcreteValue::Custom(CustomValue::new(item)),
+        );
+
+        // only fails if the server has shut down
+        let _ = self.0.send(msg);
+    }
+}
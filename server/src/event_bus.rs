@@ -27,17 +27,18 @@ pub(crate) struct EventBus {
     pub(self) robot: Arc<crate::Backends>,
     pub clients: RwLock<HashMap<ConcreteType, Vec<SubscriptionId>>>,
 
-    pub bus_tcp: transports::tcp::Tx,
-    pub bus_udp: transports::udp::Tx,
-    pub bus_ws: transports::ws::Tx,
+    /// `None` if the transport isn't running
+    pub bus_tcp: Option<transports::tcp::Tx>,
+    pub bus_udp: Option<transports::udp::Tx>,
+    pub bus_ws: Option<transports::ws::Tx>,
 }
 #[allow(dead_code)]
 impl EventBus {
     pub fn new(
         robot: Arc<crate::Backends>,
-        bus_tcp: transports::tcp::Tx,
-        bus_udp: transports::udp::Tx,
-        bus_ws: transports::ws::Tx,
+        bus_tcp: Option<transports::tcp::Tx>,
+        bus_udp: Option<transports::udp::Tx>,
+        bus_ws: Option<transports::ws::Tx>,
     ) -> Self {
         Self {
             robot,
@@ -66,15 +67,24 @@ impl EventBus {
     }
 
     fn send(&self, event: (ConcreteType, ConcreteValue), client: &SubscriptionId) {
+        self.robot.recorder.event(client, &event);
+
+        // only running transports have subscribers
         match client {
             SubscriptionId::Tcp(addr, id) => {
-                self.bus_tcp.send((event.1.clone(), (*addr, *id))).unwrap();
+                if let Some(bus) = &self.bus_tcp {
+                    bus.send((event.1.clone(), (*addr, *id))).unwrap();
+                }
             }
             SubscriptionId::Udp(addr, id) => {
-                self.bus_udp.send((event.1.clone(), (*addr, *id))).unwrap()
+                if let Some(bus) = &self.bus_udp {
+                    bus.send((event.1.clone(), (*addr, *id))).unwrap()
+                }
             }
             SubscriptionId::Ws(addr, id) => {
-                self.bus_ws.send((event.1.clone(), (*addr, *id))).unwrap();
+                if let Some(bus) = &self.bus_ws {
+                    bus.send((event.1.clone(), (*addr, *id))).unwrap();
+                }
             }
         }
     }
@@ -88,28 +98,33 @@ impl EventBus {
 
 pub(crate) async fn init(
     robot: Arc<crate::Backends>,
-    bus_tcp: transports::tcp::Tx,
-    bus_udp: transports::udp::Tx,
-    bus_ws: transports::ws::Tx,
+    bus_tcp: Option<transports::tcp::Tx>,
+    bus_udp: Option<transports::udp::Tx>,
+    bus_ws: Option<transports::ws::Tx>,
+    custom: crate::custom::Events,
 ) -> anyhow::Result<()> {
     let token = robot.abort_token.clone();
     let event_bus = Arc::new(EventBus::new(robot, bus_tcp, bus_udp, bus_ws));
 
-    #[cfg(all(feature = "roland", feature = "backend"))]
+    #[cfg(all(feature = "roland", any(feature = "backend", feature = "sim")))]
     let h2 = if event_bus.robot.roland.is_some() {
         Some(tokio::spawn(connect_roland(event_bus.clone())))
     } else {
         None
     };
 
-    let h1 = tokio::spawn(connect(event_bus));
+    let h1 = tokio::spawn(connect(event_bus.clone()));
+    let h3 = tokio::spawn(connect_custom(event_bus.clone(), custom));
+    let h4 = tokio::spawn(connect_control(event_bus));
 
     token.cancelled().await;
     log::debug!("abort: event_bus");
 
     h1.abort();
+    h3.abort();
+    h4.abort();
 
-    #[cfg(all(feature = "roland", feature = "backend"))]
+    #[cfg(all(feature = "roland", any(feature = "backend", feature = "sim")))]
     if let Some(handle) = h2 {
         handle.abort();
     }
@@ -119,14 +134,28 @@ pub(crate) async fn init(
 
 /// hook up all the "inputs" (backends) to the event bus
 pub(super) async fn connect(event_bus: Arc<EventBus>) {
+    use tokio::sync::broadcast::error::RecvError;
+
     let mut subscribe = event_bus.robot.sub.subscribe();
-    while let Ok((ty, id, sub)) = subscribe.recv().await {
+    loop {
+        let (ty, id, sub) = match subscribe.recv().await {
+            Ok(v) => v,
+            Err(RecvError::Lagged(n)) => {
+                log::error!("sub channel skipping {n}");
+                event_bus.robot.metrics.lagged("sub", n);
+                continue;
+            }
+            Err(RecvError::Closed) => break,
+        };
         let mut clients = event_bus.clients.write().await;
 
         if let SubStatus::Disconnect = sub {
             for (ty, v) in clients.iter_mut() {
+                let before = v.len();
                 v.retain(|s| !s.same_client(&id));
-                if v.is_empty() {
+                event_bus.robot.metrics.subscriptions(ty, v.len());
+                // only the resources this client was the last one to use
+                if before != 0 && v.is_empty() {
                     cleanup_resource(&event_bus, ty.clone()).await;
                 }
             }
@@ -141,12 +170,13 @@ pub(super) async fn connect(event_bus: Arc<EventBus>) {
             SubStatus::Subscribe => {
                 dbg!(&ty);
                 if ids.is_empty() {
-                    create_resource(&event_bus, ty).await;
+                    create_resource(&event_bus, ty.clone()).await;
                 } else if ids.contains(&id) {
                     log::error!("Attempted double subscription on event {ty:?}");
                     continue;
                 }
                 ids.push(id);
+                event_bus.robot.metrics.subscriptions(&ty, ids.len());
             }
             SubStatus::Unsubscribe => {
                 if ids.is_empty() {
@@ -160,6 +190,7 @@ pub(super) async fn connect(event_bus: Arc<EventBus>) {
                 };
 
                 ids.remove(i);
+                event_bus.robot.metrics.subscriptions(&ty, ids.len());
 
                 if ids.is_empty() {
                     cleanup_resource(&event_bus, ty).await;
@@ -171,15 +202,50 @@ pub(super) async fn connect(event_bus: Arc<EventBus>) {
     log::error!("event_bus_sub dropped");
 }
 
+/// forward the events emitted by downstream crates, see [`crate::custom::Emitter`]
+async fn connect_custom(event_bus: Arc<EventBus>, mut events: crate::custom::Events) {
+    while let Some(ev) = events.recv().await {
+        // custom events are emitted regardless of subscriptions
+        let clients = event_bus.clients.read().await;
+        if let Some(v) = clients.get(&ev.0) {
+            event_bus.send_all(ev, v);
+        }
+    }
+}
+
+/// tell the subscribers who's in control of the robot, see [`crate::control::Control`]
+async fn connect_control(event_bus: Arc<EventBus>) {
+    use tokio::sync::broadcast::error::RecvError;
+
+    let mut changes = event_bus.robot.control.subscribe();
+    loop {
+        let driver = match changes.recv().await {
+            Ok(driver) => driver,
+            Err(RecvError::Lagged(n)) => {
+                log::error!("Control changes lagging by {n}");
+                event_bus.robot.metrics.lagged("control", n);
+                continue;
+            }
+            Err(RecvError::Closed) => break,
+        };
+
+        let clients = event_bus.clients.read().await;
+        let ty = ConcreteType::ControlChanged(event::ControlChanged);
+        if let Some(v) = clients.get(&ty) {
+            event_bus.send_all((ty, ConcreteValue::ControlChanged(driver)), v);
+        }
+    }
+}
+
 #[allow(unused_variables)]
 async fn create_resource(event_bus: &Arc<EventBus>, ty: ConcreteType) {
     match ty {
         #[cfg(feature = "gpio")]
         ConcreteType::GpioPin(p) => {
-            #[cfg(feature = "backend")]
+            #[cfg(any(feature = "backend", feature = "sim"))]
             {
                 struct Sub(Arc<EventBus>);
-                impl roblib::gpio::backend::simple::Subscriber for Sub {
+                impl roblib::gpio::Subscriber for Sub {
                     fn handle(&self, event: roblib::gpio::event::Event) {
                         let msg = match event {
                             roblib::gpio::event::Event::PinChanged(pin, value) => (
@@ -230,6 +296,7 @@ async fn create_resource(event_bus: &Arc<EventBus>, ty: ConcreteType) {
                             Err(e) => match e {
                                 tokio::sync::broadcast::error::RecvError::Lagged(by) => {
                                     log::error!("Camloc events lagging by {by}");
+                                    eb.robot.metrics.lagged("camloc", by);
                                     continue;
                                 }
                                 tokio::sync::broadcast::error::RecvError::Closed => {
@@ -265,6 +332,8 @@ async fn create_resource(event_bus: &Arc<EventBus>, ty: ConcreteType) {
             }
         }
 
+        ConcreteType::ControlChanged(_) | ConcreteType::Custom(_) => (),
+
         ConcreteType::None => unreachable!(),
     }
 }
@@ -278,7 +347,7 @@ async fn cleanup_resource(event_bus: &Arc<EventBus>, ty: ConcreteType) {
         #[cfg(feature = "gpio")]
         ConcreteType::GpioPin(p) =>
         {
-            #[cfg(feature = "backend")]
+            #[cfg(any(feature = "backend", feature = "sim"))]
             if let Some(r) = &event_bus.robot.raw_gpio {
                 if let Err(e) = r.unsubscribe(p.0) {
                     log::error!("Failed to unsubscribe from gpio pin: {e}");
@@ -292,11 +361,13 @@ async fn cleanup_resource(event_bus: &Arc<EventBus>, ty: ConcreteType) {
         | ConcreteType::CamlocPosition(_)
         | ConcreteType::CamlocInfoUpdate(_) => (),
 
+        ConcreteType::ControlChanged(_) | ConcreteType::Custom(_) => (),
+
         ConcreteType::None => unreachable!(),
     }
 }
 
-#[cfg(all(feature = "roland", feature = "backend"))]
+#[cfg(all(feature = "roland", any(feature = "backend", feature = "sim")))]
 async fn connect_roland(event_bus: Arc<EventBus>) -> anyhow::Result<()> {
     use std::time::{Duration, Instant};
 
@@ -312,7 +383,7 @@ async fn connect_roland(event_bus: Arc<EventBus>) -> anyhow::Result<()> {
 
     roland.setup_tracksensor_interrupts()?;
 
-    let mut track_subs = 0;
+    let mut track_subs: Vec<SubscriptionId> = vec![];
 
     struct UltraScheduleData {
         id: SubscriptionId,
@@ -322,19 +393,20 @@ async fn connect_roland(event_bus: Arc<EventBus>) -> anyhow::Result<()> {
     let mut ultra = vec![];
 
     loop {
-        if track_subs + ultra.len() == 0 {
+        if track_subs.len() + ultra.len() == 0 {
             let (ty, id, sub) = match rx.recv().await {
                 Ok(v) => v,
                 Err(RecvError::Closed) => return Err(anyhow::anyhow!("sub channel closed")),
                 Err(RecvError::Lagged(n)) => {
                     error!("sub channel skipping {n}");
+                    event_bus.robot.metrics.lagged("sub", n);
                     continue;
                 }
             };
 
             if let SubStatus::Subscribe = sub {
                 match ty {
-                    ConcreteType::TrackSensor(_) => track_subs += 1,
+                    ConcreteType::TrackSensor(_) => track_subs.push(id),
                     ConcreteType::UltraSensor(event::UltraSensor(interval)) => {
                         ultra.push(UltraScheduleData {
                             id,
@@ -348,10 +420,16 @@ async fn connect_roland(event_bus: Arc<EventBus>) -> anyhow::Result<()> {
         }
 
         while let Ok((ty, id, sub)) = rx.try_recv() {
+            if let Disconnect = sub {
+                track_subs.retain(|s| !s.same_client(&id));
+                ultra.retain(|u| !u.id.same_client(&id));
+                continue;
+            }
+
             match ty {
                 ConcreteType::TrackSensor(_) => match sub {
-                    Subscribe => track_subs += 1,
-                    Unsubscribe => track_subs = track_subs.saturating_sub(1),
+                    Subscribe => track_subs.push(id),
+                    Unsubscribe => track_subs.retain(|s| s != &id),
                     Disconnect => unreachable!(),
                 },
 
@@ -377,19 +455,24 @@ async fn connect_roland(event_bus: Arc<EventBus>) -> anyhow::Result<()> {
         }
 
         const MAX_WAIT: Duration = Duration::from_millis(200);
+        // the simulated sensor answers right away
+        #[cfg(feature = "sim")]
+        const BLAST_DURATION: Duration = Duration::ZERO;
+        #[cfg(not(feature = "sim"))]
+        const BLAST_DURATION: Duration =
+            roblib::roland::backend::constants::ultra_sensor::BLAST_DURATION;
 
         let now = Instant::now();
 
         let next_ultra = ultra.iter_mut().min_by_key(|d| d.next);
 
         let poll_dur = if let Some(next_ultra) = &next_ultra {
-            (next_ultra.next - now)
-                .saturating_sub(roblib::roland::backend::constants::ultra_sensor::BLAST_DURATION)
+            (next_ultra.next - now).saturating_sub(BLAST_DURATION)
         } else {
             MAX_WAIT
         };
 
-        if track_subs > 0 {
+        if !track_subs.is_empty() {
             let poll_fn = {
                 let robot = event_bus.robot.clone();
                 move || {
@@ -0,0 +1,472 @@
+#[macro_use]
+extern crate log;
+
+mod auth;
+mod cleanup;
+mod cmd;
+pub mod config;
+mod control;
+pub mod custom;
+mod event_bus;
+pub mod logger;
+mod metrics;
+pub mod recording;
+mod scheduler;
+mod tls;
+mod transports;
+mod watchdog;
+use anyhow::Result;
+pub use config::Config;
+use futures_util::future::join_all;
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Instant,
+};
+use tokio::sync::{broadcast, mpsc};
+use tokio_util::sync::CancellationToken;
+use transports::{http, tcp, udp};
+
+/// The robot, simulated with the `sim` feature
+#[cfg(all(feature = "roland", feature = "sim"))]
+type RolandBackend = roblib::roland::sim::SimRoland;
+#[cfg(all(feature = "roland", feature = "backend", not(feature = "sim")))]
+type RolandBackend = roblib::roland::backend::RolandBackend;
+
+/// The GPIO pins, simulated with the `sim` feature
+#[cfg(all(feature = "gpio", feature = "sim"))]
+type GpioBackend = roblib::gpio::sim::SimGpio;
+#[cfg(all(feature = "gpio", feature = "backend", not(feature = "sim")))]
+type GpioBackend = roblib::gpio::backend::SimpleGpioBackend;
+
+struct Backends {
+    pub startup_time: Instant,
+
+    abort_token: CancellationToken,
+
+    sub: event_bus::sub::Tx,
+
+    custom: custom::Commands,
+
+    scheduler: scheduler::Scheduler,
+
+    safety: config::Safety,
+
+    auth: auth::Auth,
+
+    control: control::Control,
+
+    cleanup: cleanup::Cleanup,
+
+    watchdog: watchdog::Watchdog,
+
+    recorder: recording::Recorder,
+
+    metrics: metrics::Metrics,
+
+    /// The names of the transports that are running, see [`Config::transports`]
+    transports: Vec<String>,
+
+    #[cfg(all(feature = "gpio", any(feature = "backend", feature = "sim")))]
+    pub raw_gpio: Option<GpioBackend>,
+
+    #[cfg(all(feature = "roland", any(feature = "backend", feature = "sim")))]
+    pub roland: Option<RolandBackend>,
+
+    #[cfg(all(feature = "camloc", feature = "backend"))]
+    pub camloc: Option<Box<dyn roblib::camloc::service::LocationServiceTrait>>,
+}
+
+const FEATURES: &[&str] = &[
+    #[cfg(feature = "roland")]
+    "roland",
+    #[cfg(feature = "gpio")]
+    "gpio",
+    #[cfg(feature = "camloc")]
+    "camloc",
+    #[cfg(feature = "backend")]
+    "backend",
+    #[cfg(feature = "sim")]
+    "sim",
+];
+
+impl Backends {
+    /// whether each backend compiled in was successfully initialized
+    fn backend_status(&self) -> Vec<(&'static str, bool)> {
+        Vec::from([
+            #[cfg(all(feature = "roland", any(feature = "backend", feature = "sim")))]
+            ("roland", self.roland.is_some()),
+            #[cfg(all(feature = "gpio", any(feature = "backend", feature = "sim")))]
+            ("gpio", self.raw_gpio.is_some()),
+            #[cfg(all(feature = "camloc", feature = "backend"))]
+            ("camloc", self.camloc.is_some()),
+        ])
+    }
+
+    /// the names of the backends that were successfully initialized
+    fn initialized(&self) -> Vec<String> {
+        self.backend_status()
+            .into_iter()
+            .filter(|(_, up)| *up)
+            .map(|(name, _)| name.into())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+impl Backends {
+    /// A server without any backends, for the tests
+    fn test(config: &Config) -> Self {
+        Self {
+            startup_time: Instant::now(),
+            abort_token: CancellationToken::new(),
+            sub: broadcast::channel(64).0,
+            custom: Default::default(),
+            scheduler: Default::default(),
+            safety: config.safety.clone(),
+            auth: auth::Auth::new(&config.auth),
+            control: control::Control::new(&config.control),
+            cleanup: cleanup::Cleanup::new(&config.cleanup),
+            watchdog: watchdog::Watchdog::new(&config.safety),
+            recorder: recording::Recorder::new(&config.record).unwrap(),
+            metrics: metrics::Metrics::new(),
+            transports: vec![],
+            #[cfg(all(feature = "gpio", any(feature = "backend", feature = "sim")))]
+            raw_gpio: None,
+            #[cfg(all(feature = "roland", any(feature = "backend", feature = "sim")))]
+            roland: None,
+            #[cfg(all(feature = "camloc", feature = "backend"))]
+            camloc: None,
+        }
+    }
+}
+
+/// Runs the server until it's aborted, with `custom` handling any commands and events not built into roblib.
+/// Configured from the default config file and the environment, see [`Config::load`].
+pub async fn run(custom: custom::Registry) -> Result<()> {
+    run_with_config(Config::load(None)?, custom).await
+}
+
+/// Same as [`run`], with an already loaded configuration
+pub async fn run_with_config(config: Config, custom: custom::Registry) -> Result<()> {
+    serve(config, custom, None).await
+}
+
+/// Runs the commands in a recording, see [`config::Record`], then stops the server.
+/// Clients can connect in the meantime to watch, it isn't recorded again.
+pub async fn replay(mut config: Config, path: &Path, custom: custom::Registry) -> Result<()> {
+    config.record.path.clear();
+    serve(config, custom, Some(path.into())).await
+}
+
+async fn serve(config: Config, custom: custom::Registry, replay: Option<PathBuf>) -> Result<()> {
+    let (custom_commands, custom_events) = custom.split();
+
+    info!("Server starting up");
+    info!("Compiled with features: {FEATURES:?}");
+    for key in &config.unknown {
+        warn!("Unknown config key ignored: {key}");
+    }
+
+    if config.auth.secret.is_empty() {
+        info!("Authentication disabled, anyone can control the robot");
+    } else {
+        info!("Authentication required");
+    }
+
+    if !config.record.path.is_empty() {
+        info!("Recording to {}", config.record.path);
+    }
+
+    let transports = config.transports();
+    info!("Transports enabled: {transports:?}");
+    if transports.is_empty() {
+        warn!("No transports enabled, clients won't be able to connect");
+    }
+
+    // the event bus only sends events to the transports that are running
+    let (tcp_tx, tcp_rx) = config.tcp.enabled.then(|| broadcast::channel(1024)).unzip();
+    let (udp_tx, udp_rx) = config.udp.enabled.then(mpsc::unbounded_channel).unzip();
+    let ws = config.web.enabled && config.web.ws;
+    let (ws_tx, ws_rx) = ws.then(|| broadcast::channel(1024)).unzip();
+
+    #[cfg(feature = "camloc")]
+    let camloc = if config.camloc.enabled {
+        let serv = roblib::camloc::service::Builder::new()
+            .with_address(config.camloc.address)
+            .with_min_camera_angle_diff(config.camloc.min_camera_angle_diff.to_radians())
+            .with_data_validity(std::time::Duration::from_millis(
+                config.camloc.data_validity_ms,
+            ))
+            .start()
+            .await;
+
+        match serv {
+            Ok(s) => {
+                info!("Camloc operational");
+                Some(Box::new(s) as Box<dyn roblib::camloc::service::LocationServiceTrait>)
+            }
+
+            Err(err) => {
+                info!("Failed to initialize camloc: {err}");
+                None
+            }
+        }
+    } else {
+        info!("Camloc disabled");
+        None
+    };
+
+    #[cfg(all(feature = "roland", feature = "sim"))]
+    let roland = {
+        info!("Roland simulated");
+        Some(RolandBackend::new(config.sim.arena.clone()))
+    };
+
+    #[cfg(all(feature = "roland", feature = "backend", not(feature = "sim")))]
+    let roland = {
+        match RolandBackend::try_init_with(&config.roland) {
+            Ok(r) => {
+                info!("Roland operational");
+                Some(r)
+            }
+
+            Err(err) => {
+                info!("Failed to initialize roland: {err}");
+                None
+            }
+        }
+    };
+
+    #[cfg(all(feature = "gpio", feature = "sim"))]
+    let raw_gpio = {
+        info!("GPIO simulated");
+        Some(GpioBackend::new(
+            config.sim.wires.iter().map(|&[o, i]| (o, i)).collect(),
+        ))
+    };
+
+    #[cfg(all(feature = "gpio", feature = "backend", not(feature = "sim")))]
+    let raw_gpio = {
+        match GpioBackend::new() {
+            Ok(r) => {
+                info!("GPIO operational");
+                Some(r)
+            }
+
+            Err(err) => {
+                info!("Failed to initialize GPIO: {err}");
+                None
+            }
+        }
+    };
+
+    let robot = Arc::new(Backends {
+        startup_time: Instant::now(),
+
+        abort_token: CancellationToken::new(),
+
+        sub: broadcast::channel(64).0,
+
+        custom: custom_commands,
+
+        scheduler: Default::default(),
+
+        safety: config.safety.clone(),
+
+        auth: auth::Auth::new(&config.auth),
+
+        control: control::Control::new(&config.control),
+
+        cleanup: cleanup::Cleanup::new(&config.cleanup),
+
+        watchdog: watchdog::Watchdog::new(&config.safety),
+
+        recorder: recording::Recorder::new(&config.record)?,
+
+        metrics: metrics::Metrics::new(),
+
+        transports,
+
+        #[cfg(all(feature = "roland", any(feature = "backend", feature = "sim")))]
+        roland,
+
+        #[cfg(all(feature = "gpio", any(feature = "backend", feature = "sim")))]
+        raw_gpio,
+
+        #[cfg(all(feature = "camloc", feature = "backend"))]
+        camloc,
+    });
+    robot.metrics.backends(&robot.backend_status());
+
+    let tls_tcp = config.tcp.enabled && config.tls.tcp;
+    let tls_web = config.web.enabled && config.tls.web;
+    let tls = if tls_tcp || tls_web {
+        Some(tls::server_config(&config.tls)?)
+    } else {
+        None
+    };
+
+    let Config {
+        max_frame,
+        tcp,
+        udp,
+        web,
+        ..
+    } = config;
+
+    let tcp_handle = match tcp_rx {
+        Some(rx) => {
+            info!("TCP starting on {}:{}", tcp.host, tcp.port);
+            let tls = tls
+                .clone()
+                .filter(|_| tls_tcp)
+                .map(tokio_rustls::TlsAcceptor::from);
+            let addr = (tcp.host, tcp.port);
+            Some(tcp::start(addr, robot.clone(), rx, max_frame, tls).await?)
+        }
+        None => None,
+    };
+
+    let udp_handles = match udp_rx {
+        Some(rx) => {
+            info!("UDP starting on {}:{}", udp.host, udp.port);
+            Some(udp::start((udp.host, udp.port), robot.clone(), rx, max_frame).await?)
+        }
+        None => None,
+    };
+
+    let http_handle = if web.enabled {
+        info!("Webserver starting on {}:{}", web.host, web.port);
+        let addr = (web.host, web.port);
+        let tls = tls.filter(|_| tls_web);
+        Some(http::start(addr, robot.clone(), web.http, web.metrics, ws_rx, tls).await)
+    } else {
+        None
+    };
+
+    let ebus_handle = tokio::spawn(event_bus::init(
+        robot.clone(),
+        tcp_tx,
+        udp_tx,
+        ws_tx,
+        custom_events,
+    ));
+
+    let control_handle = {
+        let robot = robot.clone();
+        tokio::spawn(async move {
+            robot.control.expire(&robot.abort_token).await;
+            Ok(())
+        })
+    };
+
+    let watchdog_handle = {
+        let robot = robot.clone();
+        tokio::spawn(async move {
+            robot.watchdog.run(&robot).await;
+            Ok(())
+        })
+    };
+
+    let recorder_handle = {
+        let robot = robot.clone();
+        tokio::spawn(async move {
+            robot.recorder.run(&robot.abort_token).await;
+            Ok(())
+        })
+    };
+
+    let replay_handle = replay.map(|path| {
+        let robot = robot.clone();
+        tokio::spawn(async move {
+            match recording::replay(&robot, &path).await {
+                Ok(changed) if !changed.is_empty() => {
+                    log::warn!(
+                        "{} return values changed since the recording",
+                        changed.len()
+                    )
+                }
+                Ok(_) => (),
+                Err(e) => log::error!("Replay failed: {e:#}"),
+            }
+            robot.abort_token.cancel();
+            Ok(())
+        })
+    });
+
+    let mut sighandler = SigHandler::new();
+    tokio::select! {
+        _ = robot.abort_token.cancelled() => {
+            log::error!("Abort requested internally, cleaning up...");
+        },
+        s = sighandler.wait() => {
+            log::error!("{s} received, cleaning up...");
+            robot.abort_token.cancel();
+        }
+    };
+
+    log::debug!("abort: main");
+
+    let force_stop = tokio::spawn(async move {
+        sighandler.wait().await;
+        log::warn!("Press ^C again to force exit (THE ROBOT WILL ESCAPE)");
+        sighandler.wait().await;
+        log::error!("Bye! (Force shutdown)");
+        std::process::exit(1);
+    });
+
+    if let Some((udp_handle, udp_event_handle)) = udp_handles {
+        udp_handle.abort();
+        udp_event_handle.abort();
+    }
+
+    let mut futures = vec![
+        ebus_handle,
+        control_handle,
+        watchdog_handle,
+        recorder_handle,
+    ];
+    futures.extend(http_handle);
+    futures.extend(replay_handle);
+    if let Some(tcp_handle) = tcp_handle {
+        if let Ok(mut tcp_handles) = tcp_handle.await {
+            futures.append(&mut tcp_handles);
+        }
+    }
+    log::debug!("Waiting on {} tasks", futures.len());
+    join_all(futures).await;
+
+    force_stop.abort();
+    let _ = force_stop.await;
+    Ok(())
+}
+
+struct SigHandler {
+    #[cfg(unix)]
+    sigterm: tokio::signal::unix::Signal,
+}
+impl SigHandler {
+    pub fn new() -> Self {
+        Self {
+            #[cfg(unix)]
+            sigterm: tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+                .unwrap(),
+        }
+    }
+    pub async fn wait(&mut self) -> &str {
+        #[cfg(unix)]
+        tokio::select! {
+            _ = self.sigterm.recv() => "SIGTERM",
+            r = tokio::signal::ctrl_c() => { r.expect("failed to listen to ctrl-c"); "SIGINT" },
+        }
+
+        #[cfg(not(unix))]
+        {
+            tokio::signal::ctrl_c()
+                .await
+                .expect("failed to listen to ctrl-c");
+            "SIGINT"
+        }
+    }
+}
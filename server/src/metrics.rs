@@ -0,0 +1,281 @@
+use crate::control;
+use prometheus_client::{
+    encoding::{text, EncodeLabelSet},
+    metrics::{
+        counter::Counter,
+        family::Family,
+        gauge::Gauge,
+        histogram::{exponential_buckets, Histogram},
+    },
+    registry::Registry,
+};
+use roblib::{cmd::Concrete, event::ConcreteType};
+use std::time::Duration;
+
+/// The content type of [`Metrics::encode`]
+pub(crate) const CONTENT_TYPE: &str = "application/openmetrics-text; version=1.0.0; charset=utf-8";
+
+#[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
+struct CommandLabels {
+    command: &'static str,
+    transport: &'static str,
+}
+
+#[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
+struct LatencyLabels {
+    command: &'static str,
+}
+
+#[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
+struct TransportLabels {
+    transport: &'static str,
+}
+
+#[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
+struct EventLabels {
+    event: &'static str,
+}
+
+#[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
+struct ChannelLabels {
+    channel: &'static str,
+}
+
+#[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
+struct BackendLabels {
+    backend: &'static str,
+}
+
+/// The counters served at `/metrics`, in the Prometheus format
+pub(crate) struct Metrics {
+    registry: Registry,
+    commands: Family<CommandLabels, Counter>,
+    latency: Family<LatencyLabels, Histogram, fn() -> Histogram>,
+    errors: Family<CommandLabels, Counter>,
+    clients: Family<TransportLabels, Gauge>,
+    subscriptions: Family<EventLabels, Gauge>,
+    lagged: Family<ChannelLabels, Counter>,
+    backends: Family<BackendLabels, Gauge>,
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        let mut registry = Registry::with_prefix("roblib");
+
+        let commands = Family::default();
+        registry.register(
+            "commands",
+            "Commands run, by command and the transport they came from",
+            Family::clone(&commands),
+        );
+
+        // from 100µs to about 3s
+        let latency: Family<_, _, fn() -> Histogram> =
+            Family::new_with_constructor(|| Histogram::new(exponential_buckets(0.0001, 2., 15)));
+        registry.register(
+            "command_duration_seconds",
+            "How long commands took to run",
+            Family::clone(&latency),
+        );
+
+        let errors = Family::default();
+        registry.register(
+            "command_errors",
+            "Commands that failed, by command and the transport they came from",
+            Family::clone(&errors),
+        );
+
+        let clients = Family::default();
+        registry.register(
+            "clients",
+            "Clients connected, by transport",
+            Family::clone(&clients),
+        );
+
+        let subscriptions = Family::default();
+        registry.register(
+            "subscriptions",
+            "Active event subscriptions, by event",
+            Family::clone(&subscriptions),
+        );
+
+        let lagged = Family::default();
+        registry.register(
+            "lagged_messages",
+            "Messages skipped by receivers that fell behind on a channel",
+            Family::clone(&lagged),
+        );
+
+        let backends = Family::default();
+        registry.register(
+            "backend_up",
+            "Whether each backend compiled in was initialized",
+            Family::clone(&backends),
+        );
+
+        Self {
+            registry,
+            commands,
+            latency,
+            errors,
+            clients,
+            subscriptions,
+            lagged,
+            backends,
+        }
+    }
+
+    /// Called for every command from a client, once it's run
+    pub fn command(&self, prefix: char, elapsed: Duration, ok: bool) {
+        let command = Concrete::name_of(prefix).unwrap_or("unknown");
+        let labels = CommandLabels {
+            command,
+            transport: transport(),
+        };
+
+        self.commands.get_or_create(&labels).inc();
+        if !ok {
+            self.errors.get_or_create(&labels).inc();
+        }
+        self.latency
+            .get_or_create(&LatencyLabels { command })
+            .observe(elapsed.as_secs_f64());
+    }
+
+    /// A client connected with `transport`, see [`Connected`] for the ones that stay connected
+    pub fn connected(&self, transport: &'static str) {
+        self.clients
+            .get_or_create(&TransportLabels { transport })
+            .inc();
+    }
+
+    pub fn disconnected(&self, transport: &'static str) {
+        self.clients
+            .get_or_create(&TransportLabels { transport })
+            .dec();
+    }
+
+    /// `ty` has `n` subscribers now
+    pub fn subscriptions(&self, ty: &ConcreteType, n: usize) {
+        if let ConcreteType::None = ty {
+            return;
+        }
+        self.subscriptions
+            .get_or_create(&EventLabels {
+                event: ty.get_name(),
+            })
+            .set(n as i64);
+    }
+
+    /// A receiver of `channel` skipped `n` messages, see [`tokio::sync::broadcast::error::RecvError::Lagged`]
+    pub fn lagged(&self, channel: &'static str, n: u64) {
+        self.lagged
+            .get_or_create(&ChannelLabels { channel })
+            .inc_by(n);
+    }
+
+    pub fn backends(&self, status: &[(&'static str, bool)]) {
+        for &(backend, up) in status {
+            self.backends
+                .get_or_create(&BackendLabels { backend })
+                .set(up as i64);
+        }
+    }
+
+    /// Everything in the OpenMetrics text format
+    pub fn encode(&self) -> Result<String, std::fmt::Error> {
+        let mut buf = String::new();
+        text::encode(&mut buf, &self.registry)?;
+        Ok(buf)
+    }
+}
+
+/// Counts a client as connected until it's dropped
+pub(crate) struct Connected<'a> {
+    metrics: &'a Metrics,
+    transport: &'static str,
+}
+
+impl<'a> Connected<'a> {
+    pub fn new(metrics: &'a Metrics, transport: &'static str) -> Self {
+        metrics.connected(transport);
+        Self { metrics, transport }
+    }
+}
+
+impl Drop for Connected<'_> {
+    fn drop(&mut self) {
+        self.metrics.disconnected(self.transport);
+    }
+}
+
+/// The transport of the client running the current command, from the name it was given, see [`control::CLIENT`]
+fn transport() -> &'static str {
+    let Ok(client) = control::client() else {
+        return "none";
+    };
+    match client.split(' ').next() {
+        Some("tcp") => "tcp",
+        Some("udp") => "udp",
+        Some("ws") => "ws",
+        Some("http") => "http",
+        _ => "unknown",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{cmd::execute, Backends, Config};
+    use roblib::cmd::{Custom, GetUptime};
+    use std::sync::Arc;
+
+    #[tokio::test]
+    async fn encoded() {
+        let robot = Arc::new(Backends::test(&Config::default()));
+        let client = "tcp 127.0.0.1:1".to_string();
+
+        let connected = super::Connected::new(&robot.metrics, "tcp");
+        let ok = execute(GetUptime.into(), robot.clone());
+        assert!(as_client(&client, ok).await.is_ok());
+        let err = execute(Custom("missing".into(), vec![]).into(), robot.clone());
+        assert!(as_client(&client, err).await.is_err());
+        robot.metrics.lagged("sub", 3);
+
+        let text = robot.metrics.encode().unwrap();
+        let has = |line: &str| text.lines().any(|l| l == line);
+        assert!(has(
+            r#"roblib_commands_total{command="GetUptime",transport="tcp"} 1"#
+        ));
+        assert!(has(
+            r#"roblib_commands_total{command="Custom",transport="tcp"} 1"#
+        ));
+        assert!(has(
+            r#"roblib_command_errors_total{command="Custom",transport="tcp"} 1"#
+        ));
+        assert!(!text.contains(r#"roblib_command_errors_total{command="GetUptime""#));
+        assert!(has(
+            r#"roblib_command_duration_seconds_count{command="GetUptime"} 1"#
+        ));
+        assert!(has(r#"roblib_clients{transport="tcp"} 1"#));
+        assert!(has(r#"roblib_lagged_messages_total{channel="sub"} 3"#));
+        assert!(text.ends_with("# EOF\n"));
+
+        drop(connected);
+        let text = robot.metrics.encode().unwrap();
+        assert!(text
+            .lines()
+            .any(|l| l == r#"roblib_clients{transport="tcp"} 0"#));
+    }
+
+    #[test]
+    fn outside_a_client() {
+        let metrics = super::Metrics::new();
+        metrics.command('?', Default::default(), true);
+        let text = metrics.encode().unwrap();
+        assert!(text.contains(r#"roblib_commands_total{command="unknown",transport="none"} 1"#));
+    }
+
+    async fn as_client<F: std::future::Future>(client: &str, f: F) -> F::Output {
+        crate::control::CLIENT.scope(client.into(), f).await
+    }
+}
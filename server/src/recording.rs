@@ -0,0 +1,525 @@
+//! Recording a session to a file and replaying it, see [`config::Record`].
+//!
+//! A recording is a [`Header`] followed by [`Entry`]s, all in bincode.
+//! The entries are written as they happen, so a recording cut off by a crash is still readable up to that point.
+
+use crate::{cmd::Return, config, control, scheduler, transports::SubscriptionId, Backends};
+use anyhow::{Context, Result};
+use bincode::Options;
+use roblib::{
+    cmd::{Concrete, PROTOCOL_VERSION},
+    event::{ConcreteType, ConcreteValue},
+    text_format,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{BufWriter, Write},
+    path::Path,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex,
+    },
+    time::{Duration, Instant, SystemTime},
+};
+use tokio::sync::mpsc;
+use tokio_util::sync::CancellationToken;
+
+#[derive(Serialize, Deserialize)]
+struct Header {
+    version: u32,
+    features: Vec<String>,
+    started: SystemTime,
+}
+
+/// `at` is the time since the recording started.
+/// Return values and event items are recorded in the text format, their types aren't known when reading them back.
+#[derive(Serialize, Deserialize)]
+enum Entry<C> {
+    /// A command from a client, numbered in the order they were received.
+    /// `scheduled` is the id of the scheduled command it was run as, when it was scheduled earlier
+    Command {
+        at: Duration,
+        id: u64,
+        client: Option<String>,
+        scheduled: Option<u32>,
+        cmd: C,
+    },
+    /// What the command with the same `id` returned, `None` if it doesn't return anything, or why it failed
+    Return {
+        at: Duration,
+        id: u64,
+        ret: Result<Option<String>, String>,
+    },
+    /// An event sent to a client
+    Event {
+        at: Duration,
+        client: String,
+        ty: ConcreteType,
+        value: String,
+    },
+}
+
+/// Records what the clients do, if it's enabled
+pub(crate) struct Recorder {
+    tx: Option<mpsc::UnboundedSender<Vec<u8>>>,
+    /// Taken by [`Recorder::run`]
+    writer: Mutex<Option<Writer>>,
+    events: bool,
+    ids: AtomicU64,
+    start: Instant,
+}
+
+struct Writer {
+    file: BufWriter<File>,
+    rx: mpsc::UnboundedReceiver<Vec<u8>>,
+}
+
+impl Recorder {
+    /// Creates the recording, overwriting the file if it exists
+    pub fn new(config: &config::Record) -> Result<Self> {
+        let mut recorder = Self {
+            tx: None,
+            writer: Mutex::new(None),
+            events: config.events,
+            ids: AtomicU64::new(0),
+            start: Instant::now(),
+        };
+        if config.path.is_empty() {
+            return Ok(recorder);
+        }
+
+        let file = File::create(&config.path).with_context(|| config.path.clone())?;
+        let mut file = BufWriter::new(file);
+        let header = Header {
+            version: PROTOCOL_VERSION,
+            features: crate::FEATURES.iter().map(|f| f.to_string()).collect(),
+            started: SystemTime::now(),
+        };
+        bincode::options().serialize_into(&mut file, &header)?;
+
+        let (tx, rx) = mpsc::unbounded_channel();
+        recorder.tx = Some(tx);
+        recorder.writer = Mutex::new(Some(Writer { file, rx }));
+        Ok(recorder)
+    }
+
+    /// Writes the recording until the server is aborted
+    pub async fn run(&self, abort: &CancellationToken) {
+        let Some(Writer { mut file, mut rx }) = self.writer.lock().unwrap().take() else {
+            return;
+        };
+
+        loop {
+            let entry = tokio::select! {
+                _ = abort.cancelled() => break,
+                Some(entry) = rx.recv() => entry,
+            };
+
+            let mut res = file.write_all(&entry);
+            // only flushed once it's caught up, not after every entry
+            while let (Ok(()), Ok(entry)) = (&res, rx.try_recv()) {
+                res = file.write_all(&entry);
+            }
+            if let Err(e) = res.and_then(|_| file.flush()) {
+                log::error!("Failed to write the recording, stopping it: {e}");
+                return;
+            }
+        }
+
+        while let Ok(entry) = rx.try_recv() {
+            if file.write_all(&entry).is_err() {
+                break;
+            }
+        }
+        if let Err(e) = file.flush() {
+            log::error!("Failed to write the recording: {e}");
+        }
+    }
+
+    fn record(&self, entry: Entry<&Concrete>) {
+        let Some(tx) = &self.tx else {
+            return;
+        };
+        match bincode::options().serialize(&entry) {
+            // only fails once the writer stopped
+            Ok(bytes) => {
+                let _ = tx.send(bytes);
+            }
+            Err(e) => log::error!("Failed to record: {e}"),
+        }
+    }
+
+    /// Records a command from a client, returning the id to record its return value with
+    pub fn command(&self, cmd: &Concrete) -> Option<u64> {
+        self.tx.as_ref()?;
+        let id = self.ids.fetch_add(1, Ordering::Relaxed);
+        self.record(Entry::Command {
+            at: self.start.elapsed(),
+            id,
+            client: control::client().ok(),
+            scheduled: scheduler::scheduled(),
+            cmd,
+        });
+        Some(id)
+    }
+
+    pub fn returned(&self, id: Option<u64>, ret: &Result<Return>) {
+        let Some(id) = id else {
+            return;
+        };
+        let ret = match ret {
+            Ok(Some(r)) => text_format::ser::to_string(r)
+                .map(Some)
+                .map_err(|e| format!("unrecordable return value: {e}")),
+            Ok(None) => Ok(None),
+            Err(e) => Err(e.to_string()),
+        };
+        self.record(Entry::Return {
+            at: self.start.elapsed(),
+            id,
+            ret,
+        });
+    }
+
+    pub fn event(&self, client: &SubscriptionId, event: &(ConcreteType, ConcreteValue)) {
+        if self.tx.is_none() || !self.events {
+            return;
+        }
+        let value = match text_format::ser::to_string(&event.1) {
+            Ok(v) => v,
+            Err(e) => return log::error!("Failed to record event: {e}"),
+        };
+        self.record(Entry::Event {
+            at: self.start.elapsed(),
+            client: client.client(),
+            ty: event.0.clone(),
+            value,
+        });
+    }
+}
+
+/// Reads a whole recording, up to where it was cut off if it was
+fn read(path: &Path) -> Result<(Header, Vec<Entry<Concrete>>)> {
+    let bytes = std::fs::read(path).with_context(|| path.display().to_string())?;
+    let mut rest = &bytes[..];
+
+    let header: Header = bincode::options()
+        .deserialize_from(&mut rest)
+        .context("not a recording")?;
+    if header.version != PROTOCOL_VERSION {
+        log::warn!(
+            "Recorded with protocol version {}, this is {PROTOCOL_VERSION}",
+            header.version
+        );
+    }
+
+    let mut entries = vec![];
+    while !rest.is_empty() {
+        match bincode::options().deserialize_from(&mut rest) {
+            Ok(entry) => entries.push(entry),
+            Err(e) => {
+                log::warn!("Recording cut off after {} entries: {e}", entries.len());
+                break;
+            }
+        }
+    }
+
+    Ok((header, entries))
+}
+
+/// Prints a recording in the text format, one entry per line:
+/// `<seconds> #<id> <client> > <command>` for commands, with `@<scheduled id>` before the `>` if it was scheduled,
+/// `<seconds> #<id> < <return value>` or `<seconds> #<id> ! <error>` for what they returned,
+/// and `<seconds> <client> ~ <event> : <item>` for events.
+pub fn dump(path: &Path, out: &mut impl Write) -> Result<()> {
+    let (header, entries) = read(path)?;
+
+    let started = header
+        .started
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default();
+    writeln!(
+        out,
+        "# protocol version {}, features {:?}, started at {:.3} (unix time)",
+        header.version,
+        header.features,
+        started.as_secs_f64()
+    )?;
+
+    for entry in entries {
+        match entry {
+            Entry::Command {
+                at,
+                id,
+                client,
+                scheduled,
+                cmd,
+            } => {
+                let client = client.as_deref().unwrap_or("-");
+                let scheduled = scheduled.map(|s| format!(" @{s}")).unwrap_or_default();
+                let cmd = text_format::ser::to_string(&cmd)?;
+                writeln!(
+                    out,
+                    "{:.3} #{id} {client}{scheduled} > {cmd}",
+                    at.as_secs_f64()
+                )?
+            }
+            Entry::Return { at, id, ret } => match ret {
+                Ok(Some(ret)) => writeln!(out, "{:.3} #{id} < {ret}", at.as_secs_f64())?,
+                Ok(None) => writeln!(out, "{:.3} #{id} <", at.as_secs_f64())?,
+                Err(e) => writeln!(out, "{:.3} #{id} ! {e}", at.as_secs_f64())?,
+            },
+            Entry::Event {
+                at,
+                client,
+                ty,
+                value,
+            } => {
+                let ty = text_format::ser::to_string(&ty)?;
+                writeln!(out, "{:.3} {client} ~ {ty} : {value}", at.as_secs_f64())?
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Runs the commands in a recording again, as the clients that sent them, with the same timing.
+/// The scheduled ones are left out, replaying the [`Schedule`](roblib::cmd::Schedule) runs them again.
+/// The return values that changed since are logged, and their ids returned.
+pub(crate) async fn replay(robot: &std::sync::Arc<Backends>, path: &Path) -> Result<Vec<u64>> {
+    let (_, entries) = read(path)?;
+
+    let mut returns = HashMap::new();
+    let mut commands = vec![];
+    for entry in entries {
+        match entry {
+            Entry::Command {
+                at,
+                id,
+                client,
+                scheduled: None,
+                cmd,
+            } => commands.push((at, id, client, cmd)),
+            Entry::Command { .. } => (),
+            Entry::Return { id, ret, .. } => {
+                returns.insert(id, ret);
+            }
+            Entry::Event { .. } => (),
+        }
+    }
+    info!(
+        "Replaying {} commands from {}",
+        commands.len(),
+        path.display()
+    );
+
+    let start = Instant::now();
+    let mut changed = vec![];
+    for (at, id, client, cmd) in commands {
+        tokio::select! {
+            _ = robot.abort_token.cancelled() => return Ok(changed),
+            _ = tokio::time::sleep_until((start + at).into()) => (),
+        }
+
+        let prefix = cmd.get_prefix();
+        debug!(
+            "Replaying #{id} ({prefix}) from {}",
+            client.as_deref().unwrap_or("-")
+        );
+        let exec = crate::cmd::dispatch(cmd, robot.clone());
+        let res = match client {
+            Some(client) => control::CLIENT.scope(client, exec).await,
+            None => exec.await,
+        };
+
+        let ret = match res {
+            Ok(Some(r)) => Ok(text_format::ser::to_string(&r).ok()),
+            Ok(None) => Ok(None),
+            Err(e) => Err(e.to_string()),
+        };
+        match returns.get(&id) {
+            Some(recorded) if *recorded != ret => {
+                info!("#{id} ({prefix}) returned {ret:?}, recorded {recorded:?}");
+                changed.push(id);
+            }
+            _ => (),
+        }
+    }
+
+    info!("Replay finished");
+    Ok(changed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use roblib::{
+        cmd::{Custom, Nop},
+        event,
+    };
+    use std::path::PathBuf;
+
+    /// A file in the temp dir, removed when dropped
+    struct TempFile(PathBuf);
+
+    impl TempFile {
+        fn new(name: &str) -> Self {
+            let name = format!("roblib-{}-{name}.rec", std::process::id());
+            Self(std::env::temp_dir().join(name))
+        }
+
+        fn config(&self) -> config::Record {
+            config::Record {
+                path: self.0.display().to_string(),
+                events: true,
+            }
+        }
+    }
+
+    impl Drop for TempFile {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_file(&self.0);
+        }
+    }
+
+    /// Records a command that returned 5, a failed one and an event, then writes them all
+    async fn record(file: &TempFile) {
+        let recorder = Recorder::new(&file.config()).unwrap();
+
+        let id =
+            control::CLIENT.sync_scope("tcp 127.0.0.1:1".into(), || recorder.command(&Nop.into()));
+        recorder.returned(id, &Ok(Some(Box::new(5u32))));
+
+        let id = recorder.command(&Custom("x".into(), vec![]).into());
+        recorder.returned(id, &Err(anyhow::anyhow!("no such command")));
+
+        let sub = SubscriptionId::Tcp(([127, 0, 0, 1], 1).into(), 7);
+        let ty = ConcreteType::ControlChanged(event::ControlChanged);
+        recorder.event(&sub, &(ty, ConcreteValue::ControlChanged(None)));
+
+        // everything's written once it's aborted
+        let abort = CancellationToken::new();
+        abort.cancel();
+        recorder.run(&abort).await;
+    }
+
+    #[tokio::test]
+    async fn roundtrip() {
+        let file = TempFile::new("roundtrip");
+        record(&file).await;
+
+        let (header, entries) = read(&file.0).unwrap();
+        assert_eq!(header.version, PROTOCOL_VERSION);
+        assert_eq!(entries.len(), 5);
+        assert!(matches!(
+            &entries[0],
+            Entry::Command { id: 0, client: Some(c), cmd: Concrete::Nop(_), .. } if c == "tcp 127.0.0.1:1"
+        ));
+        assert!(matches!(
+            &entries[1],
+            Entry::Return { id: 0, ret: Ok(Some(r)), .. } if r == "5"
+        ));
+        assert!(matches!(
+            &entries[2],
+            Entry::Command {
+                id: 1,
+                client: None,
+                cmd: Concrete::Custom(_),
+                ..
+            }
+        ));
+        assert!(matches!(
+            &entries[3],
+            Entry::Return {
+                id: 1,
+                ret: Err(_),
+                ..
+            }
+        ));
+        assert!(matches!(
+            &entries[4],
+            Entry::Event { client, ty: ConcreteType::ControlChanged(_), .. } if client == "tcp 127.0.0.1:1"
+        ));
+    }
+
+    #[tokio::test]
+    async fn cut_off() {
+        let file = TempFile::new("cut_off");
+        record(&file).await;
+
+        let bytes = std::fs::read(&file.0).unwrap();
+        std::fs::write(&file.0, &bytes[..bytes.len() - 2]).unwrap();
+        let (_, entries) = read(&file.0).unwrap();
+        assert_eq!(entries.len(), 4);
+
+        // not even the header
+        std::fs::write(&file.0, &bytes[..2]).unwrap();
+        assert!(read(&file.0).is_err());
+    }
+
+    #[tokio::test]
+    async fn dump_every_entry() {
+        let file = TempFile::new("dump");
+        record(&file).await;
+
+        let mut out = vec![];
+        dump(&file.0, &mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        let lines: Vec<_> = out.lines().map(|l| l.split_once(' ').unwrap().1).collect();
+
+        assert_eq!(lines.len(), 6);
+        assert!(lines[0].starts_with(&format!("protocol version {PROTOCOL_VERSION}")));
+        assert_eq!(lines[1], "#0 tcp 127.0.0.1:1 > 0");
+        assert_eq!(lines[2], "#0 < 5");
+        assert_eq!(lines[3], "#1 - > $ 0 x 0");
+        assert_eq!(lines[4], "#1 ! no such command");
+        assert!(lines[5].starts_with("tcp 127.0.0.1:1 ~ "), "{}", lines[5]);
+        assert!(lines[5].ends_with(" : 0"), "{}", lines[5]);
+    }
+
+    #[tokio::test]
+    async fn replay_reports_changes() {
+        let file = TempFile::new("replay");
+        // `Nop` doesn't return 5 anymore, the unknown custom command still fails the same way
+        let recorder = Recorder::new(&file.config()).unwrap();
+        let id = recorder.command(&Nop.into());
+        recorder.returned(id, &Ok(Some(Box::new(5u32))));
+        let id = recorder.command(&Custom("x".into(), vec![]).into());
+        recorder.returned(id, &Err(anyhow::anyhow!("Unknown custom command: x")));
+        let id = recorder.command(&Nop.into());
+        recorder.returned(id, &Ok(None));
+        let abort = CancellationToken::new();
+        abort.cancel();
+        recorder.run(&abort).await;
+
+        let robot = std::sync::Arc::new(Backends::test(&Default::default()));
+        let changed = replay(&robot, &file.0).await.unwrap();
+        assert_eq!(changed, vec![0]);
+    }
+
+    #[tokio::test]
+    async fn scheduled_left_out() {
+        let file = TempFile::new("scheduled");
+        let recorder = Recorder::new(&file.config()).unwrap();
+        let id = scheduler::SCHEDULED.sync_scope(4, || recorder.command(&Nop.into()));
+        recorder.returned(id, &Ok(Some(Box::new(5u32))));
+        let abort = CancellationToken::new();
+        abort.cancel();
+        recorder.run(&abort).await;
+
+        let mut out = vec![];
+        dump(&file.0, &mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert_eq!(
+            out.lines().nth(1).unwrap().split_once(' ').unwrap().1,
+            "#0 - @4 > 0"
+        );
+
+        // run by replaying the schedule, not on its own
+        let robot = std::sync::Arc::new(Backends::test(&Default::default()));
+        assert!(replay(&robot, &file.0).await.unwrap().is_empty());
+    }
+}
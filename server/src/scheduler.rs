@@ -0,0 +1,74 @@
+use roblib::cmd::Scheduled;
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        Mutex,
+    },
+    time::Duration,
+};
+use tokio_util::sync::CancellationToken;
+
+tokio::task_local! {
+    /// The id of the scheduled command being run, set when it fires
+    pub(crate) static SCHEDULED: u32;
+}
+
+/// The id of the scheduled command being run, if it is one, see [`SCHEDULED`]
+pub(crate) fn scheduled() -> Option<u32> {
+    SCHEDULED.try_with(|&id| id).ok()
+}
+
+struct Pending {
+    at: Duration,
+    prefix: char,
+    cancel: CancellationToken,
+}
+
+/// Bookkeeping for the commands waiting to be run, the waiting itself is done by the tasks in `cmd`
+#[derive(Default)]
+pub(crate) struct Scheduler {
+    next_id: AtomicU32,
+    pending: Mutex<HashMap<u32, Pending>>,
+}
+
+impl Scheduler {
+    pub fn insert(&self, at: Duration, prefix: char, cancel: CancellationToken) -> u32 {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let pending = Pending { at, prefix, cancel };
+        self.pending.lock().unwrap().insert(id, pending);
+        id
+    }
+
+    /// Takes `id` out of the pending commands, whoever gets it first decides whether it runs
+    pub fn take(&self, id: u32) -> bool {
+        self.pending.lock().unwrap().remove(&id).is_some()
+    }
+
+    pub fn cancel(&self, id: u32) -> bool {
+        match self.pending.lock().unwrap().remove(&id) {
+            Some(p) => {
+                p.cancel.cancel();
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn list(&self) -> Vec<Scheduled> {
+        let mut v: Vec<_> = self
+            .pending
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(&id, p)| Scheduled {
+                id,
+                at: p.at,
+                prefix: p.prefix,
+            })
+            .collect();
+
+        v.sort_by_key(|s| (s.at, s.id));
+        v
+    }
+}
@@ -0,0 +1,72 @@
+use crate::config;
+use anyhow::{Context, Result};
+use rustls::{Certificate, PrivateKey, ServerConfig};
+use std::{fs::File, io::BufReader, sync::Arc};
+
+/// Loads the certificate and key from the config, or generates a self-signed certificate without them
+pub(crate) fn server_config(config: &config::Tls) -> Result<Arc<ServerConfig>> {
+    let (certs, key) = if config.cert.is_empty() && config.key.is_empty() {
+        self_signed(config)?
+    } else {
+        let certs =
+            read_certs(&config.cert).with_context(|| format!("tls.cert {}", config.cert))?;
+        let key = read_key(&config.key).with_context(|| format!("tls.key {}", config.key))?;
+        (certs, key)
+    };
+
+    let config = ServerConfig::builder()
+        .with_safe_defaults()
+        .with_no_client_auth()
+        .with_single_cert(certs, key)
+        .context("invalid tls certificate or key")?;
+    Ok(Arc::new(config))
+}
+
+fn read_certs(path: &str) -> Result<Vec<Certificate>> {
+    let certs = rustls_pemfile::certs(&mut BufReader::new(File::open(path)?))?;
+    if certs.is_empty() {
+        anyhow::bail!("no certificates in the file");
+    }
+    Ok(certs.into_iter().map(Certificate).collect())
+}
+
+fn read_key(path: &str) -> Result<PrivateKey> {
+    let mut reader = BufReader::new(File::open(path)?);
+    while let Some(item) = rustls_pemfile::read_one(&mut reader)? {
+        match item {
+            rustls_pemfile::Item::RSAKey(key)
+            | rustls_pemfile::Item::PKCS8Key(key)
+            | rustls_pemfile::Item::ECKey(key) => return Ok(PrivateKey(key)),
+            _ => {}
+        }
+    }
+    anyhow::bail!("no private key in the file")
+}
+
+/// A new certificate on every start, signed by a new CA that's saved for the clients to trust it.
+/// A lone self-signed certificate would do for rustls, but not for most other clients.
+fn self_signed(config: &config::Tls) -> Result<(Vec<Certificate>, PrivateKey)> {
+    let mut ca = rcgen::CertificateParams::default();
+    ca.is_ca = rcgen::IsCa::Ca(rcgen::BasicConstraints::Unconstrained);
+    ca.distinguished_name
+        .push(rcgen::DnType::CommonName, "roblib development CA");
+    let ca = rcgen::Certificate::from_params(ca)?;
+
+    let mut cert = rcgen::CertificateParams::new(config.self_signed_names.clone());
+    cert.distinguished_name
+        .push(rcgen::DnType::CommonName, "roblib-server");
+    let cert = rcgen::Certificate::from_params(cert)?;
+
+    std::fs::write(&config.self_signed_cert, ca.serialize_pem()?)
+        .with_context(|| format!("tls.self_signed_cert {}", config.self_signed_cert))?;
+
+    warn!(
+        "No TLS certificate configured, generated a self-signed one for {:?}, its CA is saved to {}. Only use it for development!",
+        config.self_signed_names, config.self_signed_cert
+    );
+
+    Ok((
+        vec![Certificate(cert.serialize_der_with_signer(&ca)?)],
+        PrivateKey(cert.serialize_private_key_der()),
+    ))
+}
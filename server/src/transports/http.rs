@@ -1,13 +1,14 @@
 use super::ws::{ws_route, Rx};
-use crate::{cmd::execute_concrete, Backends};
+use crate::{cmd::execute_concrete, control::CLIENT, metrics, Backends};
 use axum::{
-    extract::State,
-    http::StatusCode,
+    extract::{ConnectInfo, State},
+    http::{header, HeaderMap, StatusCode},
     response::{IntoResponse, Redirect, Response},
     routing::{get, post},
-    Router, Server,
+    Router,
 };
-use roblib::{cmd, text_format};
+use axum_server::{tls_rustls::RustlsConfig, Handle};
+use roblib::{cmd, json, text_format};
 use std::{net::SocketAddr, sync::Arc};
 use tokio::{
     net::{lookup_host, ToSocketAddrs},
@@ -16,44 +17,179 @@ use tokio::{
 
 pub(crate) type AppState = State<(Arc<Backends>, Arc<Rx>)>;
 
+/// Serves `/cmd` if `http` is set, `/metrics` if `metrics` is, and `/ws` if there's a receiver for its events.
+/// Over `https://` and `wss://` if there's a TLS config.
 pub(crate) async fn start(
     addr: impl ToSocketAddrs,
     robot: Arc<Backends>,
-    rx: Rx,
+    http: bool,
+    metrics: bool,
+    ws: Option<Rx>,
+    tls: Option<Arc<rustls::ServerConfig>>,
 ) -> JoinHandle<Result<(), anyhow::Error>> {
     let abort = robot.abort_token.clone();
-    let app = Router::new()
+    let mut app = Router::new()
         .route("/", get(index))
-        .route("/cmd", post(cmd))
-        .route("/ws", get(ws_route))
-        .with_state((robot, Arc::new(rx)));
+        .route("/schema", get(schema));
+    if http {
+        app = app.route("/cmd", post(cmd));
+    }
+    if metrics {
+        app = app.route("/metrics", get(metrics_route));
+    }
+    if let Some(rx) = ws {
+        let ws = Router::new()
+            .route("/ws", get(ws_route))
+            .with_state((robot.clone(), Arc::new(rx)));
+        app = app.merge(ws);
+    }
+    let app = app.with_state(robot);
+
+    let app = app.into_make_service_with_connect_info::<SocketAddr>();
+
+    let handle = Handle::new();
+    let shutdown = handle.clone();
+    tokio::spawn(async move {
+        abort.cancelled().await;
+        log::debug!("abort: http");
+        shutdown.graceful_shutdown(None);
+    });
 
     let addr = lookup_host(&addr).await.unwrap().next().unwrap();
     tokio::spawn(async move {
-        Server::bind(&addr)
-            .serve(app.into_make_service_with_connect_info::<SocketAddr>())
-            .with_graceful_shutdown(async {
-                abort.cancelled().await;
-                log::debug!("abort: http")
-            })
-            .await?;
+        match tls {
+            Some(tls) => {
+                axum_server::bind_rustls(addr, RustlsConfig::from_config(tls))
+                    .handle(handle)
+                    .serve(app)
+                    .await?
+            }
+            None => axum_server::bind(addr).handle(handle).serve(app).await?,
+        }
         Ok(())
     })
 }
 
-async fn cmd(State((robot, _)): AppState, body: String) -> Result<impl IntoResponse, Response> {
-    let Ok(cmd) = text_format::de::from_str::<cmd::Concrete>(&body) else {
-        return Err((StatusCode::BAD_REQUEST, "invalid cmd").into_response());
+async fn cmd(
+    State(robot): State<Arc<Backends>>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    body: String,
+) -> Result<impl IntoResponse, Response> {
+    let is_json = headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.starts_with(json::CONTENT_TYPE));
+
+    let authenticated = authenticated(&robot, &headers);
+    // every request is a new connection, so the client is only told apart by its address
+    let client = format!("http {}", addr.ip());
+
+    if is_json {
+        return cmd_json(robot, body, authenticated, client).await;
+    }
+
+    let cmd = match text_format::de::from_str::<cmd::Concrete>(&body) {
+        Ok(cmd) => cmd,
+        Err(e) => {
+            // e.g. "invalid cmd: arg 2 of `m` (MoveRobot): expected f64, got `fast`"
+            return Err((StatusCode::BAD_REQUEST, format!("invalid cmd: {e}")).into_response());
+        }
     };
+    if let Err(e) = check(&robot, authenticated, &client, &cmd) {
+        return Err(e.into_response());
+    }
 
     let mut buf = String::new();
-    if let Err(e) =
-        execute_concrete(cmd, robot, &mut text_format::ser::Serializer::new(&mut buf)).await
-    {
+    let mut ser = text_format::ser::Serializer::new(&mut buf);
+    let exec = execute_concrete(cmd, robot, &mut ser);
+    if let Err(e) = CLIENT.scope(client, exec).await {
         return Err((StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response());
     }
 
-    Ok(buf)
+    Ok(buf.into_response())
+}
+
+/// Same as the text format, but the body is a [`json::Request`] and the reply is the return value in json,
+/// `null` if there's none
+async fn cmd_json(
+    robot: Arc<Backends>,
+    body: String,
+    authenticated: bool,
+    client: String,
+) -> Result<Response, Response> {
+    let cmd = match serde_json::from_str::<json::Request>(&body) {
+        Ok(req) => req.cmd,
+        Err(e) => {
+            return Err((StatusCode::BAD_REQUEST, format!("invalid cmd: {e}")).into_response());
+        }
+    };
+    if let Err(e) = check(&robot, authenticated, &client, &cmd) {
+        return Err(e.into_response());
+    }
+
+    let mut buf = Vec::new();
+    let mut ser = serde_json::Serializer::new(&mut buf);
+    let exec = execute_concrete(cmd, robot, &mut ser);
+    match CLIENT.scope(client, exec).await {
+        Ok(Some(())) => (),
+        Ok(None) => buf.extend_from_slice(b"null"),
+        Err(e) => {
+            return Err((StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response());
+        }
+    }
+
+    Ok(([(header::CONTENT_TYPE, json::CONTENT_TYPE)], buf).into_response())
+}
+
+/// Refuses the command with `401 Unauthorized` if the client has to authenticate first,
+/// or `409 Conflict` if another client is in control of the robot
+fn check(
+    robot: &Backends,
+    authenticated: bool,
+    client: &str,
+    cmd: &cmd::Concrete,
+) -> Result<(), (StatusCode, String)> {
+    robot
+        .auth
+        .check(authenticated, cmd)
+        .and_then(|_| robot.control.check(client, cmd))
+        .map_err(|e| {
+            let status = match e.0 {
+                cmd::ErrorCode::NotInControl => StatusCode::CONFLICT,
+                _ => StatusCode::UNAUTHORIZED,
+            };
+            (status, e.1)
+        })
+}
+
+/// Whether the request has the token of an authenticated session, as `Authorization: Bearer <token>`.
+/// Every request is if the server doesn't require authentication.
+fn authenticated(robot: &Backends, headers: &HeaderMap) -> bool {
+    if !robot.auth.required() {
+        return true;
+    }
+    headers
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .is_some_and(|token| robot.auth.token(token))
+}
+
+/// The schema of every command and event the server supports, in json
+async fn schema() -> Result<Response, Response> {
+    match serde_json::to_string(&roblib::schema::export()) {
+        Ok(s) => Ok(([(header::CONTENT_TYPE, json::CONTENT_TYPE)], s).into_response()),
+        Err(e) => Err((StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response()),
+    }
+}
+
+/// The server's metrics in the OpenMetrics text format, for Prometheus to scrape
+async fn metrics_route(State(robot): State<Arc<Backends>>) -> Result<Response, Response> {
+    match robot.metrics.encode() {
+        Ok(s) => Ok(([(header::CONTENT_TYPE, metrics::CONTENT_TYPE)], s).into_response()),
+        Err(e) => Err((StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response()),
+    }
 }
 
 // redirect to GitHub repo for no particular reason
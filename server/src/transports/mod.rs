@@ -11,6 +11,15 @@ pub enum SubscriptionId {
 }
 
 impl SubscriptionId {
+    /// The name of the client, the same as the transports give them
+    pub fn client(&self) -> String {
+        match self {
+            SubscriptionId::Tcp(addr, _) => format!("tcp {addr}"),
+            SubscriptionId::Udp(addr, _) => format!("udp {addr}"),
+            SubscriptionId::Ws(addr, _) => format!("ws {addr}"),
+        }
+    }
+
     pub fn same_client(&self, other: &Self) -> bool {
         match (self, other) {
             (SubscriptionId::Tcp(addr1, _), SubscriptionId::Tcp(addr2, _)) => *addr1 == *addr2,
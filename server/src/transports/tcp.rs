@@ -1,19 +1,27 @@
 //! TCP wire format:
 //! -> u32: message length, (u32: id, roblib::cmd::Concrete)
-//! <- u32: message length, (u32: id, roblib::cmd::Concrete::Return)
-//! <- u32: message length, (u32: id, roblib::event::Event::Item)
+//! <- u32: message length, (u32: id, Result<roblib::cmd::Concrete::Return, roblib::cmd::Error>)
+//! <- u32: message length, (u32: id, Result<roblib::event::Event::Item, roblib::cmd::Error>)
+//!
+//! Messages longer than the configured maximum are skipped, and answered with a `Decode` error.
+//! With TLS enabled, the same is sent over the encrypted stream.
 use crate::{
-    cmd::execute_concrete, event_bus::sub::SubStatus, transports::SubscriptionId, Backends,
+    cleanup, cmd::execute_concrete, control, event_bus::sub::SubStatus, metrics,
+    transports::SubscriptionId, Backends,
 };
 use roblib::{cmd, event::ConcreteValue};
-use std::{io::Cursor, net::SocketAddr, sync::Arc, time::Duration};
+use std::{net::SocketAddr, sync::Arc};
 use tokio::{
-    io::{AsyncReadExt, AsyncWriteExt, Interest},
+    io::{self, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt},
     net::{TcpListener, TcpStream, ToSocketAddrs},
     spawn,
-    sync::broadcast::{Receiver, Sender},
+    sync::{
+        broadcast::{error::RecvError, Receiver, Sender},
+        mpsc,
+    },
     task::JoinHandle,
 };
+use tokio_rustls::TlsAcceptor;
 
 pub type Id = SocketAddr;
 pub type SubId = u32;
@@ -27,12 +35,20 @@ pub(crate) async fn start(
     addr: impl ToSocketAddrs,
     robot: Arc<Backends>,
     rx: Rx,
+    max_frame: usize,
+    tls: Option<TlsAcceptor>,
 ) -> anyhow::Result<JoinHandle<Ret>> {
     let server = TcpListener::bind(addr).await?;
-    Ok(spawn(run(server, robot, rx)))
+    Ok(spawn(run(server, robot, rx, max_frame, tls)))
 }
 
-async fn run(server: TcpListener, robot: Arc<Backends>, rx: Rx) -> Ret {
+async fn run(
+    server: TcpListener,
+    robot: Arc<Backends>,
+    rx: Rx,
+    max_frame: usize,
+    tls: Option<TlsAcceptor>,
+) -> Ret {
     let mut handles = Vec::new();
     loop {
         let conn = tokio::select! {
@@ -40,88 +56,175 @@ async fn run(server: TcpListener, robot: Arc<Backends>, rx: Rx) -> Ret {
             _ = robot.abort_token.cancelled() => return handles,
             Ok(conn) = server.accept() => conn,
         };
-        let h = spawn(handle_client(robot.clone(), conn, rx.resubscribe()));
+        let h = spawn(handle_client(
+            robot.clone(),
+            conn,
+            rx.resubscribe(),
+            max_frame,
+            tls.clone(),
+        ));
         handles.push(h);
     }
 }
 
+/// The most bytes a varint encoded `u32` takes up
+const ID_LEN: usize = 5;
+
+enum Frame {
+    Command(Vec<u8>),
+    /// A frame over the limit, only the start of it is kept, for the id
+    Oversize {
+        len: usize,
+        head: Vec<u8>,
+    },
+}
+
 enum Action {
-    ClientMessage(usize),
+    ClientMessage(Frame),
     Event(ConcreteValue, Item),
     Disconnect,
     ServerAbort,
 }
 
+/// Reads frames until the client disconnects, skipping the ones over `max_frame`
+async fn read_frames(
+    mut stream: impl AsyncRead + Unpin,
+    max_frame: usize,
+    tx: mpsc::Sender<Frame>,
+) -> io::Result<()> {
+    loop {
+        let len = match stream.read_u32().await {
+            Ok(len) => len as usize,
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(()),
+            Err(e) => return Err(e),
+        };
+
+        let frame = if len > max_frame {
+            let mut head = vec![0; len.min(ID_LEN)];
+            stream.read_exact(&mut head).await?;
+            let rest = (len - head.len()) as u64;
+            io::copy(&mut (&mut stream).take(rest), &mut io::sink()).await?;
+            Frame::Oversize { len, head }
+        } else {
+            let mut buf = vec![0; len];
+            stream.read_exact(&mut buf).await?;
+            Frame::Command(buf)
+        };
+
+        if tx.send(frame).await.is_err() {
+            return Ok(());
+        }
+    }
+}
+
 async fn handle_client(
     robot: Arc<Backends>,
-    (mut stream, addr): (TcpStream, SocketAddr),
+    (stream, addr): (TcpStream, SocketAddr),
+    rx: Rx,
+    max_frame: usize,
+    tls: Option<TlsAcceptor>,
+) -> anyhow::Result<()> {
+    let Some(tls) = tls else {
+        return serve(robot, stream, addr, rx, max_frame).await;
+    };
+
+    let stream = tokio::select! {
+        _ = robot.abort_token.cancelled() => return Ok(()),
+        res = tls.accept(stream) => match res {
+            Ok(s) => s,
+            Err(e) => {
+                log::error!("tcp: TLS handshake with {addr} failed: {e}");
+                return Ok(());
+            }
+        },
+    };
+    serve(robot, stream, addr, rx, max_frame).await
+}
+
+async fn serve(
+    robot: Arc<Backends>,
+    stream: impl AsyncRead + AsyncWrite + Send + 'static,
+    addr: SocketAddr,
     mut rx: Rx,
+    max_frame: usize,
 ) -> anyhow::Result<()> {
     let bin = bincode::options();
-    const HEADER: usize = std::mem::size_of::<u32>();
 
-    let mut buf = vec![0; 512];
-    let mut len = 0; // no. of bytes read for the current command we're attempting to parse
-    let mut maybe_cmd_len = None;
+    let (read, mut stream) = io::split(stream);
+    let (frame_tx, mut frames) = mpsc::channel(1);
+    let reader = spawn(async move {
+        if let Err(e) = read_frames(read, max_frame, frame_tx).await {
+            log::debug!("tcp: {addr} read error: {e}");
+        }
+    });
+    // stop reading when the client is dropped, whatever the reason
+    let _reader = AbortOnDrop(reader);
+
+    let mut buf = Vec::new();
+    let mut authenticated = false;
+    let client = format!("tcp {addr}");
+    let _cleanup = cleanup::Guard {
+        robot: &robot,
+        client: &client,
+        sub: SubscriptionId::Tcp(addr, 0),
+    };
+    let _connected = metrics::Connected::new(&robot.metrics, "tcp");
 
     loop {
         let action = tokio::select! {
             _ = robot.abort_token.cancelled() => Action::ServerAbort,
-            Ok(n) = stream.read(&mut buf[len..( HEADER + maybe_cmd_len.unwrap_or(0) )]) => Action::ClientMessage(n),
-            Ok(msg) = rx.recv() => Action::Event(msg.0, msg.1),
-            _ = tokio::time::sleep(Duration::from_secs(5)) => {
-                let r = stream.ready(Interest::READABLE | Interest::WRITABLE).await;
-                if r.map_or(true, |r| r.is_read_closed() || r.is_write_closed()) {
-                    Action::Disconnect
-                } else { continue; }
-            }
+            frame = frames.recv() => match frame {
+                Some(f) => Action::ClientMessage(f),
+                None => Action::Disconnect,
+            },
+            msg = rx.recv() => match msg {
+                Ok(msg) => Action::Event(msg.0, msg.1),
+                Err(RecvError::Lagged(n)) => {
+                    log::error!("tcp: {addr} events lagging by {n}");
+                    robot.metrics.lagged("tcp", n);
+                    continue;
+                }
+                // the event bus only goes away when the server is shutting down
+                Err(RecvError::Closed) => Action::ServerAbort,
+            },
         };
 
         match action {
-            Action::ClientMessage(n) => {
-                if n == 0 {
-                    log::debug!("tcp: received 0 sized msg, investigating disconnect");
-                    // give the socket some time to fully realize disconnect
-                    tokio::time::sleep(Duration::from_millis(50)).await;
-                    let r = stream.ready(Interest::READABLE | Interest::WRITABLE).await;
-                    if r.map_or(true, |r| r.is_read_closed() || r.is_write_closed()) {
-                        log::debug!("tcp client disconnected: {addr}");
-                        return Ok(());
-                    }
-                }
-
-                len += n;
-                // log::debug!(
-                //     "Thing::ClientMessage - n: {n}, len: {len}, mblen: {maybe_cmd_len:?}, buflen: {}",
-                //     buf.len()
-                // );
-
-                // not enough bytes to get command length
-                if len < HEADER {
-                    // log::debug!("read more header");
-                    continue;
+            Action::ClientMessage(Frame::Oversize { len, head }) => {
+                log::error!(
+                    "tcp: {addr} sent a frame of {len} bytes, over the limit of {max_frame}"
+                );
+                if let Ok(id) = bincode::Options::deserialize_from(bin, &head[..]) {
+                    let err = cmd::Error::new(
+                        cmd::ErrorCode::Decode,
+                        format!("frame of {len} bytes is over the limit of {max_frame}"),
+                    );
+                    send_error(&mut stream, id, err).await?;
                 }
+            }
 
-                let cmd_len = match maybe_cmd_len {
-                    Some(n) => n,
-                    None => {
-                        let cmd = u32::from_be_bytes((&buf[..HEADER]).try_into().unwrap()) as usize;
-                        // buf.resize(HEADER + cmd, 0);
-                        maybe_cmd_len = Some(cmd);
-                        // log::debug!("header received, cmdlen: {cmd}");
-                        cmd
-                    }
-                };
+            Action::ClientMessage(Frame::Command(frame)) => {
+                let (id, cmd): (u32, cmd::Concrete) =
+                    match bincode::Options::deserialize(bin, &frame) {
+                        Ok(v) => v,
+                        Err(e) => {
+                            log::error!("tcp: failed to decode command: {e}");
+                            let id = bincode::Options::deserialize_from(bin, &frame[..]);
+                            if let Ok(id) = id {
+                                let err = cmd::Error::new(cmd::ErrorCode::Decode, e);
+                                send_error(&mut stream, id, err).await?;
+                            }
+                            continue;
+                        }
+                    };
 
-                // not enough bytes to parse command, get some more
-                if len < HEADER + cmd_len {
-                    // log::debug!("read more command");
+                let allowed = robot.auth.check(authenticated, &cmd);
+                if let Err(err) = allowed.and_then(|_| robot.control.check(&client, &cmd)) {
+                    log::error!("tcp: {addr} command {id} refused: {}", err.1);
+                    send_error(&mut stream, id, err).await?;
                     continue;
                 }
 
-                let (id, cmd): (u32, cmd::Concrete) =
-                    bincode::Options::deserialize(bin, &buf[HEADER..len])?;
-
                 match cmd {
                     cmd::Concrete::Subscribe(c) => {
                         let sub = SubscriptionId::Tcp(addr, id);
@@ -138,55 +241,53 @@ async fn handle_client(
 
                     // execute any other command the usual way
                     _ => {
-                        let mut c = Cursor::new(&mut buf[..]);
-                        bincode::Options::serialize_into(bin, &mut c, &id)?;
-                        let res = execute_concrete(
-                            cmd,
-                            robot.clone(),
-                            &mut bincode::Serializer::new(&mut c, bin),
-                        )
-                        .await?;
-
-                        if res.is_some() {
-                            let len = c.position();
-                            stream.write_all(&(len as u32).to_be_bytes()).await?;
-                            stream.write_all(&buf[..len as usize]).await?;
+                        let authenticates = matches!(cmd, cmd::Concrete::Authenticate(_));
+
+                        // the `Ok` variant tag followed by the return value
+                        // is the same as a serialized `Result<Return, Error>`
+                        buf.clear();
+                        let ok = Ok::<_, cmd::Error>(());
+                        bincode::Options::serialize_into(bin, &mut buf, &(id, ok))?;
+                        let res = control::CLIENT
+                            .scope(
+                                client.clone(),
+                                execute_concrete(
+                                    cmd,
+                                    robot.clone(),
+                                    &mut bincode::Serializer::new(&mut buf, bin),
+                                ),
+                            )
+                            .await;
+                        authenticated |= authenticates && res.is_ok();
+
+                        match res {
+                            Ok(Some(_)) => {
+                                stream.write_all(&(buf.len() as u32).to_be_bytes()).await?;
+                                stream.write_all(&buf).await?;
+                                stream.flush().await?;
+                            }
+                            Ok(None) => (),
+                            Err(e) => {
+                                log::error!("tcp: command {id} failed: {e}");
+                                let err = cmd::Error::new(cmd::ErrorCode::Execute, e);
+                                send_error(&mut stream, id, err).await?;
+                            }
                         }
                     }
                 }
-
-                // reset
-                len = 0;
-                maybe_cmd_len = None;
             }
 
             Action::Event(ev, (ev_addr, id)) => {
                 if addr != ev_addr {
                     continue;
                 }
-                let data: Vec<u8> = match ev {
-                    #[cfg(feature = "roland")]
-                    ConcreteValue::TrackSensor(v) => bincode::Options::serialize(bin, &(id, v))?,
-                    #[cfg(feature = "roland")]
-                    ConcreteValue::UltraSensor(v) => bincode::Options::serialize(bin, &(id, v))?,
-                    #[cfg(feature = "gpio")]
-                    ConcreteValue::GpioPin(v) => bincode::Options::serialize(bin, &(id, v))?,
-                    #[cfg(feature = "camloc")]
-                    ConcreteValue::CamlocConnect(v) => bincode::Options::serialize(bin, &(id, v))?,
-                    #[cfg(feature = "camloc")]
-                    ConcreteValue::CamlocDisconnect(v) => {
-                        bincode::Options::serialize(bin, &(id, v))?
-                    }
-                    #[cfg(feature = "camloc")]
-                    ConcreteValue::CamlocPosition(v) => bincode::Options::serialize(bin, &(id, v))?,
-                    #[cfg(feature = "camloc")]
-                    ConcreteValue::CamlocInfoUpdate(v) => {
-                        bincode::Options::serialize(bin, &(id, v))?
-                    }
-                    ConcreteValue::None => continue,
-                };
+                if matches!(ev, ConcreteValue::None) {
+                    continue;
+                }
+                let data = bincode::Options::serialize(bin, &(id, Ok::<_, cmd::Error>(ev)))?;
                 stream.write_all(&(data.len() as u32).to_be_bytes()).await?;
                 stream.write_all(&data).await?;
+                stream.flush().await?;
             }
 
             Action::Disconnect => {
@@ -200,3 +301,23 @@ async fn handle_client(
         }
     }
 }
+
+struct AbortOnDrop(JoinHandle<()>);
+
+impl Drop for AbortOnDrop {
+    fn drop(&mut self) {
+        self.0.abort();
+    }
+}
+
+async fn send_error(
+    stream: &mut (impl AsyncWrite + Unpin),
+    id: u32,
+    err: cmd::Error,
+) -> anyhow::Result<()> {
+    let data = bincode::Options::serialize(bincode::options(), &(id, Err::<(), _>(err)))?;
+    stream.write_all(&(data.len() as u32).to_be_bytes()).await?;
+    stream.write_all(&data).await?;
+    stream.flush().await?;
+    Ok(())
+}
@@ -1,8 +1,13 @@
 use super::SubscriptionId;
-use crate::{cmd::execute_concrete, event_bus::sub::SubStatus, Backends};
+use crate::{cmd::execute_concrete, control, event_bus::sub::SubStatus, Backends};
 use anyhow::Result;
 use roblib::{cmd, event::ConcreteValue};
-use std::{io::Cursor, net::SocketAddr, sync::Arc};
+use std::{
+    collections::HashMap,
+    net::SocketAddr,
+    sync::Arc,
+    time::{Duration, Instant},
+};
 use tokio::{
     net::{ToSocketAddrs, UdpSocket},
     spawn,
@@ -20,23 +25,85 @@ pub(crate) async fn start(
     addr: impl ToSocketAddrs,
     robot: Arc<Backends>,
     rx: Rx,
+    max_frame: usize,
 ) -> Result<(JoinHandle<Result<()>>, JoinHandle<Result<()>>)> {
     let socket = Arc::new(UdpSocket::bind(addr).await?);
 
-    let server = spawn(run(socket.clone(), robot));
+    let server = spawn(run(socket.clone(), robot, max_frame));
     let event_handler = spawn(handle_event(rx, socket));
 
     Ok((server, event_handler))
 }
 
-async fn run(server: Arc<UdpSocket>, robot: Arc<Backends>) -> Result<()> {
+/// The largest payload a datagram can have
+const MAX_DATAGRAM: usize = 65507;
+
+/// How often the sessions are checked for clients that went quiet
+const SESSION_CHECK: Duration = Duration::from_secs(1);
+
+async fn run(server: Arc<UdpSocket>, robot: Arc<Backends>, max_frame: usize) -> Result<()> {
     let bin = bincode::options();
-    let mut buf = [0u8; 1024];
+    // one more byte, so an oversize datagram is detected even if it gets truncated
+    let mut recv = vec![0u8; max_frame.min(MAX_DATAGRAM) + 1];
+    let mut buf = Vec::new();
+    let session = Some(robot.cleanup.udp_session()).filter(|s| !s.is_zero());
+    let mut last_seen: HashMap<SocketAddr, Instant> = HashMap::new();
+    let mut check = tokio::time::interval(SESSION_CHECK);
 
     loop {
-        let (len, addr) = server.recv_from(&mut buf).await?;
+        let res = tokio::select! {
+            res = server.recv_from(&mut recv) => res,
+            _ = check.tick(), if session.is_some() => {
+                expire(&robot, session.unwrap(), &mut last_seen);
+                continue;
+            }
+        };
+        let (len, addr) = match res {
+            Ok(v) => v,
+            Err(e) => {
+                log::error!("udp: recv error: {e}");
+                continue;
+            }
+        };
+        // without a session, clients aren't counted as connected, there's no telling when they're gone
+        if session.is_some() && last_seen.insert(addr, Instant::now()).is_none() {
+            robot.metrics.connected("udp");
+        }
+
+        if len > max_frame {
+            log::error!("udp: {addr} sent a datagram over the limit of {max_frame} bytes");
+            if let Ok(id) = bincode::Options::deserialize_from(bin, &recv[..len]) {
+                let err = cmd::Error::new(
+                    cmd::ErrorCode::Decode,
+                    format!("datagram is over the limit of {max_frame} bytes"),
+                );
+                send_error(&server, addr, id, err).await;
+            }
+            continue;
+        }
 
-        let (id, cmd): (u32, cmd::Concrete) = bincode::Options::deserialize(bin, &buf[..len])?;
+        let ((id, cmd), token) = match decode(&recv[..len]) {
+            Ok(v) => v,
+            Err(e) => {
+                log::error!("udp: failed to decode command from {addr}: {e}");
+                if let Ok(id) = bincode::Options::deserialize_from(bin, &recv[..len]) {
+                    let err = cmd::Error::new(cmd::ErrorCode::Decode, e);
+                    send_error(&server, addr, id, err).await;
+                }
+                continue;
+            }
+        };
+
+        let client = format!("udp {addr}");
+        // there are no connections, and the address could be spoofed,
+        // so every datagram of an authenticated client carries its session token
+        let authenticated = token.is_some_and(|t| robot.auth.token(t));
+        let allowed = robot.auth.check(authenticated, &cmd);
+        if let Err(err) = allowed.and_then(|_| robot.control.check(&client, &cmd)) {
+            log::error!("udp: {addr} command {id} refused: {}", err.1);
+            send_error(&server, addr, id, err).await;
+            continue;
+        }
 
         match cmd {
             cmd::Concrete::Subscribe(c) => {
@@ -57,61 +124,141 @@ async fn run(server: Arc<UdpSocket>, robot: Arc<Backends>) -> Result<()> {
             _ => (),
         }
 
-        let mut c = Cursor::new(&mut buf[..]);
-        bincode::Options::serialize_into(bin, &mut c, &id)?;
+        // the `Ok` variant tag followed by the return value
+        // is the same as a serialized `Result<Return, Error>`
+        buf.clear();
+        bincode::Options::serialize_into(bin, &mut buf, &(id, Ok::<_, cmd::Error>(())))?;
 
-        let res = execute_concrete(
-            cmd,
-            robot.clone(),
-            &mut bincode::Serializer::new(&mut c, bin),
-        )
-        .await?;
+        let res = control::CLIENT
+            .scope(
+                client,
+                execute_concrete(
+                    cmd,
+                    robot.clone(),
+                    &mut bincode::Serializer::new(&mut buf, bin),
+                ),
+            )
+            .await;
 
-        if res.is_some() {
-            server.send_to(&buf, addr).await?;
+        match res {
+            Ok(Some(_)) if buf.len() > MAX_DATAGRAM => {
+                log::error!("udp: reply to command {id} doesn't fit in a datagram");
+                let err = cmd::Error::new(
+                    cmd::ErrorCode::Execute,
+                    format!("reply of {} bytes doesn't fit in a datagram", buf.len()),
+                );
+                send_error(&server, addr, id, err).await;
+            }
+            Ok(Some(_)) => {
+                if let Err(e) = server.send_to(&buf, addr).await {
+                    log::error!("udp: failed to reply to {addr}: {e}");
+                }
+            }
+            Ok(None) => (),
+            Err(e) => {
+                log::error!("udp: command {id} failed: {e}");
+                let err = cmd::Error::new(cmd::ErrorCode::Execute, e);
+                send_error(&server, addr, id, err).await;
+            }
         }
     }
 }
 
+/// Cleans up after the clients that have gone quiet for longer than `session`, as if they disconnected
+fn expire(robot: &Backends, session: Duration, last_seen: &mut HashMap<SocketAddr, Instant>) {
+    last_seen.retain(|addr, seen| {
+        if seen.elapsed() < session {
+            return true;
+        }
+        log::debug!("udp client timed out: {addr}");
+        let client = format!("udp {addr}");
+        let sub = SubscriptionId::Udp(*addr, 0);
+        robot.cleanup.disconnected(robot, &client, sub);
+        robot.metrics.disconnected("udp");
+        false
+    });
+}
+
+/// Splits a datagram into the command and the session token after it, if there's one
+fn decode(datagram: &[u8]) -> bincode::Result<((u32, cmd::Concrete), Option<&str>)> {
+    let mut rest = datagram;
+    let cmd = bincode::Options::deserialize_from(bincode::options(), &mut rest)?;
+    let token = std::str::from_utf8(rest).ok().filter(|t| !t.is_empty());
+    Ok((cmd, token))
+}
+
+/// Failing to send is only logged, one unreachable client shouldn't stop the server
+async fn send_error(server: &UdpSocket, addr: SocketAddr, id: u32, err: cmd::Error) {
+    let data = bincode::Options::serialize(bincode::options(), &(id, Err::<(), _>(err)))
+        .expect("errors always serialize");
+    if let Err(e) = server.send_to(&data, addr).await {
+        log::error!("udp: failed to reply to {addr}: {e}");
+    }
+}
+
 async fn handle_event(mut event_bus: Rx, event_send: Arc<UdpSocket>) -> Result<()> {
     let bin = bincode::options();
     while let Some((ev, (addr, id))) = event_bus.recv().await {
-        let val: Vec<u8> = match ev {
-            #[cfg(feature = "roland")]
-            roblib::event::ConcreteValue::TrackSensor(val) => {
-                bincode::Options::serialize(bin, &(id, val))?
-            }
-            #[cfg(feature = "roland")]
-            roblib::event::ConcreteValue::UltraSensor(val) => {
-                bincode::Options::serialize(bin, &(id, val))?
-            }
+        if matches!(ev, roblib::event::ConcreteValue::None) {
+            continue;
+        }
+        let val = bincode::Options::serialize(bin, &(id, Ok::<_, cmd::Error>(ev)))?;
 
-            #[cfg(feature = "gpio")]
-            roblib::event::ConcreteValue::GpioPin(val) => {
-                bincode::Options::serialize(bin, &(id, val))?
-            }
+        if let Err(e) = event_send.send_to(&val, addr).await {
+            log::error!("udp: failed to send event to {addr}: {e}");
+        }
+    }
+    Ok(())
+}
 
-            #[cfg(feature = "camloc")]
-            roblib::event::ConcreteValue::CamlocConnect(val) => {
-                bincode::Options::serialize(bin, &(id, val))?
-            }
-            #[cfg(feature = "camloc")]
-            roblib::event::ConcreteValue::CamlocDisconnect(val) => {
-                bincode::Options::serialize(bin, &(id, val))?
-            }
-            #[cfg(feature = "camloc")]
-            roblib::event::ConcreteValue::CamlocPosition(val) => {
-                bincode::Options::serialize(bin, &(id, val))?
-            }
-            #[cfg(feature = "camloc")]
-            roblib::event::ConcreteValue::CamlocInfoUpdate(val) => {
-                bincode::Options::serialize(bin, &(id, val))?
-            }
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Config;
 
-            roblib::event::ConcreteValue::None => continue,
-        };
+    #[test]
+    fn sessions_expire() {
+        let robot = Backends::test(&Config::default());
+        let mut subs = robot.sub.subscribe();
+        let session = Duration::from_secs(60);
 
-        event_send.send_to(&val, addr).await?;
+        let quiet: SocketAddr = ([127, 0, 0, 1], 1).into();
+        let active: SocketAddr = ([127, 0, 0, 1], 2).into();
+        let mut last_seen =
+            HashMap::from([(quiet, Instant::now() - session), (active, Instant::now())]);
+        robot.control.acquire(format!("udp {quiet}")).unwrap();
+
+        expire(&robot, session, &mut last_seen);
+
+        assert_eq!(last_seen.keys().collect::<Vec<_>>(), [&active]);
+        robot.control.acquire(format!("udp {active}")).unwrap();
+        let (_, sub, _) = subs.try_recv().unwrap();
+        assert_eq!(sub, SubscriptionId::Udp(quiet, 0));
+        assert!(subs.try_recv().is_err());
+    }
+
+    #[test]
+    fn datagrams_carry_the_token() {
+        let mut config = Config::default();
+        config.auth.secret = "hunter2".into();
+        let robot = Backends::test(&config);
+        let challenge = robot.auth.challenge("udp test").unwrap();
+        let response = cmd::auth::respond("hunter2", &challenge);
+        let token = robot.auth.authenticate(&challenge, &response).unwrap();
+
+        let cmd: cmd::Concrete = cmd::GetUptime.into();
+        let mut datagram = bincode::Options::serialize(bincode::options(), &(7u32, cmd)).unwrap();
+
+        let ((id, cmd), none) = decode(&datagram).unwrap();
+        assert_eq!(id, 7);
+        assert!(none.is_none());
+        assert!(robot.auth.check(false, &cmd).is_err());
+
+        datagram.extend_from_slice(token.as_bytes());
+        let (_, sent) = decode(&datagram).unwrap();
+        assert_eq!(sent, Some(token.as_str()));
+        assert!(robot.auth.token(&token));
+
+        assert!(!robot.auth.token("0123"));
     }
-    Ok(())
 }
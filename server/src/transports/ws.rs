@@ -1,5 +1,11 @@
 use crate::{
-    cmd::execute_concrete, event_bus::sub::SubStatus, transports::SubscriptionId, Backends,
+    cleanup,
+    cmd::{execute, execute_concrete},
+    control::CLIENT,
+    event_bus::sub::SubStatus,
+    metrics,
+    transports::SubscriptionId,
+    Backends,
 };
 use axum::{
     extract::{
@@ -8,9 +14,10 @@ use axum::{
     },
     response::IntoResponse,
 };
-use roblib::{cmd, event::ConcreteValue, text_format};
-use std::{fmt::Write, io::Cursor, net::SocketAddr, sync::Arc};
-use tokio::sync::broadcast::{Receiver, Sender};
+use roblib::{cmd, event::ConcreteValue, json, text_format};
+use serde::Serialize;
+use std::{collections::HashMap, fmt::Write, io::Cursor, net::SocketAddr, sync::Arc};
+use tokio::sync::broadcast::{error::RecvError, Receiver, Sender};
 
 pub type Id = SocketAddr;
 pub type SubId = u32;
@@ -23,11 +30,20 @@ pub(crate) async fn ws_route(
     ws: WebSocketUpgrade,
     ConnectInfo(addr): ConnectInfo<SocketAddr>,
 ) -> impl IntoResponse {
-    ws.on_upgrade(move |socket| async move {
-        if let Err(e) = ws_handler(socket, addr, robot, rx.resubscribe()).await {
-            log::error!("ws error: {e}");
-        }
-    })
+    ws.protocols([json::PROTOCOL])
+        .on_upgrade(move |socket| async move {
+            if let Err(e) = ws_handler(socket, addr, robot, rx.resubscribe()).await {
+                log::error!("ws error: {e}");
+            }
+        })
+}
+
+/// What the client sent a command in, replies are sent in the same one
+#[derive(Clone, Copy)]
+enum Format {
+    Bincode,
+    Text,
+    Json,
 }
 
 enum Action {
@@ -45,6 +61,24 @@ async fn ws_handler(
 ) -> anyhow::Result<()> {
     let bin = bincode::options();
 
+    // json is selected by the subprotocol, or a `json` first message.
+    // in json mode text messages are json, and events are sent as json too
+    let mut json = socket
+        .protocol()
+        .is_some_and(|p| p.as_bytes() == json::PROTOCOL.as_bytes());
+    let mut first = true;
+
+    // events are sent in the format they were subscribed in
+    let mut subs = HashMap::new();
+    let mut authenticated = false;
+    let client = format!("ws {addr}");
+    let _cleanup = cleanup::Guard {
+        robot: &robot,
+        client: &client,
+        sub: SubscriptionId::Ws(addr, 0),
+    };
+    let _connected = metrics::Connected::new(&robot.metrics, "ws");
+
     loop {
         let action = tokio::select! {
             _ = robot.abort_token.cancelled() => Action::ServerAbort,
@@ -52,25 +86,55 @@ async fn ws_handler(
                 Some(msg) => Action::ClientMessage(msg?),
                 None => Action::Disconnect,
             },
-            Ok(msg) = rx.recv() => Action::Event(msg.0, msg.1),
+            msg = rx.recv() => match msg {
+                Ok(msg) => Action::Event(msg.0, msg.1),
+                Err(RecvError::Lagged(n)) => {
+                    log::error!("ws: {addr} events lagging by {n}");
+                    robot.metrics.lagged("ws", n);
+                    continue;
+                }
+                // the event bus only goes away when the server is shutting down
+                Err(RecvError::Closed) => Action::ServerAbort,
+            },
         };
         match action {
             Action::ClientMessage(msg) => {
-                let (id, cmd): (u32, cmd::Concrete) = match &msg {
-                    Message::Text(s) => match text_format::de::from_str(s) {
-                        Ok(d) => d,
-                        Err(e) => {
-                            log::error!("text_format error: {e}");
-                            continue;
-                        }
-                    },
-                    Message::Binary(b) => match bincode::Options::deserialize(bin, b) {
-                        Ok(d) => d,
-                        Err(e) => {
-                            log::error!("bincode error: {e}");
-                            continue;
-                        }
-                    },
+                if std::mem::take(&mut first)
+                    && matches!(&msg, Message::Text(s) if s == json::PROTOCOL)
+                {
+                    log::debug!("ws: {addr} switched to json");
+                    json = true;
+                    continue;
+                }
+
+                let (format, decoded) = match &msg {
+                    Message::Text(s) if json => (
+                        Format::Json,
+                        serde_json::from_str(s)
+                            .map(|r: json::Request| (r.id, r.cmd))
+                            .map_err(|e| {
+                                // try to get an id for the error reply
+                                let id = serde_json::from_str::<serde_json::Value>(s)
+                                    .ok()
+                                    .and_then(|v| v.get("id")?.as_u64())
+                                    .and_then(|id| id.try_into().ok());
+                                (e.to_string(), id)
+                            }),
+                    ),
+                    Message::Text(s) => (
+                        Format::Text,
+                        text_format::de::from_str(s).map_err(|e| {
+                            let id = s.split(cmd::SEPARATOR).next().map(str::parse);
+                            (e.to_string(), id.and_then(Result::ok))
+                        }),
+                    ),
+                    Message::Binary(b) => (
+                        Format::Bincode,
+                        bincode::Options::deserialize(bin, b).map_err(|e| {
+                            let id = bincode::Options::deserialize_from(bin, &b[..]);
+                            (e.to_string(), id.ok())
+                        }),
+                    ),
                     Message::Close(close) => {
                         if let Some(close) = close {
                             log::debug!("ws close, reason: {}", close.reason);
@@ -80,74 +144,117 @@ async fn ws_handler(
                     _ => continue,
                 };
 
+                let (id, cmd): (u32, cmd::Concrete) = match decoded {
+                    Ok(d) => d,
+                    Err((e, id)) => {
+                        log::error!("ws: failed to decode command: {e}");
+                        if let Some(id) = id {
+                            let err = cmd::Error::new(cmd::ErrorCode::Decode, e);
+                            send_error(&mut socket, format, id, err).await?;
+                        }
+                        continue;
+                    }
+                };
+
+                let allowed = robot.auth.check(authenticated, &cmd);
+                if let Err(err) = allowed.and_then(|_| robot.control.check(&client, &cmd)) {
+                    log::error!("ws: {addr} command {id} refused: {}", err.1);
+                    send_error(&mut socket, format, id, err).await?;
+                    continue;
+                }
+
                 match cmd {
                     cmd::Concrete::Subscribe(c) => {
+                        subs.insert(id, format);
                         let sub = SubscriptionId::Ws(addr, id);
                         if let Err(e) = robot.sub.send((c.0, sub, SubStatus::Subscribe)) {
                             log::error!("event bus sub error: {e}");
                         };
                     }
                     cmd::Concrete::Unsubscribe(c) => {
+                        subs.remove(&id);
                         let unsub = SubscriptionId::Ws(addr, id);
                         if let Err(e) = robot.sub.send((c.0, unsub, SubStatus::Unsubscribe)) {
                             log::error!("event bus sub error: {e}");
                         };
                     }
 
-                    _ => match &msg {
-                        Message::Text(_) => {
-                            let mut buf = String::new();
-                            let mut ser = text_format::ser::Serializer::new(&mut buf);
-                            write!(ser, "{id}")?;
-                            let res = execute_concrete(cmd, robot.clone(), &mut ser).await?;
-                            if res.is_some() {
-                                socket.send(Message::Text(buf)).await?;
+                    // the `Ok` variant tag followed by the return value
+                    // is the same as a serialized `Result<Return, Error>`
+                    _ => {
+                        let authenticates = matches!(cmd, cmd::Concrete::Authenticate(_));
+                        let res = match format {
+                            Format::Text => {
+                                let mut buf = String::new();
+                                let mut ser = text_format::ser::Serializer::new(&mut buf);
+                                write!(ser, "{id}")?;
+                                Ok::<_, cmd::Error>(()).serialize(&mut ser)?;
+                                let exec = execute_concrete(cmd, robot.clone(), &mut ser);
+                                CLIENT
+                                    .scope(client.clone(), exec)
+                                    .await
+                                    .map(|r| r.map(|_| Message::Text(buf)))
                             }
-                        }
-                        Message::Binary(_) => {
-                            let mut v = Vec::new();
-                            let mut c = Cursor::new(&mut v);
-                            bincode::Options::serialize_into(bin, &mut c, &id)?;
-                            let res = execute_concrete(
-                                cmd,
-                                robot.clone(),
-                                &mut bincode::Serializer::new(&mut c, bin),
-                            )
-                            .await?;
-                            if res.is_some() {
-                                socket.send(Message::Binary(v)).await?;
+                            Format::Bincode => {
+                                let mut v = Vec::new();
+                                let mut c = Cursor::new(&mut v);
+                                bincode::Options::serialize_into(
+                                    bin,
+                                    &mut c,
+                                    &(id, Ok::<_, cmd::Error>(())),
+                                )?;
+                                let mut ser = bincode::Serializer::new(&mut c, bin);
+                                let exec = execute_concrete(cmd, robot.clone(), &mut ser);
+                                CLIENT
+                                    .scope(client.clone(), exec)
+                                    .await
+                                    .map(|r| r.map(|_| Message::Binary(v)))
+                            }
+                            Format::Json => match CLIENT
+                                .scope(client.clone(), execute(cmd, robot.clone()))
+                                .await
+                            {
+                                Ok(Some(ret)) => serde_json::to_string(&json::Reply::ok(id, ret))
+                                    .map(|s| Some(Message::Text(s)))
+                                    .map_err(anyhow::Error::from),
+                                Ok(None) => Ok(None),
+                                Err(e) => Err(e),
+                            },
+                        };
+                        authenticated |= authenticates && res.is_ok();
+
+                        match res {
+                            Ok(Some(reply)) => socket.send(reply).await?,
+                            Ok(None) => (),
+                            Err(e) => {
+                                log::error!("ws: command {id} failed: {e}");
+                                let err = cmd::Error::new(cmd::ErrorCode::Execute, e);
+                                send_error(&mut socket, format, id, err).await?;
                             }
                         }
-                        _ => unreachable!(),
-                    },
+                    }
                 }
             }
             Action::Event(ev, (ev_addr, id)) => {
                 if addr != ev_addr {
                     continue;
                 }
-                let data: Vec<u8> = match ev {
-                    #[cfg(feature = "roland")]
-                    ConcreteValue::TrackSensor(v) => bincode::Options::serialize(bin, &(id, v))?,
-                    #[cfg(feature = "roland")]
-                    ConcreteValue::UltraSensor(v) => bincode::Options::serialize(bin, &(id, v))?,
-                    #[cfg(feature = "gpio")]
-                    ConcreteValue::GpioPin(v) => bincode::Options::serialize(bin, &(id, v))?,
-                    #[cfg(feature = "camloc")]
-                    ConcreteValue::CamlocConnect(v) => bincode::Options::serialize(bin, &(id, v))?,
-                    #[cfg(feature = "camloc")]
-                    ConcreteValue::CamlocDisconnect(v) => {
-                        bincode::Options::serialize(bin, &(id, v))?
+                if matches!(ev, ConcreteValue::None) {
+                    continue;
+                }
+                let msg = match subs.get(&id) {
+                    Some(Format::Json) => {
+                        Message::Text(serde_json::to_string(&json::Reply::ok(id, ev))?)
                     }
-                    #[cfg(feature = "camloc")]
-                    ConcreteValue::CamlocPosition(v) => bincode::Options::serialize(bin, &(id, v))?,
-                    #[cfg(feature = "camloc")]
-                    ConcreteValue::CamlocInfoUpdate(v) => {
-                        bincode::Options::serialize(bin, &(id, v))?
+                    Some(Format::Text) => {
+                        Message::Text(text_format::ser::to_string(&(id, Ok::<_, cmd::Error>(ev)))?)
                     }
-                    ConcreteValue::None => continue,
+                    _ => Message::Binary(bincode::Options::serialize(
+                        bin,
+                        &(id, Ok::<_, cmd::Error>(ev)),
+                    )?),
                 };
-                socket.send(Message::Binary(data)).await?;
+                socket.send(msg).await?;
             }
 
             Action::Disconnect => {
@@ -161,3 +268,22 @@ async fn ws_handler(
         }
     }
 }
+
+/// reply with an error, in the same format the client used for the command
+async fn send_error(
+    socket: &mut WebSocket,
+    format: Format,
+    id: u32,
+    err: cmd::Error,
+) -> anyhow::Result<()> {
+    let reply = match format {
+        Format::Text => Message::Text(text_format::ser::to_string(&(id, Err::<(), _>(err)))?),
+        Format::Json => Message::Text(serde_json::to_string(&json::Reply::<()>::err(id, err))?),
+        Format::Bincode => Message::Binary(bincode::Options::serialize(
+            bincode::options(),
+            &(id, Err::<(), _>(err)),
+        )?),
+    };
+    socket.send(reply).await?;
+    Ok(())
+}
@@ -0,0 +1,192 @@
+use crate::{config, control, Backends};
+use roblib::cmd::Concrete;
+use std::{
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+use tokio::sync::Notify;
+
+/// Stops the robot when the client that last moved it goes quiet, see [`roblib::cmd::Heartbeat`]
+pub(crate) struct Watchdog {
+    timeout: Option<Duration>,
+    armed: Mutex<Option<Armed>>,
+    /// Wakes up [`Watchdog::run`] when it's armed
+    notify: Notify,
+}
+
+struct Armed {
+    client: String,
+    last: Instant,
+}
+
+impl Watchdog {
+    pub fn new(config: &config::Safety) -> Self {
+        Self {
+            timeout: (config.watchdog_ms != 0).then(|| Duration::from_millis(config.watchdog_ms)),
+            armed: Default::default(),
+            notify: Notify::new(),
+        }
+    }
+
+    /// Called for every command, arms the watchdog for the client if `cmd` starts the robot,
+    /// the same commands the clients send heartbeats after, see [`Concrete::drives`].
+    /// Feeds it if the client is the one it's armed for, and disarms it if `cmd` stops the robot.
+    /// Commands that don't come from a client, like the scheduled ones, don't count.
+    pub fn feed(&self, cmd: &Concrete) {
+        if self.timeout.is_none() {
+            return;
+        }
+        let Ok(client) = control::client() else {
+            return;
+        };
+
+        let mut armed = self.armed.lock().unwrap();
+        match (cmd.drives(), &mut *armed) {
+            (Some(false), _) => *armed = None,
+            (_, Some(a)) if a.client == client => a.last = Instant::now(),
+            (Some(true), _) => {
+                *armed = Some(Armed {
+                    client,
+                    last: Instant::now(),
+                });
+                self.notify.notify_one();
+            }
+            (None, _) => (),
+        }
+    }
+
+    /// Disarms the watchdog if it ran out, returning the client it was armed for
+    fn expired(&self, timeout: Duration) -> Option<String> {
+        let mut armed = self.armed.lock().unwrap();
+        match &*armed {
+            Some(a) if a.last.elapsed() >= timeout => armed.take().map(|a| a.client),
+            _ => None,
+        }
+    }
+
+    /// Stops the robot whenever the watchdog runs out, until the server is aborted
+    pub async fn run(&self, robot: &Backends) {
+        let Some(timeout) = self.timeout else {
+            return;
+        };
+
+        loop {
+            let last = self.armed.lock().unwrap().as_ref().map(|a| a.last);
+            let sleep = async {
+                match last {
+                    Some(last) => tokio::time::sleep_until((last + timeout).into()).await,
+                    None => std::future::pending().await,
+                }
+            };
+
+            tokio::select! {
+                _ = robot.abort_token.cancelled() => return,
+                _ = self.notify.notified() => continue,
+                _ = sleep => (),
+            }
+
+            // the client might have been heard from since
+            if let Some(client) = self.expired(timeout) {
+                log::warn!("Nothing from {client} in {timeout:?}, stopping the robot");
+                stop(robot);
+            }
+        }
+    }
+}
+
+fn stop(robot: &Backends) {
+    #[cfg(all(feature = "roland", any(feature = "backend", feature = "sim")))]
+    if let Some(r) = &robot.roland {
+        use roblib::roland::Roland;
+        if let Err(e) = r.cleanup() {
+            log::error!("Watchdog failed to stop the robot: {e}");
+        }
+    }
+
+    #[cfg(not(all(feature = "roland", any(feature = "backend", feature = "sim"))))]
+    let _ = robot;
+}
+
+#[cfg(all(test, feature = "roland"))]
+mod tests {
+    use super::*;
+    use roblib::cmd::{Heartbeat, Led, MoveRobot, StopRobot};
+
+    const TIMEOUT: Duration = Duration::from_millis(50);
+
+    fn watchdog() -> Watchdog {
+        Watchdog::new(&config::Safety {
+            max_speed: 1.,
+            allow_abort: true,
+            watchdog_ms: TIMEOUT.as_millis() as u64,
+        })
+    }
+
+    fn feed(w: &Watchdog, client: &str, cmd: impl Into<Concrete>) {
+        control::CLIENT.sync_scope(client.into(), || w.feed(&cmd.into()));
+    }
+
+    fn armed_for(w: &Watchdog) -> Option<String> {
+        w.armed.lock().unwrap().as_ref().map(|a| a.client.clone())
+    }
+
+    #[test]
+    fn arms_only_on_driving() {
+        let w = watchdog();
+
+        feed(&w, "tcp a", Led(true, false, false));
+        feed(&w, "tcp a", MoveRobot(0., 0.));
+        assert_eq!(armed_for(&w), None);
+
+        // not from a client
+        w.feed(&MoveRobot(0.5, 0.5).into());
+        assert_eq!(armed_for(&w), None);
+
+        feed(&w, "tcp a", MoveRobot(0.5, 0.5));
+        assert_eq!(armed_for(&w).as_deref(), Some("tcp a"));
+    }
+
+    #[test]
+    fn fed_by_the_driver() {
+        let w = watchdog();
+        feed(&w, "tcp a", MoveRobot(0.5, 0.5));
+
+        std::thread::sleep(TIMEOUT / 2);
+        feed(&w, "tcp b", Heartbeat);
+        feed(&w, "tcp a", Heartbeat);
+        std::thread::sleep(TIMEOUT / 2);
+        assert_eq!(w.expired(TIMEOUT), None);
+
+        std::thread::sleep(TIMEOUT);
+        assert_eq!(w.expired(TIMEOUT).as_deref(), Some("tcp a"));
+        // disarmed until the robot is driven again
+        assert_eq!(armed_for(&w), None);
+        assert_eq!(w.expired(TIMEOUT), None);
+    }
+
+    #[test]
+    fn disarmed_by_stopping() {
+        let w = watchdog();
+        feed(&w, "tcp a", MoveRobot(0.5, 0.5));
+        feed(&w, "tcp b", StopRobot);
+        assert_eq!(armed_for(&w), None);
+
+        feed(&w, "tcp a", MoveRobot(0.5, 0.5));
+        feed(&w, "tcp a", MoveRobot(0., 0.));
+        assert_eq!(armed_for(&w), None);
+
+        std::thread::sleep(TIMEOUT);
+        assert_eq!(w.expired(TIMEOUT), None);
+    }
+
+    #[test]
+    fn off_without_timeout() {
+        let w = Watchdog::new(&config::Safety {
+            max_speed: 1.,
+            allow_abort: true,
+            watchdog_ms: 0,
+        });
+        feed(&w, "tcp a", MoveRobot(0.5, 0.5));
+        assert_eq!(armed_for(&w), None);
+    }
+}
@@ -2,7 +2,7 @@
 set -e
 
 ./check_all.py -d roblib default all async gpio roland camloc gpio-backend
-./check_all.py -d roblib-server default all backend roland gpio camloc
+./check_all.py -d roblib-server default all backend roland gpio camloc sim
 ./check_all.py -d roblib-client default all async roland gpio camloc tcp udp http ws
 ./check_all.py -de roblib-client default all async roland gpio camloc tcp udp http ws
 
@@ -31,4 +31,8 @@ cargo test --all-features -p roblib-server 2>&1
 cargo test --all-features -p roblib-client 2>&1
 [ -n "$GITHUB_ACTIONS" ] && echo "##[endgroup]"
 
+[ -n "$GITHUB_ACTIONS" ] && echo "##[group]test: roblib-ts-gen"
+cargo test -p roblib-ts-gen -- --include-ignored 2>&1
+[ -n "$GITHUB_ACTIONS" ] && echo "##[endgroup]"
+
 exit 0
@@ -0,0 +1,600 @@
+//! Generates javascript bindings for the text and json formats, with typescript declarations,
+//! from the [`Schema`] of the commands and events compiled into roblib.
+//!
+//! The encoding itself is done by a small runtime, driven by the schema embedded in the generated module.
+//! The generated parts are the types, and a constructor for every command and event.
+
+use roblib::schema::{Def, Field, Schema, Type, TypeDef};
+use std::fmt::Write;
+
+const RUNTIME_JS: &str = include_str!("runtime.js");
+const RUNTIME_DTS: &str = include_str!("runtime.d.ts");
+
+pub struct Bindings {
+    /// `roblib.js`
+    pub js: String,
+    /// `roblib.d.ts`
+    pub dts: String,
+}
+
+pub fn generate(schema: &Schema) -> anyhow::Result<Bindings> {
+    Ok(Bindings {
+        js: js(schema)?,
+        dts: dts(schema)?,
+    })
+}
+
+const HEADER: &str = "// Generated by roblib-ts-gen, do not edit.\n\n";
+
+/// `move_robot` -> `moveRobot`
+fn camel_case(tag: &str) -> String {
+    let mut s = String::new();
+    let mut upper = false;
+    for c in tag.chars() {
+        if c == '_' {
+            upper = true;
+        } else if upper {
+            s.extend(c.to_uppercase());
+            upper = false;
+        } else {
+            s.push(c);
+        }
+    }
+    s
+}
+
+/// The parameters of a constructor, named after the fields if they have names
+fn params(fields: &[Field]) -> Vec<String> {
+    fields
+        .iter()
+        .enumerate()
+        .map(|(i, f)| f.name.clone().unwrap_or_else(|| format!("arg{i}")))
+        .collect()
+}
+
+/// A struct's or variant's fields are `null`, the only field's value, an array, or an object,
+/// the same as serde_json does it
+fn shape(fields: &[Field], value: impl Fn(usize, &Field) -> String, object: bool) -> String {
+    let values: Vec<_> = fields
+        .iter()
+        .enumerate()
+        .map(|(i, f)| value(i, f))
+        .collect();
+    match fields {
+        [] => "null".into(),
+        [Field { name: Some(_), .. }, ..] => {
+            let sep = if object { ", " } else { "; " };
+            let entries: Vec<_> = fields
+                .iter()
+                .zip(&values)
+                .map(|(f, v)| format!("{}: {v}", f.name.as_deref().unwrap_or_default()))
+                .collect();
+            format!("{{ {} }}", entries.join(sep))
+        }
+        [_] => values.into_iter().next().unwrap(),
+        _ => format!("[{}]", values.join(", ")),
+    }
+}
+
+fn ts_type(ty: &Type) -> String {
+    match ty {
+        Type::Unit => "null".into(),
+        Type::Bool => "boolean".into(),
+        Type::U8
+        | Type::U16
+        | Type::U32
+        | Type::U64
+        | Type::I8
+        | Type::I16
+        | Type::I32
+        | Type::I64
+        | Type::F32
+        | Type::F64 => "number".into(),
+        Type::Char | Type::String | Type::SocketAddr => "string".into(),
+        Type::Option(t) => format!("{} | null", ts_type(t)),
+        Type::Seq(t) => format!("Array<{}>", ts_type(t)),
+        Type::Map(k, v) => format!("Record<{}, {}>", ts_type(k), ts_type(v)),
+        Type::Tuple(ts) => {
+            let ts: Vec<_> = ts.iter().map(ts_type).collect();
+            format!("[{}]", ts.join(", "))
+        }
+        Type::Named(name) => name.clone(),
+        Type::Command => "Command".into(),
+        Type::Event => "Event".into(),
+        Type::Dynamic => "unknown".into(),
+    }
+}
+
+fn ts_fields(fields: &[Field]) -> String {
+    shape(fields, |_, f| ts_type(&f.ty), false)
+}
+
+fn ts_def(TypeDef { name, def }: &TypeDef) -> String {
+    match def {
+        Def::Struct(fields) if fields.first().is_some_and(|f| f.name.is_some()) => {
+            format!("export interface {name} {}\n", ts_fields(fields))
+        }
+        Def::Struct(fields) => format!("export type {name} = {};\n", ts_fields(fields)),
+        Def::Enum(variants) => {
+            let variants: Vec<_> = variants
+                .iter()
+                .map(|v| match &v.fields[..] {
+                    [] => format!("\"{}\"", v.name),
+                    fields => format!("{{ {}: {} }}", v.name, ts_fields(fields)),
+                })
+                .collect();
+            format!("export type {name} = {};\n", variants.join(" | "))
+        }
+    }
+}
+
+/// `/** \`m\`, needs the roland feature */`
+fn doc(what: &str, feature: &Option<String>) -> String {
+    match feature {
+        Some(f) => format!("/** {what}, needs the `{f}` feature */"),
+        None => format!("/** {what} */"),
+    }
+}
+
+fn js(schema: &Schema) -> anyhow::Result<String> {
+    let mut s = String::from(HEADER);
+
+    writeln!(s, "const SCHEMA = {};\n", serde_json::to_string(schema)?)?;
+    s.push_str(RUNTIME_JS);
+
+    writeln!(s, "\nexport const cmd = {{")?;
+    for c in &schema.commands {
+        let params = params(&c.args);
+        let args = shape(&c.args, |i, _| params[i].clone(), true);
+        writeln!(
+            s,
+            "\t{}: ({}) => ({{ cmd: \"{}\", args: {args} }}),",
+            camel_case(&c.tag),
+            params.join(", "),
+            c.tag,
+        )?;
+    }
+    writeln!(s, "}};")?;
+
+    writeln!(s, "\nexport const event = {{")?;
+    for e in &schema.events {
+        let params = params(&e.args);
+        let args = shape(&e.args, |i, _| params[i].clone(), true);
+        writeln!(
+            s,
+            "\t{}: ({}) => ({{ event: \"{}\", args: {args} }}),",
+            camel_case(&e.tag),
+            params.join(", "),
+            e.tag,
+        )?;
+    }
+    writeln!(s, "}};")?;
+
+    Ok(s)
+}
+
+fn dts(schema: &Schema) -> anyhow::Result<String> {
+    let mut s = String::from(HEADER);
+
+    for t in &schema.types {
+        s.push_str(&ts_def(t));
+    }
+
+    let commands: Vec<_> = schema
+        .commands
+        .iter()
+        .map(|c| format!("\t| {{ cmd: \"{}\"; args: {} }}", c.tag, ts_fields(&c.args)))
+        .collect();
+    writeln!(s, "\nexport type Command =\n{};", commands.join("\n"))?;
+    writeln!(s, "\nexport interface Returns {{")?;
+    for c in &schema.commands {
+        writeln!(s, "\t{}: {};", c.tag, ts_type(&c.returns))?;
+    }
+    writeln!(s, "}}")?;
+
+    let events: Vec<_> = schema
+        .events
+        .iter()
+        .map(|e| {
+            format!(
+                "\t| {{ event: \"{}\"; args: {} }}",
+                e.tag,
+                ts_fields(&e.args)
+            )
+        })
+        .collect();
+    writeln!(s, "\nexport type Event =\n{};", events.join("\n"))?;
+    writeln!(s, "\nexport interface Items {{")?;
+    for e in &schema.events {
+        writeln!(s, "\t{}: {};", e.tag, ts_type(&e.item))?;
+    }
+    writeln!(s, "}}")?;
+
+    writeln!(s, "\nexport declare const cmd: {{")?;
+    for c in &schema.commands {
+        let params: Vec<_> = params(&c.args)
+            .into_iter()
+            .zip(&c.args)
+            .map(|(p, f)| format!("{p}: {}", ts_type(&f.ty)))
+            .collect();
+        writeln!(s, "\t{}", doc(&format!("`{}`", c.prefix), &c.feature))?;
+        writeln!(
+            s,
+            "\t{}({}): Cmd<\"{}\">;",
+            camel_case(&c.tag),
+            params.join(", "),
+            c.tag
+        )?;
+    }
+    writeln!(s, "}};")?;
+
+    writeln!(s, "\nexport declare const event: {{")?;
+    for e in &schema.events {
+        let params: Vec<_> = params(&e.args)
+            .into_iter()
+            .zip(&e.args)
+            .map(|(p, f)| format!("{p}: {}", ts_type(&f.ty)))
+            .collect();
+        writeln!(s, "\t{}", doc(&format!("`{}`", e.name), &e.feature))?;
+        writeln!(
+            s,
+            "\t{}({}): Ev<\"{}\">;",
+            camel_case(&e.tag),
+            params.join(", "),
+            e.tag
+        )?;
+    }
+    writeln!(s, "}};\n")?;
+
+    s.push_str(RUNTIME_DTS);
+    Ok(s)
+}
+
+#[cfg(test)]
+mod tests {
+    use roblib::{
+        camloc::{PlacedCamera, Position},
+        cmd::{self, Concrete},
+        event::{self, ConcreteType},
+        gpio::Mode,
+        json, text_format,
+    };
+    use serde::{de::DeserializeOwned, Serialize};
+    use serde_json::{json, Value};
+    use std::{fmt::Debug, fs, net::SocketAddr, process::Command, time::Duration};
+
+    const DRIVER: &str = r#"
+import * as roblib from "./roblib.js";
+import { readFileSync } from "node:fs";
+
+const cases = JSON.parse(readFileSync(process.argv[2], "utf8"));
+console.log(JSON.stringify({
+	text: cases.cmds.map((c) => roblib.encodeText(c, 7)),
+	json: cases.cmds.map((c) => roblib.encodeJson(c, 7)),
+	decoded: cases.texts.map((t) => roblib.decodeText(t, true)),
+	replies: cases.replies.map(([c, line]) => roblib.decodeReplyText(c, line)),
+	events: cases.events.map(([e, line]) => roblib.decodeEventText(e, line)),
+	ctors: [
+		roblib.cmd.moveRobot(0.5, -1.25),
+		roblib.cmd.nop(),
+		roblib.cmd.pinMode(4, "Output"),
+		roblib.subscribe(roblib.event.gpioPin(4)),
+		roblib.batch(roblib.cmd.readPin(1), roblib.ack(roblib.cmd.writePin(2, true))),
+	],
+}));
+"#;
+
+    /// A command of every kind, with every kind of argument
+    fn cmds() -> Vec<Concrete> {
+        vec![
+            Concrete::MoveRobot(cmd::MoveRobot(0.5, -1.25)),
+            Concrete::MoveRobotByAngle(cmd::MoveRobotByAngle(90., 0.5)),
+            Concrete::StopRobot(cmd::StopRobot),
+            Concrete::Led(cmd::Led(true, false, true)),
+            Concrete::RolandServo(cmd::RolandServo(-45.5)),
+            Concrete::Buzzer(cmd::Buzzer(440.)),
+            Concrete::TrackSensor(cmd::TrackSensor),
+            Concrete::UltraSensor(cmd::UltraSensor),
+            Concrete::PinMode(cmd::PinMode(4, Mode::Output)),
+            Concrete::ReadPin(cmd::ReadPin(3)),
+            Concrete::WritePin(cmd::WritePin(2, true)),
+            Concrete::Pwm(cmd::Pwm(5, 50., 12.5)),
+            Concrete::Servo(cmd::Servo(6, 30.)),
+            Concrete::GetPosition(cmd::GetPosition),
+            Concrete::Subscribe(cmd::Subscribe(event::GpioPin(4).into())),
+            Concrete::Subscribe(cmd::Subscribe(
+                event::UltraSensor(Duration::from_millis(250)).into(),
+            )),
+            Concrete::Subscribe(cmd::Subscribe(
+                event::Custom("my event".into(), vec![1, 2]).into(),
+            )),
+            Concrete::Unsubscribe(cmd::Unsubscribe(event::CamlocPosition.into())),
+            Concrete::Nop(cmd::Nop),
+            Concrete::GetUptime(cmd::GetUptime),
+            Concrete::GetCapabilities(cmd::GetCapabilities),
+            Concrete::GetSchema(cmd::GetSchema),
+            Concrete::Abort(cmd::Abort),
+            Concrete::Batch(cmd::Batch::new(vec![
+                Concrete::Nop(cmd::Nop),
+                Concrete::ReadPin(cmd::ReadPin(1)),
+            ])),
+            Concrete::Ack(cmd::Ack::new(cmd::WritePin(1, false))),
+            Concrete::Schedule(cmd::Schedule::new(
+                cmd::When::After(Duration::from_millis(1500)),
+                cmd::MoveRobot(1., 1.),
+            )),
+            Concrete::ListScheduled(cmd::ListScheduled),
+            Concrete::CancelScheduled(cmd::CancelScheduled(3)),
+            Concrete::Custom(cmd::Custom("with spaces in it".into(), vec![0, 255])),
+        ]
+    }
+
+    /// What the generated `encodeText` makes of [`cmds`] with the id 7, checked by [`node_roundtrip`]
+    const TEXT: &[&str] = &[
+        "7 m 0.5 -1.25",
+        "7 M 90 0.5",
+        "7 s",
+        "7 l 1 0 1",
+        "7 a -45.5",
+        "7 b 440",
+        "7 t",
+        "7 u",
+        "7 p 4 1",
+        "7 r 3",
+        "7 w 2 1",
+        "7 W 5 50 12.5",
+        "7 V 6 30",
+        "7 P",
+        "7 + 0 pin 4",
+        "7 + 0 ultra_sensor 0 250000000",
+        "7 + 0 custom 1 my event 2 1 2",
+        "7 - 0 position",
+        "7 0",
+        "7 U",
+        "7 C",
+        "7 S",
+        "7 X",
+        "7 B 2 0 r 1",
+        "7 A w 1 0",
+        "7 T 0 1 500000000 m 1 1",
+        "7 Q",
+        "7 K 3",
+        "7 $ 3 with spaces in it 2 0 255",
+    ];
+
+    /// Same as [`TEXT`], for `encodeJson`
+    const JSON: &[&str] = &[
+        r#"{"id":7,"cmd":"move_robot","args":[0.5,-1.25]}"#,
+        r#"{"id":7,"cmd":"move_robot_by_angle","args":[90,0.5]}"#,
+        r#"{"id":7,"cmd":"stop_robot","args":null}"#,
+        r#"{"id":7,"cmd":"led","args":[true,false,true]}"#,
+        r#"{"id":7,"cmd":"roland_servo","args":-45.5}"#,
+        r#"{"id":7,"cmd":"buzzer","args":440}"#,
+        r#"{"id":7,"cmd":"track_sensor","args":null}"#,
+        r#"{"id":7,"cmd":"ultra_sensor","args":null}"#,
+        r#"{"id":7,"cmd":"pin_mode","args":[4,"Output"]}"#,
+        r#"{"id":7,"cmd":"read_pin","args":3}"#,
+        r#"{"id":7,"cmd":"write_pin","args":[2,true]}"#,
+        r#"{"id":7,"cmd":"pwm","args":[5,50,12.5]}"#,
+        r#"{"id":7,"cmd":"servo","args":[6,30]}"#,
+        r#"{"id":7,"cmd":"get_position","args":null}"#,
+        r#"{"id":7,"cmd":"subscribe","args":{"args":4,"event":"gpio_pin"}}"#,
+        r#"{"id":7,"cmd":"subscribe","args":{"args":{"nanos":250000000,"secs":0},"event":"ultra_sensor"}}"#,
+        r#"{"id":7,"cmd":"subscribe","args":{"args":["my event",[1,2]],"event":"custom"}}"#,
+        r#"{"id":7,"cmd":"unsubscribe","args":{"args":null,"event":"camloc_position"}}"#,
+        r#"{"id":7,"cmd":"nop","args":null}"#,
+        r#"{"id":7,"cmd":"get_uptime","args":null}"#,
+        r#"{"id":7,"cmd":"get_capabilities","args":null}"#,
+        r#"{"id":7,"cmd":"get_schema","args":null}"#,
+        r#"{"id":7,"cmd":"abort","args":null}"#,
+        r#"{"id":7,"cmd":"batch","args":[{"args":null,"cmd":"nop"},{"args":1,"cmd":"read_pin"}]}"#,
+        r#"{"id":7,"cmd":"ack","args":{"args":[1,false],"cmd":"write_pin"}}"#,
+        r#"{"id":7,"cmd":"schedule","args":[{"After":{"nanos":500000000,"secs":1}},{"args":[1,1],"cmd":"move_robot"}]}"#,
+        r#"{"id":7,"cmd":"list_scheduled","args":null}"#,
+        r#"{"id":7,"cmd":"cancel_scheduled","args":3}"#,
+        r#"{"id":7,"cmd":"custom","args":["with spaces in it",[0,255]]}"#,
+    ];
+
+    type Check = Box<dyn Fn(Value) -> String>;
+
+    /// The reply `line` to `c`, and how to check the decoded json against `ret`
+    fn reply<T: Serialize + DeserializeOwned + Debug + 'static>(
+        c: Concrete,
+        ret: T,
+    ) -> (Value, String, String, Check) {
+        let line = text_format::ser::to_string(&(3u32, Ok::<_, cmd::Error>(&ret))).unwrap();
+        let check: Check = Box::new(|v| {
+            let reply: json::Reply<T> = serde_json::from_value(v).unwrap();
+            format!("{:?}", reply.into_result())
+        });
+        let c = serde_json::to_value(&c).unwrap();
+        (c, line, format!("{:?}", Ok::<_, cmd::Error>(ret)), check)
+    }
+
+    fn item<T: Serialize + DeserializeOwned + Debug + 'static>(
+        e: ConcreteType,
+        item: T,
+    ) -> (Value, String, String, Check) {
+        let line = text_format::ser::to_string(&(3u32, Ok::<_, cmd::Error>(&item))).unwrap();
+        let check: Check = Box::new(|v| {
+            let reply: json::Reply<T> = serde_json::from_value(v).unwrap();
+            format!("{:?}", reply.into_result())
+        });
+        let e = serde_json::to_value(&e).unwrap();
+        (e, line, format!("{:?}", Ok::<_, cmd::Error>(item)), check)
+    }
+
+    /// The encoders' output is read by the server as the same command, without needing node
+    #[test]
+    fn golden_encodings() {
+        let cmds = cmds();
+        assert_eq!(cmds.len(), TEXT.len());
+        assert_eq!(cmds.len(), JSON.len());
+
+        for (i, c) in cmds.iter().enumerate() {
+            let (id, decoded): (u32, Concrete) = text_format::de::from_str(TEXT[i]).unwrap();
+            assert_eq!(id, 7);
+            assert_eq!(format!("{decoded:?}"), format!("{c:?}"), "{}", TEXT[i]);
+
+            let req: json::Request = serde_json::from_str(JSON[i]).unwrap();
+            assert_eq!(req.id, 7);
+            assert_eq!(format!("{:?}", req.cmd), format!("{c:?}"), "{}", JSON[i]);
+        }
+    }
+
+    #[test]
+    #[ignore = "needs node, run with --include-ignored"]
+    fn node_roundtrip() {
+        let dir = std::env::temp_dir().join(format!("roblib-ts-gen-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let bindings = super::generate(&roblib::schema::export()).unwrap();
+        fs::write(dir.join("roblib.js"), bindings.js).unwrap();
+        fs::write(dir.join("driver.mjs"), DRIVER).unwrap();
+
+        let pos = Position::new(1.5, -2., 0.25);
+        let addr: SocketAddr = "192.168.1.20:1110".parse().unwrap();
+        let addr6: SocketAddr = "[fe80::1:2]:5000".parse().unwrap();
+
+        let cmds = cmds();
+        let texts: Vec<_> = cmds
+            .iter()
+            .map(|c| text_format::ser::to_string(&(7u32, c)).unwrap())
+            .collect();
+
+        let replies = vec![
+            reply(Concrete::ReadPin(cmd::ReadPin(1)), true),
+            reply(Concrete::Nop(cmd::Nop), ()),
+            reply(
+                Concrete::TrackSensor(cmd::TrackSensor),
+                [true, false, true, true],
+            ),
+            reply(Concrete::UltraSensor(cmd::UltraSensor), 0.125),
+            reply(Concrete::GetUptime(cmd::GetUptime), Duration::new(12, 345)),
+            reply(
+                Concrete::GetCapabilities(cmd::GetCapabilities),
+                cmd::Capabilities {
+                    version: cmd::PROTOCOL_VERSION,
+                    features: vec!["gpio".into(), "roland".into()],
+                    backends: vec!["gpio".into()],
+                    transports: vec!["http".into()],
+                    commands: Concrete::PREFIXES.to_vec(),
+                    events: ConcreteType::NAMES.iter().map(|e| e.to_string()).collect(),
+                },
+            ),
+            reply(
+                Concrete::GetSchema(cmd::GetSchema),
+                roblib::schema::export(),
+            ),
+            reply(Concrete::GetPosition(cmd::GetPosition), Some(pos)),
+            reply(Concrete::GetPosition(cmd::GetPosition), None::<Position>),
+            reply(
+                Concrete::ListScheduled(cmd::ListScheduled),
+                vec![cmd::Scheduled {
+                    id: 2,
+                    at: Duration::from_millis(1500),
+                    prefix: 'm',
+                }],
+            ),
+            reply(
+                Concrete::Schedule(cmd::Schedule::new(
+                    cmd::When::At(Duration::from_secs(60)),
+                    cmd::Nop,
+                )),
+                5u32,
+            ),
+            reply(Concrete::Ack(cmd::Ack::new(cmd::ReadPin(1))), false),
+            reply(
+                Concrete::Batch(cmd::Batch::new(vec![
+                    Concrete::Nop(cmd::Nop),
+                    Concrete::ReadPin(cmd::ReadPin(1)),
+                    Concrete::GetUptime(cmd::GetUptime),
+                ])),
+                ((), true, Duration::from_secs(3)),
+            ),
+        ];
+        let mut error = reply(Concrete::ReadPin(cmd::ReadPin(1)), false);
+        error.1 = text_format::ser::to_string(&(
+            3u32,
+            Err::<(), _>(cmd::Error::new(cmd::ErrorCode::Execute, "invalid pin 42")),
+        ))
+        .unwrap();
+        error.2 = format!(
+            "{:?}",
+            Err::<bool, _>(cmd::Error::new(cmd::ErrorCode::Execute, "invalid pin 42"))
+        );
+        let replies: Vec<_> = replies.into_iter().chain([error]).collect();
+
+        let events = vec![
+            item(event::GpioPin(4).into(), true),
+            item(event::TrackSensor.into(), [false, false, true, false]),
+            item(event::UltraSensor(Duration::from_millis(250)).into(), 0.5),
+            item(event::CamlocPosition.into(), pos),
+            item(event::CamlocDisconnect.into(), addr6),
+            item(
+                event::CamlocConnect.into(),
+                (addr, PlacedCamera::new(pos, 1.2)),
+            ),
+        ];
+
+        let input = json!({
+            "cmds": cmds,
+            "texts": texts,
+            "replies": replies.iter().map(|r| (&r.0, &r.1)).collect::<Vec<_>>(),
+            "events": events.iter().map(|e| (&e.0, &e.1)).collect::<Vec<_>>(),
+        });
+        fs::write(dir.join("cases.json"), input.to_string()).unwrap();
+
+        let out = Command::new("node")
+            .current_dir(&dir)
+            .args(["driver.mjs", "cases.json"])
+            .output()
+            .unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        assert!(
+            out.status.success(),
+            "{}",
+            String::from_utf8_lossy(&out.stderr)
+        );
+        let out: Value = serde_json::from_slice(&out.stdout).unwrap();
+
+        for (i, c) in cmds.iter().enumerate() {
+            let text = out["text"][i].as_str().unwrap();
+            assert_eq!(text, texts[i], "{c:?}");
+            assert_eq!(text, TEXT[i], "{c:?}");
+            assert_eq!(out["json"][i].as_str().unwrap(), JSON[i], "{c:?}");
+
+            let json: json::Request =
+                serde_json::from_str(out["json"][i].as_str().unwrap()).unwrap();
+            assert_eq!(json.id, 7);
+            assert_eq!(format!("{:?}", json.cmd), format!("{c:?}"));
+
+            let decoded: json::Request = serde_json::from_value(out["decoded"][i].clone()).unwrap();
+            assert_eq!(decoded.id, 7);
+            assert_eq!(format!("{:?}", decoded.cmd), format!("{c:?}"));
+        }
+
+        for (i, (c, line, expected, check)) in replies.into_iter().enumerate() {
+            let got = check(out["replies"][i].clone());
+            assert_eq!(got, expected, "{c} {line}");
+        }
+        for (i, (e, line, expected, check)) in events.into_iter().enumerate() {
+            let got = check(out["events"][i].clone());
+            assert_eq!(got, expected, "{e} {line}");
+        }
+
+        let ctors = [
+            Concrete::MoveRobot(cmd::MoveRobot(0.5, -1.25)),
+            Concrete::Nop(cmd::Nop),
+            Concrete::PinMode(cmd::PinMode(4, Mode::Output)),
+            Concrete::Subscribe(cmd::Subscribe(event::GpioPin(4).into())),
+            Concrete::Batch(cmd::Batch::new(vec![
+                Concrete::ReadPin(cmd::ReadPin(1)),
+                Concrete::Ack(cmd::Ack::new(cmd::WritePin(2, true))),
+            ])),
+        ];
+        for (i, c) in ctors.iter().enumerate() {
+            let got: Concrete = serde_json::from_value(out["ctors"][i].clone()).unwrap();
+            assert_eq!(format!("{got:?}"), format!("{c:?}"));
+        }
+    }
+}
@@ -0,0 +1,14 @@
+use std::{fs, path::PathBuf};
+
+/// Writes `roblib.js` and `roblib.d.ts` to the given directory, the current one by default
+fn main() -> anyhow::Result<()> {
+    let dir = PathBuf::from(std::env::args().nth(1).unwrap_or_else(|| ".".into()));
+    fs::create_dir_all(&dir)?;
+
+    let bindings = roblib_ts_gen::generate(&roblib::schema::export())?;
+    fs::write(dir.join("roblib.js"), bindings.js)?;
+    fs::write(dir.join("roblib.d.ts"), bindings.dts)?;
+
+    println!("Generated bindings in {}", dir.display());
+    Ok(())
+}
@@ -0,0 +1,49 @@
+export declare const PROTOCOL_VERSION: number;
+/** The websocket subprotocol of the json format */
+export declare const JSON_PROTOCOL: "json";
+
+export type Cmd<T extends Command["cmd"]> = Extract<Command, { cmd: T }>;
+export type Ev<T extends Event["event"]> = Extract<Event, { event: T }>;
+
+/** Acks and batches return whatever the commands in them do */
+export type Return<C extends Command> = C extends { cmd: "ack"; args: infer I extends Command }
+	? Return<I>
+	: Returns[C["cmd"]];
+
+export type Reply<T> = { id: number; ok: T } | { id: number; err: Error };
+
+/** Whether the server replies to `c` when it succeeds */
+export declare function replies(c: Command): boolean;
+
+/** `id` is left out for http */
+export declare function encodeText(c: Command, id?: number): string;
+/** Decodes a text command, with its id if `withId` is set */
+export declare function decodeText(text: string): Command;
+export declare function decodeText(text: string, withId: true): Command & { id: number };
+/** Decodes the body of an http reply to `c` */
+export declare function decodeReturnText<C extends Command>(c: C, body: string): Return<C>;
+/** Decodes a websocket reply to `c` */
+export declare function decodeReplyText<C extends Command>(c: C, line: string): Reply<Return<C>>;
+/** Decodes an item of an event `e` was subscribed to */
+export declare function decodeEventText<E extends Event>(e: E, line: string): Reply<Items[E["event"]]>;
+
+export declare function encodeJson(c: Command, id?: number): string;
+/** Replies, events and http bodies are plain json */
+export declare function decodeJson<T = unknown>(text: string): T;
+
+export declare function replyId(line: string): number;
+
+export declare function subscribe(e: Event): Cmd<"subscribe">;
+export declare function unsubscribe(e: Event): Cmd<"unsubscribe">;
+export declare function ack<C extends Command>(c: C): { cmd: "ack"; args: C };
+export declare function batch(...cmds: Command[]): Cmd<"batch">;
+
+/** A client for the server's `/ws` endpoint, in json or the text format */
+export declare class RoblibWs {
+	constructor(url: string, options?: { format?: "json" | "text"; WebSocket?: unknown });
+	/** Resolves with the return value, or `null` right away if the command doesn't reply */
+	cmd<C extends Command>(c: C): Promise<Return<C>>;
+	subscribe<E extends Event>(e: E, handler: (item: Items[E["event"]]) => void): Promise<void>;
+	unsubscribe(e: Event): Promise<void>;
+	close(): void;
+}
@@ -0,0 +1,500 @@
+// Everything below is driven by SCHEMA, the same encoding rules as roblib's text format and json.
+// Values are always in their json shape, the text format is only a different encoding of them.
+
+const COMMANDS = new Map(SCHEMA.commands.map((c) => [c.tag, c]));
+const PREFIXES = new Map(SCHEMA.commands.map((c) => [c.prefix, c]));
+const EVENTS = new Map(SCHEMA.events.map((e) => [e.tag, e]));
+const EVENT_NAMES = new Map(SCHEMA.events.map((e) => [e.name, e]));
+const TYPES = new Map(SCHEMA.types.map((t) => [t.name, t.def]));
+
+export const PROTOCOL_VERSION = SCHEMA.version;
+export const JSON_PROTOCOL = "json";
+
+class Reader {
+	constructor(text) {
+		this.tokens = text === "" ? [] : text.split(" ");
+		this.i = 0;
+	}
+
+	next() {
+		const t = this.tokens[this.i++];
+		if (t === undefined) throw new Error("Missing argument");
+		if (t === "") throw new Error("Empty argument");
+		return t;
+	}
+
+	// the parts of a string, which can be empty
+	part() {
+		return this.tokens[this.i++] ?? "";
+	}
+
+	int() {
+		const t = this.next();
+		if (!/^[+-]?\d+$/.test(t)) throw new Error(`Expected an integer, got '${t}'`);
+		return Number(t);
+	}
+
+	float() {
+		const t = this.next();
+		if (/^[+-]?inf(inity)?$/i.test(t)) return t.startsWith("-") ? -Infinity : Infinity;
+		const n = Number(t);
+		if (Number.isNaN(n) && t.toLowerCase() !== "nan") throw new Error(`Expected a number, got '${t}'`);
+		return n;
+	}
+
+	rest() {
+		const r = this.tokens.slice(this.i);
+		this.i = this.tokens.length;
+		return r;
+	}
+
+	end() {
+		if (this.i < this.tokens.length) throw new Error("Trailing characters");
+	}
+}
+
+// a struct's or variant's fields are null, the value of the only one, an array, or an object
+function fieldValues(fields, v) {
+	if (fields.length === 0) return [];
+	if (fields[0].name !== null) return fields.map((f) => v[f.name]);
+	if (fields.length === 1) return [v];
+	return v;
+}
+
+function fromFieldValues(fields, vals) {
+	if (fields.length === 0) return null;
+	if (fields[0].name !== null) return Object.fromEntries(fields.map((f, i) => [f.name, vals[i]]));
+	if (fields.length === 1) return vals[0];
+	return vals;
+}
+
+function encFields(fields, v, out) {
+	fieldValues(fields, v).forEach((x, i) => enc(fields[i].ty, x, out));
+}
+
+function decFields(fields, r) {
+	return fromFieldValues(
+		fields,
+		fields.map((f) => dec(f.ty, r)),
+	);
+}
+
+function encStr(s, out) {
+	out.push(String(s.split(" ").length - 1));
+	if (s !== "") out.push(s);
+}
+
+function decStr(r) {
+	const spaces = r.int();
+	const parts = [r.part()];
+	for (let i = 0; i < spaces; i++) parts.push(r.part());
+	return parts.join(" ");
+}
+
+function encAddr(addr, out) {
+	const v4 = /^(\d+)\.(\d+)\.(\d+)\.(\d+):(\d+)$/.exec(addr);
+	if (v4) {
+		out.push("0", ...v4.slice(1));
+		return;
+	}
+
+	const v6 = /^\[(.*)\]:(\d+)$/.exec(addr);
+	if (!v6) throw new Error(`Invalid socket address '${addr}'`);
+	const [head, tail = ""] = v6[1].split("::");
+	const groups = (s) => (s === "" ? [] : s.split(":").map((g) => parseInt(g, 16)));
+	const h = groups(head);
+	const t = groups(tail);
+	const all = [...h, ...Array(8 - h.length - t.length).fill(0), ...t];
+
+	out.push("1", ...all.flatMap((g) => [String(g >> 8), String(g & 0xff)]), v6[2]);
+}
+
+function decAddr(r) {
+	switch (r.int()) {
+		case 0: {
+			const ip = [r.int(), r.int(), r.int(), r.int()];
+			return `${ip.join(".")}:${r.int()}`;
+		}
+		case 1: {
+			const groups = [];
+			for (let i = 0; i < 8; i++) groups.push(((r.int() << 8) | r.int()).toString(16));
+			return `[${groups.join(":")}]:${r.int()}`;
+		}
+		default:
+			throw new Error("Invalid socket address");
+	}
+}
+
+function encCommand(c, out) {
+	const def = COMMANDS.get(c.cmd);
+	if (!def) throw new Error(`Unknown command '${c.cmd}'`);
+	out.push(def.prefix);
+	encFields(def.args, c.args, out);
+}
+
+function decCommand(r) {
+	const prefix = r.next();
+	const def = PREFIXES.get(prefix);
+	if (!def) throw new Error(`Unknown command prefix '${prefix}'`);
+	return { cmd: def.tag, args: decFields(def.args, r) };
+}
+
+function encEvent(e, out) {
+	const def = EVENTS.get(e.event);
+	if (!def) throw new Error(`Unknown event '${e.event}'`);
+	encStr(def.name, out);
+	encFields(def.args, e.args, out);
+}
+
+function decEvent(r) {
+	const name = decStr(r);
+	const def = EVENT_NAMES.get(name);
+	if (!def) throw new Error(`Unknown event '${name}'`);
+	return { event: def.tag, args: decFields(def.args, r) };
+}
+
+function encDef(def, v, out) {
+	if (def.struct) return encFields(def.struct, v, out);
+
+	const name = typeof v === "string" ? v : Object.keys(v)[0];
+	const i = def.enum.findIndex((variant) => variant.name === name);
+	if (i < 0) throw new Error(`Unknown variant '${name}'`);
+	out.push(String(i));
+	if (typeof v !== "string") encFields(def.enum[i].fields, v[name], out);
+}
+
+function decDef(def, r) {
+	if (def.struct) return decFields(def.struct, r);
+
+	const variant = def.enum[r.int()];
+	if (!variant) throw new Error("Invalid variant");
+	if (variant.fields.length === 0) return variant.name;
+	return { [variant.name]: decFields(variant.fields, r) };
+}
+
+function enc(ty, v, out) {
+	switch (ty) {
+		case "unit":
+			return;
+		case "bool":
+			out.push(v ? "1" : "0");
+			return;
+		case "u8":
+		case "u16":
+		case "u32":
+		case "u64":
+		case "i8":
+		case "i16":
+		case "i32":
+		case "i64":
+		case "f32":
+		case "f64":
+			out.push(String(v));
+			return;
+		case "char":
+			out.push(v);
+			return;
+		case "string":
+			encStr(v, out);
+			return;
+		case "socket_addr":
+			encAddr(v, out);
+			return;
+		case "command":
+			encCommand(v, out);
+			return;
+		case "event":
+			encEvent(v, out);
+			return;
+		case "dynamic":
+			throw new Error("Dynamic values can only be sent as json");
+	}
+
+	if ("option" in ty) {
+		if (v === null || v === undefined) {
+			out.push("0");
+		} else {
+			out.push("1");
+			enc(ty.option, v, out);
+		}
+	} else if ("seq" in ty) {
+		out.push(String(v.length));
+		for (const x of v) enc(ty.seq, x, out);
+	} else if ("map" in ty) {
+		const [k, t] = ty.map;
+		const entries = Object.entries(v);
+		out.push(String(entries.length));
+		for (const [key, x] of entries) {
+			// object keys are always strings
+			enc(k, k === "bool" ? key === "true" : key, out);
+			enc(t, x, out);
+		}
+	} else if ("tuple" in ty) {
+		ty.tuple.forEach((t, i) => enc(t, v[i], out));
+	} else if ("named" in ty) {
+		encDef(TYPES.get(ty.named), v, out);
+	}
+}
+
+function dec(ty, r) {
+	switch (ty) {
+		case "unit":
+			return null;
+		case "bool": {
+			const t = r.next();
+			if (t !== "0" && t !== "1") throw new Error(`Expected a bool, got '${t}'`);
+			return t === "1";
+		}
+		case "u8":
+		case "u16":
+		case "u32":
+		case "u64":
+		case "i8":
+		case "i16":
+		case "i32":
+		case "i64":
+			return r.int();
+		case "f32":
+		case "f64":
+			return r.float();
+		case "char":
+			return r.next();
+		case "string":
+			return decStr(r);
+		case "socket_addr":
+			return decAddr(r);
+		case "command":
+			return decCommand(r);
+		case "event":
+			return decEvent(r);
+		case "dynamic":
+			// the tokens are all we know
+			return r.rest();
+	}
+
+	if ("option" in ty) {
+		const t = r.next();
+		if (t === "0") return null;
+		if (t === "1") return dec(ty.option, r);
+		throw new Error(`Expected an option, got '${t}'`);
+	}
+	if ("seq" in ty) {
+		const len = r.int();
+		const v = [];
+		for (let i = 0; i < len; i++) v.push(dec(ty.seq, r));
+		return v;
+	}
+	if ("map" in ty) {
+		const [k, t] = ty.map;
+		const len = r.int();
+		const v = {};
+		for (let i = 0; i < len; i++) {
+			const key = dec(k, r);
+			v[key] = dec(t, r);
+		}
+		return v;
+	}
+	if ("tuple" in ty) return ty.tuple.map((t) => dec(t, r));
+	if ("named" in ty) return decDef(TYPES.get(ty.named), r);
+}
+
+// a decoder for what `c` returns, batches and acks depend on the commands in them
+function returns(c) {
+	switch (c.cmd) {
+		case "ack":
+			return returns(c.args);
+		case "batch": {
+			const decs = c.args.map(returns);
+			return (r) => (decs.length ? decs.map((d) => d(r)) : null);
+		}
+		default: {
+			const ty = COMMANDS.get(c.cmd).returns;
+			return (r) => dec(ty, r);
+		}
+	}
+}
+
+/** Whether the server replies to `c` when it succeeds */
+export function replies(c) {
+	switch (c.cmd) {
+		case "ack":
+			return true;
+		case "batch":
+			return true;
+		default:
+			return COMMANDS.get(c.cmd).replies;
+	}
+}
+
+function decReply(line, decode) {
+	const r = new Reader(line);
+	const id = r.int();
+	let reply;
+	switch (r.next()) {
+		case "0":
+			reply = { id, ok: decode(r) };
+			break;
+		case "1":
+			reply = { id, err: dec({ named: "Error" }, r) };
+			break;
+		default:
+			throw new Error("Invalid reply");
+	}
+	r.end();
+	return reply;
+}
+
+/** `id` is left out for http */
+export function encodeText(c, id) {
+	const out = id === undefined ? [] : [String(id)];
+	encCommand(c, out);
+	return out.join(" ");
+}
+
+/** Decodes a text command, with its id if `withId` is set */
+export function decodeText(text, withId = false) {
+	const r = new Reader(text);
+	const id = withId ? r.int() : undefined;
+	const c = decCommand(r);
+	r.end();
+	return withId ? { id, ...c } : c;
+}
+
+/** Decodes the body of an http reply to `c` */
+export function decodeReturnText(c, body) {
+	const r = new Reader(body);
+	const v = returns(c)(r);
+	r.end();
+	return v;
+}
+
+/** Decodes a websocket reply to `c` */
+export function decodeReplyText(c, line) {
+	return decReply(line, returns(c));
+}
+
+/** Decodes an item of an event `e` was subscribed to */
+export function decodeEventText(e, line) {
+	const ty = EVENTS.get(e.event).item;
+	return decReply(line, (r) => dec(ty, r));
+}
+
+export function encodeJson(c, id = 0) {
+	return JSON.stringify({ id, cmd: c.cmd, args: c.args });
+}
+
+/** Replies, events and http bodies are plain json */
+export function decodeJson(text) {
+	return JSON.parse(text);
+}
+
+export function replyId(line) {
+	return line.startsWith("{") ? JSON.parse(line).id : Number(line.split(" ", 1)[0]);
+}
+
+export function subscribe(e) {
+	return { cmd: "subscribe", args: e };
+}
+
+export function unsubscribe(e) {
+	return { cmd: "unsubscribe", args: e };
+}
+
+export function ack(c) {
+	return { cmd: "ack", args: c };
+}
+
+export function batch(...cmds) {
+	return { cmd: "batch", args: cmds };
+}
+
+/** A client for the server's `/ws` endpoint, in json or the text format */
+export class RoblibWs {
+	#ws;
+	#json;
+	#open;
+	#nextId = 1;
+	#pending = new Map();
+	#events = new Map();
+	#subs = new Map();
+
+	constructor(url, { format = "json", WebSocket = globalThis.WebSocket } = {}) {
+		this.#json = format === "json";
+		this.#ws = new WebSocket(url, this.#json ? [JSON_PROTOCOL] : []);
+		this.#open = new Promise((resolve, reject) => {
+			this.#ws.onopen = () => resolve();
+			this.#ws.onerror = (e) => reject(e);
+		});
+		this.#ws.onmessage = (m) => typeof m.data === "string" && this.#receive(m.data);
+		this.#ws.onclose = () => {
+			for (const { reject } of this.#pending.values()) reject(new Error("Disconnected from the server"));
+			this.#pending.clear();
+		};
+	}
+
+	#receive(line) {
+		const id = replyId(line);
+
+		const sub = this.#events.get(id);
+		if (sub) {
+			const reply = this.#json ? decodeJson(line) : decodeEventText(sub.event, line);
+			if ("ok" in reply) sub.handler(reply.ok);
+			else console.error(`roblib: subscription ${id} failed:`, reply.err);
+			return;
+		}
+
+		const pending = this.#pending.get(id);
+		this.#pending.delete(id);
+		if (!pending) {
+			console.error(`roblib: unexpected reply ${line}`);
+			return;
+		}
+
+		try {
+			const reply = this.#json ? decodeJson(line) : decodeReplyText(pending.cmd, line);
+			if ("ok" in reply) pending.resolve(reply.ok);
+			else pending.reject(new Error(`${reply.err[0]} error: ${reply.err[1]}`));
+		} catch (e) {
+			pending.reject(e);
+		}
+	}
+
+	async #send(c, id) {
+		await this.#open;
+		this.#ws.send(this.#json ? encodeJson(c, id) : encodeText(c, id));
+	}
+
+	/** Resolves with the return value, or `null` right away if the command doesn't reply */
+	async cmd(c) {
+		const id = this.#nextId++;
+		if (!replies(c)) {
+			await this.#send(c, id);
+			return null;
+		}
+
+		const reply = new Promise((resolve, reject) => this.#pending.set(id, { cmd: c, resolve, reject }));
+		await this.#send(c, id);
+		return reply;
+	}
+
+	async subscribe(e, handler) {
+		const id = this.#nextId++;
+		const key = JSON.stringify(e);
+		this.#events.set(id, { event: e, handler });
+		this.#subs.set(key, id);
+		await this.#send(subscribe(e), id);
+	}
+
+	async unsubscribe(e) {
+		const key = JSON.stringify(e);
+		const id = this.#subs.get(key);
+		if (id === undefined) throw new Error("Subscription not found");
+		this.#subs.delete(key);
+		await this.#send(unsubscribe(e), id);
+		this.#events.delete(id);
+	}
+
+	close() {
+		this.#ws.close();
+	}
+}